@@ -6,6 +6,7 @@ pub use datachannel::{
 pub struct PeerConnection {
     peer_conn: Box<datachannel::RtcPeerConnection<PeerConnectionHandler>>,
     data_channel_rx: tokio::sync::mpsc::Receiver<DataChannel>,
+    pending_remote_candidates: Vec<IceCandidate>,
 }
 
 impl PeerConnection {
@@ -22,6 +23,7 @@ impl PeerConnection {
             PeerConnection {
                 peer_conn,
                 data_channel_rx,
+                pending_remote_candidates: vec![],
             },
             event_rx,
         ))
@@ -68,6 +70,9 @@ impl PeerConnection {
         self.peer_conn
             .set_remote_description(&sess_desc)
             .map_err(datachannel_error_to_io_error)?;
+        for cand in std::mem::take(&mut self.pending_remote_candidates) {
+            self.add_remote_candidate(cand)?;
+        }
         Ok(())
     }
 
@@ -79,7 +84,12 @@ impl PeerConnection {
         self.peer_conn.remote_description()
     }
 
+    /// Candidates that are trickled in before the remote description is known are held until it is set.
     pub fn add_remote_candidate(&mut self, cand: IceCandidate) -> Result<(), std::io::Error> {
+        if self.peer_conn.remote_description().is_none() {
+            self.pending_remote_candidates.push(cand);
+            return Ok(());
+        }
         self.peer_conn
             .add_remote_candidate(&cand)
             .map_err(datachannel_error_to_io_error)?;
@@ -5,5 +5,6 @@
 #include <mgba/core/sync.h>
 #include <mgba/core/thread.h>
 #include <mgba/gba/core.h>
+#include <mgba/internal/arm/decoder.h>
 #include <mgba/internal/gba/gba.h>
 #include <mgba/internal/gba/serialize.h>
@@ -22,6 +22,49 @@ impl<'a> ARMCoreRef<'a> {
         self.gpr(15) as u32 - mgba_sys::WordSize_WORD_SIZE_ARM
     }
 
+    /// Disassembles the THUMB instruction at `address`. `next` is the halfword after it, for BL, which is split across two. Returns the text and the instruction's size in bytes.
+    pub fn disassemble_thumb(&self, address: u32, opcode: u16, next: Option<u16>) -> (String, u32) {
+        unsafe {
+            let mut info = std::mem::zeroed::<mgba_sys::ARMInstructionInfo>();
+            mgba_sys::ARMDecodeThumb(opcode, &mut info);
+            let mut size = mgba_sys::WordSize_WORD_SIZE_THUMB;
+            if let Some(next) = next {
+                let mut next_info = std::mem::zeroed::<mgba_sys::ARMInstructionInfo>();
+                mgba_sys::ARMDecodeThumb(next, &mut next_info);
+                let mut combined = std::mem::zeroed::<mgba_sys::ARMInstructionInfo>();
+                if mgba_sys::ARMDecodeThumbCombine(&mut info, &mut next_info, &mut combined) {
+                    info = combined;
+                    size *= 2;
+                }
+            }
+            (
+                self.disassemble(&info, address + mgba_sys::WordSize_WORD_SIZE_THUMB * 2),
+                size,
+            )
+        }
+    }
+
+    pub fn disassemble_arm(&self, address: u32, opcode: u32) -> String {
+        unsafe {
+            let mut info = std::mem::zeroed::<mgba_sys::ARMInstructionInfo>();
+            mgba_sys::ARMDecodeARM(opcode, &mut info);
+            self.disassemble(&info, address + mgba_sys::WordSize_WORD_SIZE_ARM * 2)
+        }
+    }
+
+    unsafe fn disassemble(&self, info: &mgba_sys::ARMInstructionInfo, pc: u32) -> String {
+        let mut buf = [0 as std::os::raw::c_char; 64];
+        mgba_sys::ARMDisassemble(
+            info,
+            self.ptr as *mut _,
+            std::ptr::null(),
+            pc,
+            buf.as_mut_ptr(),
+            buf.len() as i32,
+        );
+        std::ffi::CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned()
+    }
+
     pub fn execution_mode(&self) -> ExecutionMode {
         unsafe {
             match (*self.ptr).executionMode {
@@ -3,12 +3,14 @@ use super::gba;
 use super::state;
 use super::trapper;
 use super::vfile;
+use super::watcher;
 use std::ffi::CString;
 
 pub struct Core {
     pub(super) ptr: *mut mgba_sys::mCore,
     video_buffer: Option<Vec<u8>>,
     trapper: Option<trapper::Trapper>,
+    watcher: Option<watcher::Watcher>,
 }
 
 unsafe impl Send for Core {}
@@ -38,6 +40,7 @@ impl Core {
             ptr,
             video_buffer: None,
             trapper: None,
+            watcher: None,
         })
     }
 
@@ -74,10 +77,20 @@ impl Core {
     pub fn set_traps(&mut self, traps: Vec<(u32, Box<dyn Fn(CoreMutRef)>)>) {
         self.trapper = Some(trapper::Trapper::new(self.as_mut(), traps));
     }
+
+    /// Replaces any watchpoints that were set before. An empty list removes them all.
+    pub fn set_watchpoints(&mut self, watchpoints: Vec<watcher::Watchpoint>) {
+        // The old watcher has to put the original memory functions back before the new one can wrap them.
+        self.watcher = None;
+        if !watchpoints.is_empty() {
+            self.watcher = Some(watcher::Watcher::new(self.as_mut(), watchpoints));
+        }
+    }
 }
 
 impl Drop for Core {
     fn drop(&mut self) {
+        self.watcher = None;
         unsafe {
             mgba_sys::mCoreConfigDeinit(&mut self.ptr.as_mut().unwrap().config);
             (*self.ptr).deinit.unwrap()(self.ptr)
@@ -10,6 +10,7 @@ pub mod thread;
 pub mod timing;
 pub mod trapper;
 pub mod vfile;
+pub mod watcher;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
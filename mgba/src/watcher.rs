@@ -0,0 +1,342 @@
+use super::core;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Kind {
+    fn matches(&self, access: Access) -> bool {
+        match self {
+            Kind::Read => access == Access::Read,
+            Kind::Write => access == Access::Write,
+            Kind::ReadWrite => true,
+        }
+    }
+}
+
+/// A single access to a watched range. For reads, `old_value` and `new_value` are both the value read.
+#[derive(Clone, Copy, Debug)]
+pub struct Hit {
+    pub access: Access,
+    pub address: u32,
+    pub width: u32,
+    pub old_value: u32,
+    pub new_value: u32,
+    pub pc: u32,
+}
+
+pub struct Watchpoint {
+    pub range: std::ops::Range<u32>,
+    pub kind: Kind,
+    pub handler: Box<dyn Fn(core::CoreMutRef, Hit)>,
+}
+
+impl Watchpoint {
+    fn overlaps(&self, address: u32, width: u32) -> bool {
+        address < self.range.end && address.wrapping_add(width) > self.range.start
+    }
+}
+
+/// Watches memory accesses by swapping the CPU's memory functions out for ones that check each access against the watchpoints first, the same way mGBA's own debugger does.
+#[repr(transparent)]
+pub struct Watcher(Box<WatcherCStruct>);
+
+#[repr(C)]
+struct WatcherCStruct {
+    cpu_component: mgba_sys::mCPUComponent,
+    original: mgba_sys::ARMMemory,
+    r#impl: Impl,
+}
+
+struct Impl {
+    watchpoints: Vec<Watchpoint>,
+    core_ptr: *mut mgba_sys::mCore,
+    cpu_ptr: *mut mgba_sys::ARMCore,
+}
+
+unsafe impl Send for WatcherCStruct {}
+unsafe impl Send for Impl {}
+
+const COMPONENT: mgba_sys::mCPUComponentType = mgba_sys::mCPUComponentType_CPU_COMPONENT_MISC_2;
+
+// From enum LSMDirection.
+const LSM_B: u32 = 1;
+const LSM_D: u32 = 2;
+
+impl Impl {
+    fn is_watched(&self, access: Access, address: u32, width: u32) -> bool {
+        self.watchpoints
+            .iter()
+            .any(|w| w.kind.matches(access) && w.overlaps(address, width))
+    }
+
+    fn core(&self) -> core::CoreMutRef {
+        core::CoreMutRef {
+            ptr: self.core_ptr,
+            _lifetime: std::marker::PhantomData,
+        }
+    }
+
+    fn peek(&self, address: u32, width: u32) -> u32 {
+        let mut core = self.core();
+        match width {
+            1 => core.raw_read_8(address, -1) as u32,
+            2 => core.raw_read_16(address, -1) as u32,
+            _ => core.raw_read_32(address, -1),
+        }
+    }
+
+    fn pc(&self) -> u32 {
+        let cpu = super::arm_core::ARMCoreRef {
+            ptr: self.cpu_ptr,
+            _lifetime: std::marker::PhantomData,
+        };
+        // The PC is two instructions ahead of the one doing the access.
+        match cpu.execution_mode() {
+            super::arm_core::ExecutionMode::Thumb => {
+                (cpu.gpr(15) as u32).wrapping_sub(mgba_sys::WordSize_WORD_SIZE_THUMB * 2)
+            }
+            super::arm_core::ExecutionMode::ARM => {
+                (cpu.gpr(15) as u32).wrapping_sub(mgba_sys::WordSize_WORD_SIZE_ARM * 2)
+            }
+        }
+    }
+
+    fn hit(&self, access: Access, address: u32, width: u32, old_value: u32, new_value: u32) {
+        let hit = Hit {
+            access,
+            address,
+            width,
+            old_value,
+            new_value,
+            pc: self.pc(),
+        };
+        for watchpoint in self.watchpoints.iter() {
+            if watchpoint.kind.matches(access) && watchpoint.overlaps(address, width) {
+                (watchpoint.handler)(self.core(), hit);
+            }
+        }
+    }
+
+    fn load(&self, address: u32, width: u32, value: u32) {
+        if self.is_watched(Access::Read, address, width) {
+            self.hit(Access::Read, address, width, value, value);
+        }
+    }
+
+    fn store(&self, address: u32, width: u32, f: impl FnOnce()) {
+        if !self.is_watched(Access::Write, address, width) {
+            f();
+            return;
+        }
+        let old_value = self.peek(address, width);
+        f();
+        let new_value = self.peek(address, width);
+        self.hit(Access::Write, address, width, old_value, new_value);
+    }
+}
+
+/// The word addresses that LDM/STM touch, lowest first, mirroring GBALoadMultiple/GBAStoreMultiple.
+fn multiple_addresses(base: u32, mask: i32, direction: u32) -> impl Iterator<Item = u32> {
+    let count = (mask as u32 & 0xffff).count_ones();
+    let mut address = base;
+    if direction & LSM_D != 0 {
+        address = address.wrapping_sub(count * 4).wrapping_add(4);
+        if direction & LSM_B != 0 {
+            address = address.wrapping_sub(4);
+        }
+    } else if direction & LSM_B != 0 {
+        address = address.wrapping_add(4);
+    }
+    let address = address & !3;
+    (0..count).map(move |i| address.wrapping_add(i * 4))
+}
+
+unsafe fn watcher_for_cpu<'a>(cpu: *mut mgba_sys::ARMCore) -> &'a WatcherCStruct {
+    let components = std::slice::from_raw_parts(
+        (*cpu).components,
+        mgba_sys::mCPUComponentType_CPU_COMPONENT_MAX as usize,
+    );
+    &*(components[COMPONENT as usize] as *const WatcherCStruct)
+}
+
+unsafe extern "C" fn c_watcher_init(_cpu: *mut std::os::raw::c_void, _cpu_component: *mut mgba_sys::mCPUComponent) {}
+
+unsafe extern "C" fn c_watcher_deinit(_cpu_component: *mut mgba_sys::mCPUComponent) {}
+
+unsafe extern "C" fn c_watcher_load32(
+    cpu: *mut mgba_sys::ARMCore,
+    address: u32,
+    cycle_counter: *mut std::os::raw::c_int,
+) -> u32 {
+    let watcher = watcher_for_cpu(cpu);
+    let value = watcher.original.load32.unwrap()(cpu, address, cycle_counter);
+    watcher.r#impl.load(address, 4, value);
+    value
+}
+
+unsafe extern "C" fn c_watcher_load16(
+    cpu: *mut mgba_sys::ARMCore,
+    address: u32,
+    cycle_counter: *mut std::os::raw::c_int,
+) -> u32 {
+    let watcher = watcher_for_cpu(cpu);
+    let value = watcher.original.load16.unwrap()(cpu, address, cycle_counter);
+    watcher.r#impl.load(address, 2, value & 0xffff);
+    value
+}
+
+unsafe extern "C" fn c_watcher_load8(
+    cpu: *mut mgba_sys::ARMCore,
+    address: u32,
+    cycle_counter: *mut std::os::raw::c_int,
+) -> u32 {
+    let watcher = watcher_for_cpu(cpu);
+    let value = watcher.original.load8.unwrap()(cpu, address, cycle_counter);
+    watcher.r#impl.load(address, 1, value & 0xff);
+    value
+}
+
+unsafe extern "C" fn c_watcher_store32(
+    cpu: *mut mgba_sys::ARMCore,
+    address: u32,
+    value: i32,
+    cycle_counter: *mut std::os::raw::c_int,
+) {
+    let watcher = watcher_for_cpu(cpu);
+    watcher.r#impl.store(address, 4, || {
+        watcher.original.store32.unwrap()(cpu, address, value, cycle_counter)
+    });
+}
+
+unsafe extern "C" fn c_watcher_store16(
+    cpu: *mut mgba_sys::ARMCore,
+    address: u32,
+    value: i16,
+    cycle_counter: *mut std::os::raw::c_int,
+) {
+    let watcher = watcher_for_cpu(cpu);
+    watcher.r#impl.store(address, 2, || {
+        watcher.original.store16.unwrap()(cpu, address, value, cycle_counter)
+    });
+}
+
+unsafe extern "C" fn c_watcher_store8(
+    cpu: *mut mgba_sys::ARMCore,
+    address: u32,
+    value: i8,
+    cycle_counter: *mut std::os::raw::c_int,
+) {
+    let watcher = watcher_for_cpu(cpu);
+    watcher.r#impl.store(address, 1, || {
+        watcher.original.store8.unwrap()(cpu, address, value, cycle_counter)
+    });
+}
+
+unsafe extern "C" fn c_watcher_load_multiple(
+    cpu: *mut mgba_sys::ARMCore,
+    address: u32,
+    mask: i32,
+    direction: mgba_sys::LSMDirection,
+    cycle_counter: *mut std::os::raw::c_int,
+) -> u32 {
+    let watcher = watcher_for_cpu(cpu);
+    let r = watcher.original.loadMultiple.unwrap()(cpu, address, mask, direction, cycle_counter);
+    for address in multiple_addresses(address, mask, direction as u32) {
+        if watcher.r#impl.is_watched(Access::Read, address, 4) {
+            let value = watcher.r#impl.peek(address, 4);
+            watcher.r#impl.hit(Access::Read, address, 4, value, value);
+        }
+    }
+    r
+}
+
+unsafe extern "C" fn c_watcher_store_multiple(
+    cpu: *mut mgba_sys::ARMCore,
+    address: u32,
+    mask: i32,
+    direction: mgba_sys::LSMDirection,
+    cycle_counter: *mut std::os::raw::c_int,
+) -> u32 {
+    let watcher = watcher_for_cpu(cpu);
+    let watched = multiple_addresses(address, mask, direction as u32)
+        .filter(|address| watcher.r#impl.is_watched(Access::Write, *address, 4))
+        .map(|address| (address, watcher.r#impl.peek(address, 4)))
+        .collect::<Vec<_>>();
+    let r = watcher.original.storeMultiple.unwrap()(cpu, address, mask, direction, cycle_counter);
+    for (address, old_value) in watched {
+        let new_value = watcher.r#impl.peek(address, 4);
+        watcher.r#impl.hit(Access::Write, address, 4, old_value, new_value);
+    }
+    r
+}
+
+impl Watcher {
+    pub fn new(mut core: core::CoreMutRef, watchpoints: Vec<Watchpoint>) -> Self {
+        let mut cpu_component = unsafe { std::mem::zeroed::<mgba_sys::mCPUComponent>() };
+        cpu_component.init = Some(c_watcher_init);
+        cpu_component.deinit = Some(c_watcher_deinit);
+        let cpu_ptr = core.gba_mut().cpu_mut().ptr;
+        let mut watcher_c_struct = Box::new(WatcherCStruct {
+            cpu_component,
+            original: unsafe { (*cpu_ptr).memory },
+            r#impl: Impl {
+                watchpoints,
+                core_ptr: core.ptr,
+                cpu_ptr,
+            },
+        });
+
+        unsafe {
+            let arm_core = &mut *cpu_ptr;
+            let components = std::slice::from_raw_parts_mut(
+                arm_core.components,
+                mgba_sys::mCPUComponentType_CPU_COMPONENT_MAX as usize,
+            );
+            components[COMPONENT as usize] = &mut *watcher_c_struct as *mut _ as *mut mgba_sys::mCPUComponent;
+            mgba_sys::ARMHotplugAttach(arm_core, COMPONENT as _);
+            arm_core.memory.load32 = Some(c_watcher_load32);
+            arm_core.memory.load16 = Some(c_watcher_load16);
+            arm_core.memory.load8 = Some(c_watcher_load8);
+            arm_core.memory.store32 = Some(c_watcher_store32);
+            arm_core.memory.store16 = Some(c_watcher_store16);
+            arm_core.memory.store8 = Some(c_watcher_store8);
+            arm_core.memory.loadMultiple = Some(c_watcher_load_multiple);
+            arm_core.memory.storeMultiple = Some(c_watcher_store_multiple);
+        }
+
+        Watcher(watcher_c_struct)
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        unsafe {
+            let arm_core = &mut *self.0.r#impl.cpu_ptr;
+            let original = &self.0.original;
+            arm_core.memory.load32 = original.load32;
+            arm_core.memory.load16 = original.load16;
+            arm_core.memory.load8 = original.load8;
+            arm_core.memory.store32 = original.store32;
+            arm_core.memory.store16 = original.store16;
+            arm_core.memory.store8 = original.store8;
+            arm_core.memory.loadMultiple = original.loadMultiple;
+            arm_core.memory.storeMultiple = original.storeMultiple;
+            mgba_sys::ARMHotplugDetach(arm_core, COMPONENT as _);
+            let components = std::slice::from_raw_parts_mut(
+                arm_core.components,
+                mgba_sys::mCPUComponentType_CPU_COMPONENT_MAX as usize,
+            );
+            components[COMPONENT as usize] = std::ptr::null_mut();
+        }
+    }
+}
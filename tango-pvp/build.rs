@@ -13,6 +13,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         "tango.replay.protos.replay11.Metadata.GameInfo.Patch",
         "#[derive(serde::Serialize)]",
     );
+    prost_config.type_attribute(
+        "tango.replay.protos.replay11.Metadata.SetScore",
+        "#[derive(serde::Serialize)]",
+    );
     prost_config.compile_protos(&["src/replay/protos/replay11.proto"], &["src/"])?;
 
     Ok(())
@@ -15,27 +15,82 @@ pub struct CommittedState {
     pub packet: Vec<u8>,
 }
 
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct SetScore {
+    pub best_of: u8,
+    pub wins: u8,
+    pub losses: u8,
+}
+
+impl SetScore {
+    pub fn winner(&self) -> Option<BattleOutcome> {
+        if self.best_of == 0 {
+            return None;
+        }
+        let wins_needed = self.best_of / 2 + 1;
+        if self.wins >= wins_needed {
+            Some(BattleOutcome::Win)
+        } else if self.losses >= wins_needed {
+            Some(BattleOutcome::Loss)
+        } else {
+            None
+        }
+    }
+}
+
 pub struct RoundState {
     pub number: u8,
     pub round: Option<Round>,
     pub last_outcome: Option<BattleOutcome>,
+    /// The outcome reported during the current round. Unlike last_outcome, this never holds the coin flip or an earlier round's result.
+    round_outcome: Option<BattleOutcome>,
+    pub score: SetScore,
+    completion_token: crate::hooks::CompletionToken,
 }
 
 impl RoundState {
     pub fn end_round(&mut self) -> anyhow::Result<()> {
         match self.round.take() {
-            Some(round) => {
+            Some(mut round) => {
                 log::info!("round ended at {:x}", round.current_tick);
+                if round.lockstep.is_some() {
+                    if let Err(e) = round.finish_lockstep_replay() {
+                        log::error!("failed to finish replay: {:?}", e);
+                    }
+                }
             }
             None => {
                 return Ok(());
             }
         }
+
+        match self.round_outcome.take() {
+            Some(BattleOutcome::Win) => {
+                self.score.wins += 1;
+            }
+            Some(BattleOutcome::Loss) => {
+                self.score.losses += 1;
+            }
+            None => {
+                log::error!("round ended without an outcome, not updating set score");
+            }
+        }
+        log::info!("set score is now {}-{}", self.score.wins, self.score.losses);
+
+        if let Some(winner) = self.score.winner() {
+            log::info!("set is over: {:?}", winner);
+            self.completion_token.complete();
+        }
+
         Ok(())
     }
 
     pub fn set_last_outcome(&mut self, last_outcome: BattleOutcome) {
         self.last_outcome = Some(last_outcome);
+        self.round_outcome = Some(last_outcome);
+        if let Some(round) = self.round.as_mut() {
+            round.mark_lockstep_outcome();
+        }
     }
 }
 
@@ -48,6 +103,8 @@ pub struct Match {
     cancellation_token: tokio_util::sync::CancellationToken,
     match_type: (u8, u8),
     input_delay: u32,
+    lockstep: bool,
+    lockstep_sender: parking_lot::Mutex<Option<crate::lockstep::Sender>>,
     is_offerer: bool,
     round_state: tokio::sync::Mutex<RoundState>,
     primary_thread_handle: mgba::thread::Handle,
@@ -57,11 +114,13 @@ pub struct Match {
         dyn Fn(
                 /* round_number */ u8,
                 /* local_player_index */ u8,
+                /* set_score */ SetScore,
             ) -> std::io::Result<Option<crate::replay::Writer>>
             + Send
             + Sync,
     >,
     on_replay_complete: std::sync::Arc<dyn Fn(&mut dyn std::io::Read) -> anyhow::Result<()> + Send + Sync>,
+    telemetry: crate::telemetry::Recorder,
 }
 
 impl Match {
@@ -77,16 +136,25 @@ impl Match {
         remote_rom: &[u8],
         remote_save: &(dyn tango_dataview::save::Save + Send + Sync),
         match_type: (u8, u8),
+        best_of: u8,
+        lockstep: bool,
         input_delay: u32,
+        completion_token: crate::hooks::CompletionToken,
         replay_writer_factory: impl Fn(
                 /* round_number */ u8,
                 /* local_player_index */ u8,
+                /* set_score */ SetScore,
             ) -> std::io::Result<Option<crate::replay::Writer>>
             + Send
             + Sync
             + 'static,
         on_replay_complete: impl Fn(&mut dyn std::io::Read) -> anyhow::Result<()> + Send + Sync + 'static,
+        telemetry: crate::telemetry::Recorder,
     ) -> anyhow::Result<std::sync::Arc<Self>> {
+        if lockstep && !(local_hooks.supports_lockstep() && remote_hooks.supports_lockstep()) {
+            anyhow::bail!("lockstep isn't supported for these games");
+        }
+
         let (round_started_tx, round_started_rx) = tokio::sync::mpsc::channel(1);
         let did_polite_win_last_round = rng.gen::<bool>();
         let last_outcome = if did_polite_win_last_round == is_offerer {
@@ -111,10 +179,18 @@ impl Match {
             cancellation_token,
             match_type,
             input_delay,
+            lockstep,
+            lockstep_sender: parking_lot::Mutex::new(None),
             round_state: tokio::sync::Mutex::new(RoundState {
                 number: 0,
                 round: None,
                 last_outcome: Some(last_outcome),
+                round_outcome: None,
+                score: SetScore {
+                    best_of,
+                    ..Default::default()
+                },
+                completion_token,
             }),
             is_offerer,
             primary_thread_handle,
@@ -122,6 +198,7 @@ impl Match {
             round_started_rx: tokio::sync::Mutex::new(round_started_rx),
             replay_writer_factory: Box::new(replay_writer_factory),
             on_replay_complete: std::sync::Arc::new(on_replay_complete),
+            telemetry,
         });
         Ok(match_)
     }
@@ -142,7 +219,18 @@ impl Match {
         self.shadow.lock().advance_until_first_committed_state()
     }
 
-    pub async fn run(&self, mut receiver: Box<dyn crate::net::Receiver + Send + Sync>) -> anyhow::Result<()> {
+    pub async fn run(&self, receiver: Box<dyn crate::net::Receiver + Send + Sync>) -> anyhow::Result<()> {
+        let r = self.receive_remote_inputs(receiver).await;
+
+        // Make sure a round waiting on lockstep input doesn't wait forever.
+        *self.lockstep_sender.lock() = None;
+        r
+    }
+
+    async fn receive_remote_inputs(
+        &self,
+        mut receiver: Box<dyn crate::net::Receiver + Send + Sync>,
+    ) -> anyhow::Result<()> {
         let mut last_round_number = 0;
         loop {
             let input = receiver.receive().await?;
@@ -158,6 +246,21 @@ impl Match {
                 last_round_number = input.round_number;
             }
 
+            // In lockstep, the round waits for its remote inputs itself, so they are handed straight to it instead.
+            if self.lockstep {
+                let delivered = self
+                    .lockstep_sender
+                    .lock()
+                    .as_ref()
+                    .map(|sender| sender.send(input.clone()))
+                    .unwrap_or(false);
+                if !delivered {
+                    log::info!("no round in progress, dropping input");
+                    self.record_dropped_input(&input, "no round in progress");
+                }
+                continue;
+            }
+
             // We need to wait for the first state to be committed before we can add remote input.
             //
             // This is because we don't know what tick to add the input at, and the input queue has not been filled up with delay frames yet.
@@ -166,12 +269,14 @@ impl Match {
 
                 if input.round_number != round_state.number {
                     log::error!("round number mismatch, dropping input: this is probably bad!");
+                    self.record_dropped_input(&input, "round number mismatch");
                     continue;
                 }
 
                 let round = match &mut round_state.round {
                     None => {
                         log::info!("no round in progress, dropping input");
+                        self.record_dropped_input(&input, "no round in progress");
                         continue;
                     }
                     Some(b) => b,
@@ -185,12 +290,14 @@ impl Match {
             let mut round_state = self.round_state.lock().await;
             if input.round_number != round_state.number {
                 log::error!("round number mismatch, dropping input: this is probably bad!");
+                self.record_dropped_input(&input, "round number mismatch");
                 continue;
             }
 
             let round = match &mut round_state.round {
                 None => {
                     log::info!("no round in progress, dropping input");
+                    self.record_dropped_input(&input, "no round in progress");
                     continue;
                 }
                 Some(b) => b,
@@ -200,6 +307,14 @@ impl Match {
                 anyhow::bail!("remote overflowed our input buffer");
             }
 
+            if input.local_tick < round.current_tick {
+                self.telemetry.record(crate::telemetry::Event::LateInput {
+                    round_number: input.round_number,
+                    tick: input.local_tick,
+                    ticks_late: round.current_tick - input.local_tick,
+                });
+            }
+
             let now = std::time::Instant::now();
             round.add_remote_input(crate::input::PartialInput {
                 local_tick: input.local_tick,
@@ -213,6 +328,18 @@ impl Match {
         Ok(())
     }
 
+    fn record_dropped_input(&self, input: &crate::net::Input, reason: &str) {
+        self.telemetry.record(crate::telemetry::Event::DroppedInput {
+            round_number: input.round_number,
+            tick: input.local_tick,
+            reason: reason.to_string(),
+        });
+    }
+
+    pub fn telemetry(&self) -> &crate::telemetry::Recorder {
+        &self.telemetry
+    }
+
     pub fn lock_round_state(&self) -> tokio::sync::MutexGuard<'_, RoundState> {
         self.round_state.blocking_lock()
     }
@@ -229,16 +356,64 @@ impl Match {
         self.is_offerer
     }
 
+    pub fn lockstep(&self) -> bool {
+        self.lockstep
+    }
+
+    /// Adds the local input for the current tick and steps the round.
+    ///
+    /// With rollback, this fastforwards from the last committed state and loads the result into the core. In lockstep, this waits for the remote input with the round state unlocked and returns the local joyflags to step the tick with: the rest of the input pair is applied when the game copies input data.
+    pub async fn add_local_input_and_fastforward(
+        &self,
+        mut round_state: tokio::sync::MutexGuard<'_, RoundState>,
+        core: mgba::core::CoreMutRef<'_>,
+        joyflags: u16,
+    ) -> anyhow::Result<Option<u16>> {
+        let round = if let Some(round) = round_state.round.as_mut() {
+            round
+        } else {
+            return Ok(None);
+        };
+        let frame_time = round.send_local_input(joyflags).await?;
+
+        if round.lockstep.is_none() {
+            round.fastforward(core, frame_time)?;
+            return Ok(None);
+        }
+
+        let current_tick = round.current_tick;
+        let remote_inputs_needed = round.remote_inputs_needed();
+        let telemetry = round.telemetry.clone();
+        let lockstep = round.lockstep.as_mut().unwrap();
+        if lockstep.pending_pair.is_some() {
+            anyhow::bail!("input for tick {} was never applied", current_tick);
+        }
+        let mut receiver = lockstep.receiver.take().expect("lockstep receiver");
+        drop(round_state);
+
+        let remote_inputs = receiver.recv(remote_inputs_needed, &telemetry).await;
+
+        let mut round_state = self.round_state.lock().await;
+        let round = if let Some(round) = round_state.round.as_mut() {
+            round
+        } else {
+            anyhow::bail!("round ended while waiting for remote input");
+        };
+        round.lockstep.as_mut().expect("lockstep").receiver = Some(receiver);
+        Ok(Some(round.commit_lockstep_input(remote_inputs?, frame_time)?))
+    }
+
     pub async fn start_round(self: &std::sync::Arc<Self>) -> anyhow::Result<()> {
         let mut round_state = self.round_state.lock().await;
         round_state.number += 1;
+        round_state.round_outcome = None;
         let local_player_index = match round_state.last_outcome.take().unwrap() {
             BattleOutcome::Win => 0,
             BattleOutcome::Loss => 1,
         };
         log::info!("starting round: local_player_index = {}", local_player_index);
 
-        let replay_writer = (self.replay_writer_factory)(round_state.number, local_player_index)?;
+        let replay_writer = (self.replay_writer_factory)(round_state.number, local_player_index, round_state.score)?;
 
         log::info!("preparing round state");
 
@@ -268,6 +443,31 @@ impl Match {
             }
         }
 
+        let (lockstep, stepper) = if self.lockstep {
+            let (sender, receiver) = crate::lockstep::channel(round_state.number, self.cancellation_token.clone());
+            *self.lockstep_sender.lock() = Some(sender);
+            (
+                Some(LockstepState {
+                    receiver: Some(receiver),
+                    pending_pair: None,
+                    local_packet: vec![],
+                    unwritten_pair: None,
+                    outcome_tick: None,
+                }),
+                None,
+            )
+        } else {
+            (
+                None,
+                Some(crate::stepper::Fastforwarder::new(
+                    &self.rom,
+                    self.local_hooks,
+                    self.match_type,
+                    local_player_index,
+                )?),
+            )
+        };
+
         let now = std::time::Instant::now();
         round_state.round = Some(Round {
             hooks: self.local_hooks,
@@ -286,17 +486,14 @@ impl Match {
             first_state_committed_local_packet: Some(first_state_committed_local_packet),
             first_state_committed_rx: Some(first_state_committed_rx),
             committed_state: None,
-            stepper: crate::stepper::Fastforwarder::new(
-                &self.rom,
-                self.local_hooks,
-                self.match_type,
-                local_player_index,
-            )?,
+            stepper,
             replay_writer,
             primary_thread_handle: self.primary_thread_handle.clone(),
             sender: self.sender.clone(),
             shadow: self.shadow.clone(),
             on_replay_complete: self.on_replay_complete.clone(),
+            telemetry: self.telemetry.clone(),
+            lockstep,
             last_local_input_time: now,
             last_remote_input_time: now,
         });
@@ -306,6 +503,17 @@ impl Match {
     }
 }
 
+struct LockstepState {
+    /// Taken out of the round while it waits for remote input, so the round state doesn't need to stay locked.
+    receiver: Option<crate::lockstep::Receiver>,
+    /// The input pair committed for the current tick, until the game copies input data.
+    pending_pair: Option<crate::input::Pair<crate::input::PartialInput, crate::input::PartialInput>>,
+    local_packet: Vec<u8>,
+    /// Held back until it's known whether the round's outcome came before it, as input from then on is left out of the replay.
+    unwritten_pair: Option<crate::input::Pair<crate::input::Input, crate::input::Input>>,
+    outcome_tick: Option<u32>,
+}
+
 pub struct Round {
     hooks: &'static (dyn crate::hooks::Hooks + Send + Sync),
     number: u8,
@@ -317,12 +525,15 @@ pub struct Round {
     first_state_committed_local_packet: Option<tokio::sync::oneshot::Sender<()>>,
     first_state_committed_rx: Option<tokio::sync::oneshot::Receiver<()>>,
     committed_state: Option<CommittedState>,
-    stepper: crate::stepper::Fastforwarder,
+    /// Not used in lockstep, where nothing is ever re-simulated.
+    stepper: Option<crate::stepper::Fastforwarder>,
     replay_writer: Option<crate::replay::Writer>,
     primary_thread_handle: mgba::thread::Handle,
     sender: std::sync::Arc<tokio::sync::Mutex<Box<dyn crate::net::Sender + Send + Sync>>>,
     shadow: std::sync::Arc<parking_lot::Mutex<crate::shadow::Shadow>>,
     on_replay_complete: std::sync::Arc<dyn Fn(&mut dyn std::io::Read) -> anyhow::Result<()> + Send + Sync>,
+    telemetry: crate::telemetry::Recorder,
+    lockstep: Option<LockstepState>,
     last_local_input_time: std::time::Instant,
     last_remote_input_time: std::time::Instant,
 }
@@ -340,6 +551,10 @@ impl Round {
         self.local_player_index
     }
 
+    pub fn remote_player_index(&self) -> u8 {
+        1 - self.local_player_index
+    }
+
     pub fn set_first_committed_state(
         &mut self,
         local_state: Box<mgba::state::State>,
@@ -351,6 +566,10 @@ impl Round {
             replay_writer.write_state(&remote_state).expect("write remote state");
         }
 
+        if let Some(lockstep) = self.lockstep.as_mut() {
+            lockstep.local_packet = first_packet.to_vec();
+        }
+
         self.committed_state = Some(CommittedState {
             state: local_state,
             tick: 0,
@@ -361,11 +580,8 @@ impl Round {
         }
     }
 
-    pub async fn add_local_input_and_fastforward(
-        &mut self,
-        mut core: mgba::core::CoreMutRef<'_>,
-        joyflags: u16,
-    ) -> anyhow::Result<Option<BattleOutcome>> {
+    /// Sends the local input for the tick `local_delay` ticks ahead and queues it, returning the time since the last one.
+    async fn send_local_input(&mut self, joyflags: u16) -> anyhow::Result<std::time::Duration> {
         let local_tick = self.current_tick + self.local_delay();
         let remote_tick = self.last_committed_remote_input.local_tick;
 
@@ -391,14 +607,22 @@ impl Round {
             .await?;
 
         let now = std::time::Instant::now();
+        let frame_time = now - self.last_local_input_time;
         self.add_local_input(crate::input::PartialInput {
             local_tick,
             remote_tick,
             joyflags,
-            dt: now - self.last_local_input_time,
+            dt: frame_time,
         });
         self.last_local_input_time = now;
+        Ok(frame_time)
+    }
 
+    fn fastforward(
+        &mut self,
+        mut core: mgba::core::CoreMutRef<'_>,
+        frame_time: std::time::Duration,
+    ) -> anyhow::Result<Option<BattleOutcome>> {
         let (committable, predict_required) = self.iq.consume_and_peek_local();
 
         let last_committed_state = self.committed_state.take().expect("committed state");
@@ -433,8 +657,9 @@ impl Round {
             }))
             .collect::<Vec<crate::input::Pair<crate::input::PartialInput, crate::input::PartialInput>>>();
         let last_local_input = input_pairs.last().unwrap().local.clone();
+        let rollback_depth = input_pairs.len() as u32 - 1;
 
-        let ff_result = self.stepper.fastforward(
+        let ff_result = self.stepper.as_mut().expect("stepper").fastforward(
             &last_committed_state.state,
             input_pairs,
             last_committed_state.tick,
@@ -489,6 +714,17 @@ impl Round {
 
         self.dtick = last_local_input.lag() - self.last_committed_remote_input.lag();
 
+        self.telemetry.record(crate::telemetry::Event::Frame {
+            round_number: self.number,
+            tick: self.current_tick,
+            rollback_depth,
+            local_queue_length: self.iq.local_queue_length() as u32,
+            remote_queue_length: self.iq.remote_queue_length() as u32,
+            local_delay: self.iq.local_delay(),
+            dtick: self.dtick,
+            frame_time_us: frame_time.as_micros() as u64,
+        });
+
         core.gba_mut()
             .sync_mut()
             .expect("set fps target")
@@ -508,25 +744,169 @@ impl Round {
             return Ok(None);
         }
 
+        log::info!(
+            "replay finished at {:x} (real tick {:x})",
+            round_result.tick,
+            self.current_tick
+        );
+        self.finish_replay()?;
+
+        Ok(Some(match round_result.outcome {
+            crate::stepper::BattleOutcome::Draw => self.on_draw_outcome(),
+            crate::stepper::BattleOutcome::Loss => BattleOutcome::Loss,
+            crate::stepper::BattleOutcome::Win => BattleOutcome::Win,
+        }))
+    }
+
+    fn finish_replay(&mut self) -> anyhow::Result<()> {
         if let Some(replay_writer) = self.replay_writer.take() {
             let mut r = replay_writer.finish()?;
-            log::info!(
-                "replay finished at {:x} (real tick {:x})",
-                round_result.tick,
-                self.current_tick
-            );
-
             r.seek(std::io::SeekFrom::Start(0))?;
             if let Err(e) = (self.on_replay_complete)(&mut r) {
                 log::error!("on_replay_complete failed: {}", e);
             }
         }
+        Ok(())
+    }
 
-        Ok(Some(match round_result.outcome {
-            crate::stepper::BattleOutcome::Draw => self.on_draw_outcome(),
-            crate::stepper::BattleOutcome::Loss => BattleOutcome::Loss,
-            crate::stepper::BattleOutcome::Win => BattleOutcome::Win,
-        }))
+    /// How many more remote inputs are needed before the current tick can be committed.
+    fn remote_inputs_needed(&self) -> usize {
+        std::cmp::max(
+            self.iq.local_queue_length() as isize
+                - self.iq.local_delay() as isize
+                - self.iq.remote_queue_length() as isize,
+            0,
+        ) as usize
+    }
+
+    fn commit_lockstep_input(
+        &mut self,
+        remote_inputs: Vec<crate::input::PartialInput>,
+        frame_time: std::time::Duration,
+    ) -> anyhow::Result<u16> {
+        for input in remote_inputs {
+            if !self.iq.can_add_remote_input() {
+                anyhow::bail!("remote overflowed our input buffer");
+            }
+            self.add_remote_input(input);
+        }
+
+        let (committable, _) = self.iq.consume_and_peek_local();
+        let ip = match <[_; 1]>::try_from(committable) {
+            Ok([ip]) => ip,
+            Err(committable) => {
+                anyhow::bail!(
+                    "expected to commit one input pair on tick {}, got {}",
+                    self.current_tick,
+                    committable.len()
+                );
+            }
+        };
+
+        if ip.local.local_tick != ip.remote.local_tick {
+            anyhow::bail!(
+                "local tick != remote tick (in battle tick = {}): {} != {}",
+                self.current_tick,
+                ip.local.local_tick,
+                ip.remote.local_tick
+            );
+        }
+
+        if ip.local.local_tick != self.current_tick {
+            anyhow::bail!(
+                "input tick != in battle tick: {} != {}",
+                ip.local.local_tick,
+                self.current_tick
+            );
+        }
+
+        let joyflags = ip.local.joyflags;
+        self.dtick = ip.local.lag() - ip.remote.lag();
+        self.lockstep.as_mut().expect("lockstep").pending_pair = Some(ip);
+
+        self.telemetry.record(crate::telemetry::Event::Frame {
+            round_number: self.number,
+            tick: self.current_tick,
+            rollback_depth: 0,
+            local_queue_length: self.iq.local_queue_length() as u32,
+            remote_queue_length: self.iq.remote_queue_length() as u32,
+            local_delay: self.iq.local_delay(),
+            dtick: self.dtick,
+            frame_time_us: frame_time.as_micros() as u64,
+        });
+
+        Ok(joyflags)
+    }
+
+    /// In lockstep, applies the input pair committed for the current tick and returns the packets the local and remote players should receive.
+    ///
+    /// Returns None if there is nothing to apply, i.e. when not in lockstep or when the pair has already been applied.
+    pub fn take_lockstep_rx_packets(&mut self) -> anyhow::Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let lockstep = if let Some(lockstep) = self.lockstep.as_mut() {
+            lockstep
+        } else {
+            return Ok(None);
+        };
+
+        let ip = if let Some(ip) = lockstep.pending_pair.take() {
+            ip
+        } else {
+            return Ok(None);
+        };
+
+        let local = ip.local.with_packet(lockstep.local_packet.clone());
+        let r = self.shadow.lock().apply_input(crate::input::Pair {
+            local: local.clone(),
+            remote: ip.remote.clone(),
+        })?;
+        if r.tick != local.local_tick {
+            anyhow::bail!(
+                "shadow input did not match current tick: {} != {}",
+                r.tick,
+                local.local_tick
+            );
+        }
+        let remote = ip.remote.with_packet(r.packet);
+        self.last_committed_remote_input = remote.clone();
+
+        let packets = (local.packet.clone(), remote.packet.clone());
+        if let Some(ip) = lockstep.unwritten_pair.replace(crate::input::Pair { local, remote }) {
+            write_lockstep_input(
+                &mut self.replay_writer,
+                self.local_player_index,
+                lockstep.outcome_tick,
+                &ip,
+            )?;
+        }
+        Ok(Some(packets))
+    }
+
+    /// In lockstep, records the packet the local player sends on the next tick.
+    pub fn set_lockstep_local_packet(&mut self, packet: &[u8]) {
+        if let Some(lockstep) = self.lockstep.as_mut() {
+            lockstep.local_packet = packet.to_vec();
+        }
+    }
+
+    fn mark_lockstep_outcome(&mut self) {
+        let current_tick = self.current_tick;
+        if let Some(lockstep) = self.lockstep.as_mut() {
+            lockstep.outcome_tick.get_or_insert(current_tick);
+        }
+    }
+
+    fn finish_lockstep_replay(&mut self) -> anyhow::Result<()> {
+        let lockstep = self.lockstep.as_mut().expect("lockstep");
+        if let Some(ip) = lockstep.unwritten_pair.take() {
+            write_lockstep_input(
+                &mut self.replay_writer,
+                self.local_player_index,
+                lockstep.outcome_tick,
+                &ip,
+            )?;
+        }
+        log::info!("replay finished at {:x}", self.current_tick);
+        self.finish_replay()
     }
 
     pub fn on_draw_outcome(&self) -> BattleOutcome {
@@ -571,6 +951,22 @@ impl Round {
     }
 }
 
+/// Like the rollback path, only writes input from before the round's outcome.
+fn write_lockstep_input(
+    replay_writer: &mut Option<crate::replay::Writer>,
+    local_player_index: u8,
+    outcome_tick: Option<u32>,
+    ip: &crate::input::Pair<crate::input::Input, crate::input::Input>,
+) -> anyhow::Result<()> {
+    if outcome_tick.map(|tick| ip.local.local_tick >= tick).unwrap_or(false) {
+        return Ok(());
+    }
+    if let Some(replay_writer) = replay_writer.as_mut() {
+        replay_writer.write_input(local_player_index, ip)?;
+    }
+    Ok(())
+}
+
 impl Drop for Round {
     fn drop(&mut self) {
         // HACK: This is the only safe way to set the FPS without clogging everything else up.
@@ -0,0 +1,207 @@
+use rand::Rng;
+
+pub trait Policy {
+    fn joyflags(&mut self, round_number: u8, tick: u32) -> u16;
+}
+
+pub struct IdlePolicy;
+
+impl Policy for IdlePolicy {
+    fn joyflags(&mut self, _round_number: u8, _tick: u32) -> u16 {
+        0
+    }
+}
+
+pub struct RandomPolicy {
+    rng: rand_pcg::Mcg128Xsl64,
+    current: u16,
+    hold_ticks_left: u32,
+}
+
+impl RandomPolicy {
+    pub fn new(rng: rand_pcg::Mcg128Xsl64) -> Self {
+        Self {
+            rng,
+            current: 0,
+            hold_ticks_left: 0,
+        }
+    }
+}
+
+impl Policy for RandomPolicy {
+    fn joyflags(&mut self, _round_number: u8, _tick: u32) -> u16 {
+        if self.hold_ticks_left == 0 {
+            // Mashing every frame looks nothing like a person playing, so hold each random input for a few frames.
+            self.current = self.rng.gen::<u16>() & 0b1111111111;
+            self.hold_ticks_left = self.rng.gen_range(4..30);
+        }
+        self.hold_ticks_left -= 1;
+        self.current
+    }
+}
+
+pub struct ReplayPolicy {
+    joyflags: Vec<u16>,
+}
+
+impl ReplayPolicy {
+    /// Plays back the local side of the given replay.
+    pub fn new(replay: &crate::replay::Replay) -> Self {
+        Self {
+            joyflags: replay.input_pairs.iter().map(|ip| ip.local.joyflags).collect(),
+        }
+    }
+}
+
+impl Policy for ReplayPolicy {
+    fn joyflags(&mut self, _round_number: u8, tick: u32) -> u16 {
+        self.joyflags.get(tick as usize).copied().unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ScriptStep {
+    joyflags: u16,
+    ticks: u32,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScriptParseError {
+    #[error("line {0}: expected <keys> <ticks>")]
+    Malformed(usize),
+
+    #[error("line {0}: unknown key {1:?}")]
+    UnknownKey(usize, String),
+
+    #[error("line {0}: invalid tick count {1:?}")]
+    InvalidTicks(usize, String),
+
+    #[error("script is empty")]
+    Empty,
+}
+
+/// Loops over a list of steps, one per line, of the form `<keys> <ticks>`.
+///
+/// Keys are joined with `+` (e.g. `A+LEFT 4`) and `-` means no keys are held. Blank lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone)]
+pub struct ScriptPolicy {
+    steps: Vec<ScriptStep>,
+    total_ticks: u32,
+}
+
+impl ScriptPolicy {
+    pub fn parse(script: &str) -> Result<Self, ScriptParseError> {
+        let mut steps = vec![];
+        for (i, line) in script.lines().enumerate() {
+            let lineno = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (keys, ticks) = if let Some((keys, ticks)) = line.split_once(char::is_whitespace) {
+                (keys, ticks.trim())
+            } else {
+                return Err(ScriptParseError::Malformed(lineno));
+            };
+
+            let mut joyflags = 0u16;
+            if keys != "-" {
+                for key in keys.split('+') {
+                    joyflags |= match key.to_ascii_uppercase().as_str() {
+                        "A" => mgba::input::keys::A,
+                        "B" => mgba::input::keys::B,
+                        "SELECT" => mgba::input::keys::SELECT,
+                        "START" => mgba::input::keys::START,
+                        "RIGHT" => mgba::input::keys::RIGHT,
+                        "LEFT" => mgba::input::keys::LEFT,
+                        "UP" => mgba::input::keys::UP,
+                        "DOWN" => mgba::input::keys::DOWN,
+                        "R" => mgba::input::keys::R,
+                        "L" => mgba::input::keys::L,
+                        _ => {
+                            return Err(ScriptParseError::UnknownKey(lineno, key.to_string()));
+                        }
+                    } as u16;
+                }
+            }
+
+            let ticks = match ticks.parse::<u32>() {
+                Ok(ticks) if ticks > 0 => ticks,
+                _ => {
+                    return Err(ScriptParseError::InvalidTicks(lineno, ticks.to_string()));
+                }
+            };
+
+            steps.push(ScriptStep { joyflags, ticks });
+        }
+
+        if steps.is_empty() {
+            return Err(ScriptParseError::Empty);
+        }
+
+        let total_ticks = steps.iter().map(|step| step.ticks).sum();
+        Ok(Self { steps, total_ticks })
+    }
+}
+
+impl Policy for ScriptPolicy {
+    fn joyflags(&mut self, _round_number: u8, tick: u32) -> u16 {
+        let mut offset = tick % self.total_ticks;
+        for step in &self.steps {
+            if offset < step.ticks {
+                return step.joyflags;
+            }
+            offset -= step.ticks;
+        }
+        unreachable!()
+    }
+}
+
+/// Creates a connected sender and receiver pair that stands in for a remote peer.
+///
+/// Every input sent by the local side is answered with an input for the same tick, produced by the given policy. The bot is therefore never behind or ahead of the local side.
+pub fn new(policy: Box<dyn Policy + Send + Sync>) -> (Sender, Receiver) {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    (Sender { tx }, Receiver { rx, policy })
+}
+
+pub struct Sender {
+    tx: tokio::sync::mpsc::UnboundedSender<crate::net::Input>,
+}
+
+#[async_trait::async_trait]
+impl crate::net::Sender for Sender {
+    async fn send(&mut self, input: &crate::net::Input) -> std::io::Result<()> {
+        self.tx
+            .send(input.clone())
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "bot has gone away"))
+    }
+}
+
+pub struct Receiver {
+    rx: tokio::sync::mpsc::UnboundedReceiver<crate::net::Input>,
+    policy: Box<dyn Policy + Send + Sync>,
+}
+
+#[async_trait::async_trait]
+impl crate::net::Receiver for Receiver {
+    async fn receive(&mut self) -> std::io::Result<crate::net::Input> {
+        let input = if let Some(input) = self.rx.recv().await {
+            input
+        } else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "local side has gone away",
+            ));
+        };
+
+        Ok(crate::net::Input {
+            round_number: input.round_number,
+            local_tick: input.local_tick,
+            // The bot plays each tick as soon as the local side sends it, so from the bot's own point of view the local side is never ahead or behind. Copying the local side's tick_diff would instead echo any local stall back as remote delay.
+            tick_diff: 0,
+            joyflags: self.policy.joyflags(input.round_number, input.local_tick),
+        })
+    }
+}
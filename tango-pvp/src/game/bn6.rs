@@ -294,10 +294,10 @@ impl crate::hooks::Hooks for Hooks {
             (self.offsets.rom.main_read_joyflags, {
                 let match_ = match_.clone();
                 let munger = self.munger();
-                Box::new(move |core| {
-                    let match_ = match_.blocking_lock();
-                    let match_ = match &*match_ {
-                        Some(match_) => match_,
+                Box::new(move |mut core| {
+                    // Keep our own reference to the match: in lockstep, nothing may stay locked while waiting for remote input.
+                    let match_ = match &*match_.blocking_lock() {
+                        Some(match_) => match_.clone(),
                         _ => {
                             return;
                         }
@@ -360,15 +360,97 @@ impl crate::hooks::Hooks for Hooks {
                         );
                     }
 
-                    if let Err(e) = crate::sync::block_on(round.add_local_input_and_fastforward(
+                    match crate::sync::block_on(match_.add_local_input_and_fastforward(
+                        round_state,
                         core,
                         joyflags.load(std::sync::atomic::Ordering::Relaxed) as u16,
                     )) {
-                        log::error!("failed to add local input: {}", e);
-                        match_.cancel();
+                        Ok(Some(joyflags)) => {
+                            core.gba_mut().cpu_mut().set_gpr(4, (joyflags | 0xfc00) as i32);
+                        }
+                        Ok(None) => {}
+                        Err(e) => {
+                            log::error!("failed to add local input: {}", e);
+                            match_.cancel();
+                        }
+                    }
+                })
+            }),
+            (self.offsets.rom.copy_input_data_entry, {
+                let match_ = match_.clone();
+                let munger = self.munger();
+                Box::new(move |core| {
+                    let match_ = match_.blocking_lock();
+                    let match_ = match &*match_ {
+                        Some(match_) => match_,
+                        _ => {
+                            return;
+                        }
+                    };
+
+                    // Outside of lockstep, input data is copied in by the fastforwarder instead.
+                    if !match_.lockstep() {
+                        return;
+                    }
+
+                    let mut round_state = match_.lock_round_state();
+
+                    let round = match round_state.round.as_mut() {
+                        Some(round) => round,
+                        None => {
+                            return;
+                        }
+                    };
+
+                    match round.take_lockstep_rx_packets() {
+                        Ok(Some((local_packet, remote_packet))) => {
+                            munger.set_rx_packet(
+                                core,
+                                round.local_player_index() as u32,
+                                &local_packet.try_into().unwrap(),
+                            );
+                            munger.set_rx_packet(
+                                core,
+                                round.remote_player_index() as u32,
+                                &remote_packet.try_into().unwrap(),
+                            );
+                        }
+                        Ok(None) => {}
+                        Err(e) => {
+                            log::error!("failed to apply lockstep input: {}", e);
+                            match_.cancel();
+                        }
                     }
                 })
             }),
+            (self.offsets.rom.copy_input_data_ret, {
+                let match_ = match_.clone();
+                let munger = self.munger();
+                Box::new(move |core| {
+                    let match_ = match_.blocking_lock();
+                    let match_ = match &*match_ {
+                        Some(match_) => match_,
+                        _ => {
+                            return;
+                        }
+                    };
+
+                    if !match_.lockstep() {
+                        return;
+                    }
+
+                    let mut round_state = match_.lock_round_state();
+
+                    let round = match round_state.round.as_mut() {
+                        Some(round) => round,
+                        None => {
+                            return;
+                        }
+                    };
+
+                    round.set_lockstep_local_packet(&munger.tx_packet(core));
+                })
+            }),
             {
                 let match_ = match_.clone();
                 let munger = self.munger();
@@ -974,6 +1056,10 @@ impl crate::hooks::Hooks for Hooks {
         ]
     }
 
+    fn supports_lockstep(&self) -> bool {
+        true
+    }
+
     fn prepare_for_fastforward(&self, mut core: mgba::core::CoreMutRef) {
         core.gba_mut()
             .cpu_mut()
@@ -0,0 +1,320 @@
+const SAMPLE_RATE: f64 = 48000.0;
+
+const EWRAM: std::ops::Range<u32> = 0x02000000..0x02040000;
+const IWRAM: std::ops::Range<u32> = 0x03000000..0x03008000;
+
+/// What joypad buttons to hold for how many frames, one step after another.
+///
+/// The text form has one step per line: a frame count, then either `-` for no buttons, a number, or button names joined with `+`. Anything after `#` is a comment.
+///
+/// ```text
+/// 120 -        # wait for the title screen
+/// 1 START
+/// 30 A+RIGHT
+/// 10 0x0001
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Script {
+    pub steps: Vec<(u32, u32)>,
+}
+
+impl Script {
+    pub fn joyflags(&self) -> impl Iterator<Item = u32> + '_ {
+        self.steps
+            .iter()
+            .flat_map(|(frames, joyflags)| std::iter::repeat(*joyflags).take(*frames as usize))
+    }
+}
+
+fn parse_joyflags(s: &str) -> anyhow::Result<u32> {
+    if s == "-" {
+        return Ok(0);
+    }
+    if let Some(hex) = s.strip_prefix("0x") {
+        return Ok(u32::from_str_radix(hex, 16)?);
+    }
+    if let Ok(joyflags) = s.parse() {
+        return Ok(joyflags);
+    }
+    s.split('+').try_fold(0, |joyflags, name| {
+        Ok(joyflags
+            | match name.to_ascii_uppercase().as_str() {
+                "A" => mgba::input::keys::A,
+                "B" => mgba::input::keys::B,
+                "SELECT" => mgba::input::keys::SELECT,
+                "START" => mgba::input::keys::START,
+                "RIGHT" => mgba::input::keys::RIGHT,
+                "LEFT" => mgba::input::keys::LEFT,
+                "UP" => mgba::input::keys::UP,
+                "DOWN" => mgba::input::keys::DOWN,
+                "R" => mgba::input::keys::R,
+                "L" => mgba::input::keys::L,
+                _ => anyhow::bail!("unknown button: {}", name),
+            })
+    })
+}
+
+impl std::str::FromStr for Script {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut steps = vec![];
+        for (i, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (frames, joyflags) = if let Some(step) = line.split_once(char::is_whitespace) {
+                step
+            } else {
+                anyhow::bail!("line {}: expected a frame count and buttons", i + 1);
+            };
+            let frames = frames
+                .parse()
+                .map_err(|e| anyhow::anyhow!("line {}: bad frame count: {}", i + 1, e))?;
+            let joyflags = parse_joyflags(joyflags.trim()).map_err(|e| anyhow::anyhow!("line {}: {}", i + 1, e))?;
+            steps.push((frames, joyflags));
+        }
+        Ok(Self { steps })
+    }
+}
+
+/// 64-bit FNV-1a. Hashes need to stay the same across builds so they can be checked in and compared later, which rules out std's hasher.
+fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct FrameHash {
+    pub wram: u64,
+    pub vbuf: u64,
+    pub audio: u64,
+}
+
+pub struct Setup<'a> {
+    pub rom: &'a [u8],
+    pub save: Option<&'a [u8]>,
+    pub state: Option<&'a mgba::state::State>,
+    /// If set, the ROM is patched and the common traps installed, the same way as for a real session.
+    pub hooks: Option<&'a (dyn crate::hooks::Hooks + Send + Sync)>,
+}
+
+/// Runs a core without any video or audio output, hashing what it produces each frame.
+pub struct Runner {
+    core: mgba::core::Core,
+    audio_buf: Vec<i16>,
+}
+
+impl Runner {
+    pub fn new(setup: Setup) -> anyhow::Result<Self> {
+        let mut core = mgba::core::Core::new_gba("tango")?;
+        core.enable_video_buffer();
+
+        core.as_mut()
+            .load_rom(mgba::vfile::VFile::from_vec(setup.rom.to_vec()))?;
+        if let Some(save) = setup.save {
+            core.as_mut().load_save(mgba::vfile::VFile::from_vec(save.to_vec()))?;
+        }
+        core.as_mut().reset();
+
+        if let Some(hooks) = setup.hooks {
+            hooks.patch(core.as_mut());
+            core.set_traps(hooks.common_traps());
+        }
+
+        if let Some(state) = setup.state {
+            core.as_mut().load_state(state)?;
+        }
+
+        let clock_rate = core.as_ref().frequency();
+        for ch in 0..2 {
+            let mut channel = core.as_mut().audio_channel(ch);
+            channel.clear();
+            channel.set_rates(clock_rate as f64, SAMPLE_RATE);
+        }
+
+        Ok(Self {
+            core,
+            audio_buf: vec![],
+        })
+    }
+
+    pub fn core_mut(&mut self) -> mgba::core::CoreMutRef {
+        self.core.as_mut()
+    }
+
+    pub fn run_frame(&mut self, joyflags: u32) -> FrameHash {
+        let mut core = self.core.as_mut();
+        core.set_keys(joyflags);
+        core.run_frame();
+
+        let mut wram = vec![0u8; EWRAM.len() + IWRAM.len()];
+        let (ewram, iwram) = wram.split_at_mut(EWRAM.len());
+        core.raw_read_range(EWRAM.start, -1, ewram);
+        core.raw_read_range(IWRAM.start, -1, iwram);
+
+        // Drain everything this frame made, so the buffers never fill up and start dropping samples.
+        let available = core.audio_channel(0).samples_avail() as usize;
+        self.audio_buf.resize(available * 2, 0);
+        core.audio_channel(0)
+            .read_samples(&mut self.audio_buf, available as i32, true);
+        core.audio_channel(1)
+            .read_samples(&mut self.audio_buf[1..], available as i32, true);
+
+        FrameHash {
+            wram: fnv1a(wram),
+            vbuf: fnv1a(self.core.video_buffer().unwrap().iter().copied()),
+            audio: fnv1a(self.audio_buf.iter().flat_map(|sample| sample.to_le_bytes())),
+        }
+    }
+
+    pub fn run(&mut self, script: &Script) -> Vec<FrameHash> {
+        script.joyflags().map(|joyflags| self.run_frame(joyflags)).collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Divergence {
+    /// The first frame where any of the hashes differ.
+    Frame {
+        frame: usize,
+        wram: bool,
+        vbuf: bool,
+        audio: bool,
+    },
+    /// Every frame both runs have matched, but one run is longer.
+    Length { left: usize, right: usize },
+}
+
+impl std::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Divergence::Frame {
+                frame,
+                wram,
+                vbuf,
+                audio,
+            } => {
+                let parts = [(*wram, "wram"), (*vbuf, "vbuf"), (*audio, "audio")]
+                    .into_iter()
+                    .filter(|(differs, _)| *differs)
+                    .map(|(_, name)| name)
+                    .collect::<Vec<_>>();
+                write!(f, "frame {} diverged: {}", frame, parts.join(", "))
+            }
+            Divergence::Length { left, right } => write!(f, "runs have different lengths: {} != {}", left, right),
+        }
+    }
+}
+
+/// Returns where two runs first stop matching, if they ever do.
+pub fn diff(left: &[FrameHash], right: &[FrameHash]) -> Option<Divergence> {
+    for (frame, (l, r)) in left.iter().zip(right.iter()).enumerate() {
+        if l != r {
+            return Some(Divergence::Frame {
+                frame,
+                wram: l.wram != r.wram,
+                vbuf: l.vbuf != r.vbuf,
+                audio: l.audio != r.audio,
+            });
+        }
+    }
+    if left.len() != right.len() {
+        return Some(Divergence::Length {
+            left: left.len(),
+            right: right.len(),
+        });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(n: u64) -> FrameHash {
+        FrameHash {
+            wram: n,
+            vbuf: n,
+            audio: n,
+        }
+    }
+
+    #[test]
+    fn test_script_from_str() {
+        let script = "
+# intro
+120 -        # wait for the title screen
+1 START
+30 a+right
+10 0x0001
+2 3
+"
+        .parse::<Script>()
+        .unwrap();
+        assert_eq!(
+            script.steps,
+            vec![
+                (120, 0),
+                (1, mgba::input::keys::START),
+                (30, mgba::input::keys::A | mgba::input::keys::RIGHT),
+                (10, 0x0001),
+                (2, 3),
+            ]
+        );
+        assert_eq!(script.joyflags().count(), 163);
+    }
+
+    #[test]
+    fn test_script_from_str_errors() {
+        let err = "1 A\n\n2\n".parse::<Script>().unwrap_err();
+        assert!(err.to_string().starts_with("line 3:"), "{}", err);
+
+        let err = "1 A\nx B\n".parse::<Script>().unwrap_err();
+        assert!(err.to_string().starts_with("line 2: bad frame count"), "{}", err);
+
+        let err = "# comment\n1 A+TURBO\n".parse::<Script>().unwrap_err();
+        assert_eq!(err.to_string(), "line 2: unknown button: TURBO");
+
+        let err = "1 0xzz\n".parse::<Script>().unwrap_err();
+        assert!(err.to_string().starts_with("line 1:"), "{}", err);
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(diff(&[hash(1), hash(2)], &[hash(1), hash(2)]), None);
+        assert_eq!(diff(&[], &[]), None);
+
+        assert_eq!(
+            diff(
+                &[hash(1), hash(2), hash(3)],
+                &[hash(1), FrameHash { vbuf: 0, ..hash(2) }, hash(0)]
+            ),
+            Some(Divergence::Frame {
+                frame: 1,
+                wram: false,
+                vbuf: true,
+                audio: false,
+            })
+        );
+
+        // A frame that differs wins over a length mismatch after it.
+        assert_eq!(
+            diff(&[hash(1), hash(2)], &[hash(0)]),
+            Some(Divergence::Frame {
+                frame: 0,
+                wram: true,
+                vbuf: true,
+                audio: true,
+            })
+        );
+
+        assert_eq!(
+            diff(&[hash(1), hash(2)], &[hash(1)]),
+            Some(Divergence::Length { left: 2, right: 1 })
+        );
+        assert_eq!(diff(&[], &[hash(1)]), Some(Divergence::Length { left: 0, right: 1 }));
+    }
+}
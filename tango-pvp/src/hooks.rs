@@ -43,6 +43,11 @@ pub trait Hooks {
         0x10
     }
 
+    /// Whether the primary traps step lockstep rounds themselves, by setting the joyflags read for each tick and copying in both sides' input data. Lockstep is only offered when both sides' games do.
+    fn supports_lockstep(&self) -> bool {
+        false
+    }
+
     fn prepare_for_fastforward(&self, core: mgba::core::CoreMutRef);
 
     fn predict_rx(&self, _rx: &mut Vec<u8>) {}
@@ -1,10 +1,14 @@
 pub mod battle;
+pub mod bot;
 pub mod eval;
 pub mod game;
+pub mod headless;
 pub mod hooks;
 pub mod input;
+pub mod lockstep;
 pub mod net;
 pub mod replay;
 pub mod shadow;
 pub mod stepper;
 pub mod sync;
+pub mod telemetry;
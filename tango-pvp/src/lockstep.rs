@@ -0,0 +1,97 @@
+//! Delay-based lockstep.
+//!
+//! Instead of predicting remote input and rolling back when the prediction turns out to be wrong, the round waits for the remote input for a tick to arrive before stepping it. Every tick is then committed as soon as it's stepped, so the primary core applies the input pair directly and nothing is re-simulated: the cost is that any latency beyond the input delay stalls the game.
+
+pub fn channel(round_number: u8, cancellation_token: tokio_util::sync::CancellationToken) -> (Sender, Receiver) {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    (
+        Sender { tx },
+        Receiver {
+            round_number,
+            rx,
+            cancellation_token,
+            last_input_time: std::time::Instant::now(),
+        },
+    )
+}
+
+/// Hands remote inputs from the network task to the round, without needing to lock the round state.
+pub struct Sender {
+    tx: tokio::sync::mpsc::UnboundedSender<crate::net::Input>,
+}
+
+impl Sender {
+    /// Returns false if the round has already ended.
+    pub fn send(&self, input: crate::net::Input) -> bool {
+        self.tx.send(input).is_ok()
+    }
+}
+
+pub struct Receiver {
+    round_number: u8,
+    rx: tokio::sync::mpsc::UnboundedReceiver<crate::net::Input>,
+    cancellation_token: tokio_util::sync::CancellationToken,
+    last_input_time: std::time::Instant,
+}
+
+impl Receiver {
+    fn to_partial_input(
+        &mut self,
+        telemetry: &crate::telemetry::Recorder,
+        input: crate::net::Input,
+    ) -> Option<crate::input::PartialInput> {
+        if input.round_number != self.round_number {
+            log::info!(
+                "dropping input for round {} in round {}",
+                input.round_number,
+                self.round_number
+            );
+            telemetry.record(crate::telemetry::Event::DroppedInput {
+                round_number: input.round_number,
+                tick: input.local_tick,
+                reason: "round number mismatch".to_string(),
+            });
+            return None;
+        }
+
+        let now = std::time::Instant::now();
+        let input = crate::input::PartialInput {
+            local_tick: input.local_tick,
+            remote_tick: (input.local_tick as i64 + input.tick_diff as i64) as u32,
+            joyflags: input.joyflags,
+            dt: now - self.last_input_time,
+        };
+        log::debug!("remote input: {:?}", input);
+        self.last_input_time = now;
+        Some(input)
+    }
+
+    /// Waits for the next `n` remote inputs of the round.
+    ///
+    /// This doesn't touch the round, so it must be called without the round state locked: otherwise everything else that looks at the round stalls along with it.
+    pub async fn recv(
+        &mut self,
+        n: usize,
+        telemetry: &crate::telemetry::Recorder,
+    ) -> anyhow::Result<Vec<crate::input::PartialInput>> {
+        let mut inputs = Vec::with_capacity(n);
+        while inputs.len() < n {
+            let input = tokio::select! {
+                input = self.rx.recv() => {
+                    if let Some(input) = input {
+                        input
+                    } else {
+                        anyhow::bail!("remote input stream ended");
+                    }
+                }
+                _ = self.cancellation_token.cancelled() => {
+                    anyhow::bail!("match cancelled while waiting for remote input");
+                }
+            };
+            if let Some(input) = self.to_partial_input(telemetry, input) {
+                inputs.push(input);
+            }
+        }
+        Ok(inputs)
+    }
+}
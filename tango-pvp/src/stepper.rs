@@ -3,6 +3,7 @@ pub struct InnerState {
     current_tick: u32,
     local_player_index: u8,
     input_pairs: std::collections::VecDeque<crate::input::Pair<crate::input::PartialInput, crate::input::PartialInput>>,
+    consumed_input_pairs: usize,
     output_pairs: Vec<crate::input::Pair<crate::input::Input, crate::input::Input>>,
     apply_shadow_input: Box<
         dyn FnMut(crate::input::Pair<crate::input::Input, crate::input::PartialInput>) -> anyhow::Result<Vec<u8>>
@@ -96,7 +97,9 @@ impl InnerState {
     pub fn pop_input_pair(
         &mut self,
     ) -> Option<crate::input::Pair<crate::input::PartialInput, crate::input::PartialInput>> {
-        self.input_pairs.pop_front()
+        let ip = self.input_pairs.pop_front()?;
+        self.consumed_input_pairs += 1;
+        Some(ip)
     }
 
     pub fn apply_shadow_input(
@@ -165,6 +168,72 @@ impl InnerState {
     pub fn increment_current_tick(&mut self) {
         self.current_tick += 1;
     }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            consumed_input_pairs: self.consumed_input_pairs,
+            current_tick: self.current_tick,
+            local_packet: self.local_packet.clone(),
+            phase: self.phase,
+            round_result: self.round_result,
+        }
+    }
+}
+
+/// Where a replay's stepper was at, so it can be put back there along with a save state taken at the same time.
+#[derive(Clone)]
+pub struct Checkpoint {
+    consumed_input_pairs: usize,
+    current_tick: u32,
+    local_packet: Option<crate::input::Packet>,
+    phase: RoundPhase,
+    round_result: Option<RoundResult>,
+}
+
+impl Checkpoint {
+    pub fn current_tick(&self) -> u32 {
+        self.current_tick
+    }
+}
+
+fn partial_input_pairs(
+    input_pairs: &[crate::input::Pair<crate::input::Input, crate::input::Input>],
+) -> std::collections::VecDeque<crate::input::Pair<crate::input::PartialInput, crate::input::PartialInput>> {
+    input_pairs
+        .iter()
+        .map(|ip| crate::input::Pair {
+            local: crate::input::PartialInput {
+                local_tick: ip.local.local_tick,
+                remote_tick: ip.local.remote_tick,
+                joyflags: ip.local.joyflags,
+                dt: ip.local.dt,
+            },
+            remote: crate::input::PartialInput {
+                local_tick: ip.remote.local_tick,
+                remote_tick: ip.remote.remote_tick,
+                joyflags: ip.remote.joyflags,
+                dt: ip.remote.dt,
+            },
+        })
+        .collect()
+}
+
+fn replay_shadow_input(
+    input_pairs: Vec<crate::input::Pair<crate::input::Input, crate::input::Input>>,
+) -> Box<
+    dyn FnMut(crate::input::Pair<crate::input::Input, crate::input::PartialInput>) -> anyhow::Result<Vec<u8>>
+        + Sync
+        + Send,
+> {
+    let mut iq = input_pairs.into_iter().collect::<std::collections::VecDeque<_>>();
+    Box::new(move |_| {
+        let ip = if let Some(ip) = iq.pop_front() {
+            ip
+        } else {
+            anyhow::bail!("no more committed inputs");
+        };
+        Ok(ip.remote.packet)
+    })
 }
 
 pub struct FastforwardResult {
@@ -222,34 +291,9 @@ impl State {
             disable_bgm: false,
             current_tick: 0,
             local_player_index,
-            input_pairs: input_pairs
-                .iter()
-                .map(|ip| crate::input::Pair {
-                    local: crate::input::PartialInput {
-                        local_tick: ip.local.local_tick,
-                        remote_tick: ip.local.remote_tick,
-                        joyflags: ip.local.joyflags,
-                        dt: ip.local.dt,
-                    },
-                    remote: crate::input::PartialInput {
-                        local_tick: ip.remote.local_tick,
-                        remote_tick: ip.remote.remote_tick,
-                        joyflags: ip.remote.joyflags,
-                        dt: ip.remote.dt,
-                    },
-                })
-                .collect(),
-            apply_shadow_input: Box::new({
-                let mut iq = input_pairs.into_iter().collect::<std::collections::VecDeque<_>>();
-                move |_| {
-                    let ip = if let Some(ip) = iq.pop_front() {
-                        ip
-                    } else {
-                        anyhow::bail!("no more committed inputs");
-                    };
-                    Ok(ip.remote.packet)
-                }
-            }),
+            input_pairs: partial_input_pairs(&input_pairs),
+            consumed_input_pairs: 0,
+            apply_shadow_input: replay_shadow_input(input_pairs),
             match_type,
             output_pairs: vec![],
             local_packet,
@@ -267,6 +311,27 @@ impl State {
     pub fn lock_inner(&self) -> parking_lot::MappedMutexGuard<'_, InnerState> {
         parking_lot::MutexGuard::map(self.0.lock(), |s| s.as_mut().unwrap())
     }
+
+    /// Puts a replay back to a checkpoint. `input_pairs` must be all of the replay's input pairs, not just the ones left.
+    pub fn restore(
+        &self,
+        checkpoint: &Checkpoint,
+        input_pairs: &[crate::input::Pair<crate::input::Input, crate::input::Input>],
+    ) {
+        let mut inner = self.lock_inner();
+        let input_pairs = &input_pairs[checkpoint.consumed_input_pairs..];
+        inner.input_pairs = partial_input_pairs(input_pairs);
+        inner.consumed_input_pairs = checkpoint.consumed_input_pairs;
+        inner.apply_shadow_input = replay_shadow_input(input_pairs.to_vec());
+        inner.output_pairs.truncate(checkpoint.consumed_input_pairs);
+        inner.current_tick = checkpoint.current_tick;
+        inner.local_packet = checkpoint.local_packet.clone();
+        inner.phase = checkpoint.phase;
+        inner.round_result = checkpoint.round_result;
+        inner.committed_state = None;
+        inner.dirty_state = None;
+        inner.error = None;
+    }
 }
 
 impl Fastforwarder {
@@ -319,6 +384,7 @@ impl Fastforwarder {
             current_tick,
             local_player_index: self.local_player_index,
             input_pairs: input_pairs.into_iter().collect(),
+            consumed_input_pairs: 0,
             output_pairs: vec![],
             apply_shadow_input,
             match_type: self.match_type,
@@ -0,0 +1,133 @@
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    Frame {
+        round_number: u8,
+        tick: u32,
+        rollback_depth: u32,
+        local_queue_length: u32,
+        remote_queue_length: u32,
+        local_delay: u32,
+        dtick: i32,
+        frame_time_us: u64,
+    },
+    Rtt {
+        rtt_us: u64,
+    },
+    LateInput {
+        round_number: u8,
+        tick: u32,
+        ticks_late: u32,
+    },
+    DroppedInput {
+        round_number: u8,
+        tick: u32,
+        reason: String,
+    },
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Sample {
+    pub ts_us: u64,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+const MAX_RECENT_SAMPLES: usize = 2000;
+
+struct Inner {
+    writer: Option<Box<dyn std::io::Write + Send>>,
+    recent: std::collections::VecDeque<Sample>,
+}
+
+/// Records netplay telemetry as JSON lines, keeping the most recent samples in memory for live display.
+#[derive(Clone)]
+pub struct Recorder {
+    start_time: std::time::Instant,
+    inner: std::sync::Arc<parking_lot::Mutex<Inner>>,
+}
+
+impl Recorder {
+    pub fn new(writer: Option<Box<dyn std::io::Write + Send>>) -> Self {
+        Self {
+            start_time: std::time::Instant::now(),
+            inner: std::sync::Arc::new(parking_lot::Mutex::new(Inner {
+                writer,
+                recent: std::collections::VecDeque::with_capacity(MAX_RECENT_SAMPLES),
+            })),
+        }
+    }
+
+    pub fn record(&self, event: Event) {
+        let sample = Sample {
+            ts_us: (std::time::Instant::now() - self.start_time).as_micros() as u64,
+            event,
+        };
+
+        let mut inner = self.inner.lock();
+        if let Some(writer) = inner.writer.as_mut() {
+            if let Err(e) = (|| -> std::io::Result<()> {
+                serde_json::to_writer(&mut *writer, &sample)?;
+                writer.write_all(b"\n")
+            })() {
+                log::error!("failed to write telemetry, disabling: {:?}", e);
+                inner.writer = None;
+            }
+        }
+
+        while inner.recent.len() >= MAX_RECENT_SAMPLES {
+            inner.recent.pop_front();
+        }
+        inner.recent.push_back(sample);
+    }
+
+    pub fn recent_samples(&self) -> Vec<Sample> {
+        self.inner.lock().recent.iter().cloned().collect()
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        if let Some(writer) = self.writer.as_mut() {
+            let _ = writer.flush();
+        }
+    }
+}
+
+pub fn read(r: impl std::io::BufRead) -> std::io::Result<Vec<Sample>> {
+    let mut samples = vec![];
+    for line in r.lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        samples.push(serde_json::from_str(&line)?);
+    }
+    Ok(samples)
+}
+
+/// Deletes the oldest telemetry files in a directory until the ones left add up to at most `max_bytes`.
+pub fn prune(dir: &std::path::Path, max_bytes: u64) -> std::io::Result<()> {
+    let mut files = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.extension() != Some(std::ffi::OsStr::new("jsonl")) {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        files.push((metadata.modified()?, metadata.len(), path));
+    }
+    files.sort();
+
+    let mut total = files.iter().map(|(_, len, _)| *len).sum::<u64>();
+    for (_, len, path) in files {
+        if total <= max_bytes {
+            break;
+        }
+        log::info!("pruning telemetry: {}", path.display());
+        std::fs::remove_file(&path)?;
+        total -= len;
+    }
+    Ok(())
+}
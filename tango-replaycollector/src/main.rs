@@ -0,0 +1,301 @@
+mod ratings;
+mod store;
+use envconfig::Envconfig;
+use routerify::ext::RequestExt;
+
+#[derive(Envconfig)]
+struct Config {
+    #[envconfig(from = "LISTEN_ADDR", default = "[::]:1985")]
+    listen_addr: String,
+
+    #[envconfig(from = "DATA_PATH", default = "replays")]
+    data_path: String,
+
+    #[envconfig(from = "MAX_REPLAY_SIZE", default = "16777216")]
+    max_replay_size: usize,
+
+    #[envconfig(from = "MAX_QUERY_LIMIT", default = "500")]
+    max_query_limit: usize,
+
+    // A directory of unpatched ROMs to evaluate replays with. Ratings are only computed if this is set.
+    #[envconfig(from = "ROMS_PATH", default = "")]
+    roms_path: String,
+
+    #[envconfig(from = "ELO_K_FACTOR", default = "32")]
+    elo_k_factor: f64,
+}
+
+const DEFAULT_QUERY_LIMIT: usize = 50;
+
+const REPLAY_CONTENT_TYPE: &str = "application/x-tango-replay";
+
+struct State {
+    store: std::sync::Arc<store::Store>,
+    ratings: Option<std::sync::Arc<ratings::Ratings>>,
+    max_replay_size: usize,
+    max_query_limit: usize,
+}
+
+#[derive(serde::Serialize)]
+struct UploadResponse {
+    id: String,
+    duplicate: bool,
+}
+
+fn error_response(status: hyper::StatusCode, body: &'static str) -> hyper::Response<hyper::Body> {
+    hyper::Response::builder()
+        .status(status)
+        .body(hyper::Body::from(body))
+        .unwrap()
+}
+
+async fn handle_healthcheck_request(
+    _request: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, anyhow::Error> {
+    Ok(hyper::Response::builder()
+        .status(hyper::StatusCode::OK)
+        .body(hyper::Body::from("ok"))
+        .unwrap())
+}
+
+async fn handle_upload_request(
+    request: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, anyhow::Error> {
+    let (store, ratings, max_replay_size) = {
+        let state = request.data::<State>().unwrap();
+        (state.store.clone(), state.ratings.clone(), state.max_replay_size)
+    };
+
+    if request
+        .headers()
+        .get(hyper::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        != Some(REPLAY_CONTENT_TYPE)
+    {
+        return Ok(error_response(
+            hyper::StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "expected application/x-tango-replay",
+        ));
+    }
+
+    // Read the body ourselves so an oversized upload is cut off instead of being buffered in full.
+    let mut body = request.into_body();
+    let mut raw = vec![];
+    while let Some(chunk) = hyper::body::HttpBody::data(&mut body).await {
+        let chunk = chunk?;
+        if raw.len() + chunk.len() > max_replay_size {
+            return Ok(error_response(hyper::StatusCode::PAYLOAD_TOO_LARGE, "replay too large"));
+        }
+        raw.extend_from_slice(&chunk);
+    }
+
+    let (raw, replay) = match tokio::task::spawn_blocking(move || {
+        let r = tango_pvp::replay::Replay::decode(std::io::Cursor::new(&raw[..]));
+        (raw, r)
+    })
+    .await?
+    {
+        (raw, Ok(replay)) => (raw, replay),
+        (_, Err(e)) => {
+            log::warn!("rejecting invalid replay: {}", e);
+            return Ok(error_response(hyper::StatusCode::BAD_REQUEST, "invalid replay"));
+        }
+    };
+
+    let (id, outcome) = store.insert(&raw, &replay).await?;
+    log::info!("received replay {}: {:?}", id, outcome);
+
+    if outcome == store::InsertOutcome::Mismatched {
+        return Ok(error_response(
+            hyper::StatusCode::CONFLICT,
+            "replay does not match the copy already stored",
+        ));
+    }
+
+    if let Some(ratings) = ratings {
+        if outcome != store::InsertOutcome::Duplicate && replay.is_complete {
+            ratings.enqueue(id.clone());
+        }
+    }
+
+    Ok(hyper::Response::builder()
+        .status(if outcome == store::InsertOutcome::Inserted {
+            hyper::StatusCode::CREATED
+        } else {
+            hyper::StatusCode::OK
+        })
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(hyper::Body::from(serde_json::to_vec(&UploadResponse {
+            id,
+            duplicate: outcome != store::InsertOutcome::Inserted,
+        })?))
+        .unwrap())
+}
+
+async fn handle_query_request(
+    request: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, anyhow::Error> {
+    let state = request.data::<State>().unwrap();
+
+    let mut query = store::Query {
+        limit: DEFAULT_QUERY_LIMIT,
+        ..Default::default()
+    };
+    for (k, v) in url::form_urlencoded::parse(request.uri().query().unwrap_or_default().as_bytes()).into_owned() {
+        match k.as_str() {
+            "link_code" => query.link_code = Some(v),
+            "nickname" => query.nickname = Some(v),
+            "rom_family" => query.rom_family = Some(v),
+            "before" => {
+                query.before = if let Ok(before) = v.parse() {
+                    Some(before)
+                } else {
+                    return Ok(error_response(hyper::StatusCode::BAD_REQUEST, "invalid before"));
+                };
+            }
+            "complete" => query.complete_only = v == "true",
+            "limit" => {
+                query.limit = if let Ok(limit) = v.parse::<usize>() {
+                    std::cmp::min(limit, state.max_query_limit)
+                } else {
+                    return Ok(error_response(hyper::StatusCode::BAD_REQUEST, "invalid limit"));
+                };
+            }
+            _ => {}
+        }
+    }
+
+    Ok(hyper::Response::builder()
+        .status(hyper::StatusCode::OK)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(hyper::Body::from(serde_json::to_vec(&state.store.query(&query).await)?))
+        .unwrap())
+}
+
+async fn handle_download_request(
+    request: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, anyhow::Error> {
+    let state = request.data::<State>().unwrap();
+    let id = request.param("id").unwrap();
+
+    let raw = if let Some(raw) = state.store.read(id).await? {
+        raw
+    } else {
+        return Ok(error_response(hyper::StatusCode::NOT_FOUND, "no such replay"));
+    };
+
+    Ok(hyper::Response::builder()
+        .status(hyper::StatusCode::OK)
+        .header(hyper::header::CONTENT_TYPE, REPLAY_CONTENT_TYPE)
+        .header(
+            hyper::header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}.tangoreplay\"", id),
+        )
+        .body(hyper::Body::from(raw))
+        .unwrap())
+}
+
+async fn handle_leaderboard_request(
+    request: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, anyhow::Error> {
+    let state = request.data::<State>().unwrap();
+    let ratings = state.ratings.as_ref().unwrap();
+    let family = request.param("family").unwrap();
+
+    let limit = match url::form_urlencoded::parse(request.uri().query().unwrap_or_default().as_bytes())
+        .into_owned()
+        .find(|(k, _)| k == "limit")
+    {
+        Some((_, v)) => {
+            if let Ok(limit) = v.parse::<usize>() {
+                std::cmp::min(limit, state.max_query_limit)
+            } else {
+                return Ok(error_response(hyper::StatusCode::BAD_REQUEST, "invalid limit"));
+            }
+        }
+        None => DEFAULT_QUERY_LIMIT,
+    };
+
+    Ok(hyper::Response::builder()
+        .status(hyper::StatusCode::OK)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(hyper::Body::from(serde_json::to_vec(
+            &ratings.leaderboard(family, limit).await,
+        )?))
+        .unwrap())
+}
+
+async fn handle_player_request(
+    request: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, anyhow::Error> {
+    let state = request.data::<State>().unwrap();
+    let ratings = state.ratings.as_ref().unwrap();
+    let nickname = request.param("nickname").unwrap();
+
+    Ok(hyper::Response::builder()
+        .status(hyper::StatusCode::OK)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(hyper::Body::from(serde_json::to_vec(&ratings.player(nickname).await)?))
+        .unwrap())
+}
+
+fn router(
+    store: std::sync::Arc<store::Store>,
+    ratings: Option<std::sync::Arc<ratings::Ratings>>,
+    max_replay_size: usize,
+    max_query_limit: usize,
+) -> routerify::Router<hyper::Body, anyhow::Error> {
+    let enable_ratings = ratings.is_some();
+    let mut builder = routerify::Router::builder()
+        .data(State {
+            store,
+            ratings,
+            max_replay_size,
+            max_query_limit,
+        })
+        // Tango posts replays straight to the configured endpoint.
+        .post("/", handle_upload_request)
+        .post("/replays", handle_upload_request)
+        .get("/replays", handle_query_request)
+        .get("/replays/:id", handle_download_request)
+        .get("/ok", handle_healthcheck_request);
+    if enable_ratings {
+        builder = builder
+            .get("/leaderboards/:family", handle_leaderboard_request)
+            .get("/players/:nickname", handle_player_request);
+    }
+    builder.build().unwrap()
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::Builder::from_default_env()
+        .filter(Some("tango_replaycollector"), log::LevelFilter::Info)
+        .init();
+    log::info!("welcome to {} {}!", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    let config = Config::init_from_env().unwrap();
+    let addr = config.listen_addr.parse()?;
+
+    let data_path = std::path::Path::new(&config.data_path);
+    let store = std::sync::Arc::new(store::Store::open(data_path)?);
+    let ratings = if !config.roms_path.is_empty() {
+        log::info!("computing ratings with roms from {}", config.roms_path);
+        Some(
+            ratings::Ratings::start(
+                data_path,
+                std::path::Path::new(&config.roms_path),
+                config.elo_k_factor,
+                store.clone(),
+            )
+            .await?,
+        )
+    } else {
+        log::warn!("no roms path, will not compute ratings");
+        None
+    };
+    let router = router(store, ratings, config.max_replay_size, config.max_query_limit);
+
+    let service = routerify::RouterService::new(router).unwrap();
+    hyper::Server::bind(&addr).serve(service).await?;
+    Ok(())
+}
@@ -0,0 +1,359 @@
+use crate::store;
+
+const LEDGER_FILENAME: &str = "ratings.json";
+const INITIAL_RATING: f64 = 1500.0;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Rating {
+    pub nickname: String,
+    pub rating: f64,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+}
+
+impl Rating {
+    fn new(nickname: &str) -> Self {
+        Self {
+            nickname: nickname.to_string(),
+            rating: INITIAL_RATING,
+            wins: 0,
+            losses: 0,
+            draws: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Outcome {
+    P1Win,
+    P2Win,
+    Draw,
+}
+
+enum Evaluation {
+    Rated {
+        family: &'static str,
+        outcome: Outcome,
+    },
+    /// Both sides were played back but don't agree on the outcome. Playing them back again won't change that.
+    Disagreed,
+    /// This server can never rate the round, e.g. because one of the sides is patched or the sides are playing different games.
+    Unratable,
+    /// One of the sides is for a game we don't have the ROM for yet.
+    MissingRom,
+}
+
+/// Everything needed to pick up where we left off after a restart.
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+struct Ledger {
+    rated: std::collections::HashSet<String>,
+    families: std::collections::BTreeMap<String, std::collections::HashMap<String, Rating>>,
+}
+
+/// Elo ratings per game family, updated from each round that both sides' ROMs agree on the result of.
+pub struct Ratings {
+    path: std::path::PathBuf,
+    k_factor: f64,
+    roms: std::collections::HashMap<(&'static str, u8), Vec<u8>>,
+    store: std::sync::Arc<store::Store>,
+    ledger: tokio::sync::Mutex<Ledger>,
+    queue_tx: tokio::sync::mpsc::UnboundedSender<String>,
+}
+
+fn load_roms(roms_path: &std::path::Path) -> anyhow::Result<std::collections::HashMap<(&'static str, u8), Vec<u8>>> {
+    let mut roms = std::collections::HashMap::new();
+    for entry in std::fs::read_dir(roms_path)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        let rom = std::fs::read(&path)?;
+        let game = if let Some(game) = tango_gamedb::detect(&rom) {
+            game
+        } else {
+            log::warn!("skipping unrecognized rom: {}", path.display());
+            continue;
+        };
+        log::info!("loaded rom for {:?}: {}", game.family_and_variant, path.display());
+        roms.insert(game.family_and_variant, rom);
+    }
+    Ok(roms)
+}
+
+impl Ratings {
+    /// Starts the background worker. Any complete replays that were collected before ratings were turned on are rated first.
+    pub async fn start(
+        data_path: &std::path::Path,
+        roms_path: &std::path::Path,
+        k_factor: f64,
+        store: std::sync::Arc<store::Store>,
+    ) -> anyhow::Result<std::sync::Arc<Self>> {
+        let roms = load_roms(roms_path)?;
+        let path = data_path.join(LEDGER_FILENAME);
+        let ledger = match std::fs::read(&path) {
+            Ok(raw) => serde_json::from_slice(&raw)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ledger::default(),
+            Err(e) => {
+                return Err(e.into());
+            }
+        };
+
+        let (queue_tx, mut queue_rx) = tokio::sync::mpsc::unbounded_channel();
+        let ratings = std::sync::Arc::new(Self {
+            path,
+            k_factor,
+            roms,
+            store,
+            ledger: tokio::sync::Mutex::new(ledger),
+            queue_tx,
+        });
+
+        {
+            let ledger = ratings.ledger.lock().await;
+            for id in ratings.store.complete_ids().await {
+                if !ledger.rated.contains(&id) {
+                    ratings.enqueue(id);
+                }
+            }
+        }
+
+        tokio::spawn({
+            let ratings = ratings.clone();
+            async move {
+                while let Some(id) = queue_rx.recv().await {
+                    if let Err(e) = ratings.rate(&id).await {
+                        log::error!("failed to rate replay {}: {:?}", id, e);
+                    }
+                }
+            }
+        });
+
+        Ok(ratings)
+    }
+
+    pub fn enqueue(&self, id: String) {
+        let _ = self.queue_tx.send(id);
+    }
+
+    /// Plays the round back from both sides, so a replay from a peer that desynced doesn't count.
+    async fn evaluate(&self, replay: tango_pvp::replay::Replay) -> anyhow::Result<Evaluation> {
+        let replay = if replay.local_player_index == 0 {
+            replay
+        } else {
+            replay.into_remote()
+        };
+
+        let mut games = vec![];
+        for side in [&replay.metadata.local_side, &replay.metadata.remote_side] {
+            let game_info = if let Some(game_info) = side.as_ref().and_then(|side| side.game_info.as_ref()) {
+                game_info
+            } else {
+                log::warn!("missing game info, not rating");
+                return Ok(Evaluation::Unratable);
+            };
+            if game_info.patch.is_some() {
+                // We only have the unpatched ROMs.
+                return Ok(Evaluation::Unratable);
+            }
+            let game = if let Some(game) =
+                tango_gamedb::find_by_family_and_variant(&game_info.rom_family, game_info.rom_variant as u8)
+            {
+                game
+            } else {
+                log::warn!(
+                    "unknown game {} {}, not rating",
+                    game_info.rom_family,
+                    game_info.rom_variant
+                );
+                return Ok(Evaluation::Unratable);
+            };
+            let rom = if let Some(rom) = self.roms.get(&game.family_and_variant) {
+                rom.clone()
+            } else {
+                log::warn!("no rom for {:?}, not rating", game.family_and_variant);
+                return Ok(Evaluation::MissingRom);
+            };
+            let hooks = if let Some(hooks) = tango_pvp::hooks::hooks_for_gamedb_entry(game) {
+                hooks
+            } else {
+                log::warn!("no hooks for {:?}, not rating", game.family_and_variant);
+                return Ok(Evaluation::Unratable);
+            };
+            games.push((game.family_and_variant.0, rom, hooks));
+        }
+        let (p2_family, p2_rom, p2_hooks) = games.pop().unwrap();
+        let (p1_family, p1_rom, p1_hooks) = games.pop().unwrap();
+        if p1_family != p2_family {
+            // There's no ladder to put a cross-family round on.
+            log::warn!("sides are playing {} and {}, not rating", p1_family, p2_family);
+            return Ok(Evaluation::Unratable);
+        }
+
+        // Evaluation emulates the whole round, so keep it off the async workers.
+        let handle = tokio::runtime::Handle::current();
+        let (p1_result, p2_result) = match tokio::task::spawn_blocking(move || {
+            handle.block_on(async {
+                let (p1_result, _) = tango_pvp::eval::eval(&replay, &p1_rom, p1_hooks, Vec::new).await?;
+                let (p2_result, _) = tango_pvp::eval::eval(&replay.into_remote(), &p2_rom, p2_hooks, Vec::new).await?;
+                Ok::<_, anyhow::Error>((p1_result, p2_result))
+            })
+        })
+        .await?
+        {
+            Ok(results) => results,
+            Err(e) => {
+                // Playback is deterministic, so trying again won't help.
+                log::warn!("failed to play back round, not rating: {:?}", e);
+                return Ok(Evaluation::Unratable);
+            }
+        };
+
+        Ok(match (p1_result.outcome, p2_result.outcome) {
+            (tango_pvp::stepper::BattleOutcome::Win, tango_pvp::stepper::BattleOutcome::Loss) => Evaluation::Rated {
+                family: p1_family,
+                outcome: Outcome::P1Win,
+            },
+            (tango_pvp::stepper::BattleOutcome::Loss, tango_pvp::stepper::BattleOutcome::Win) => Evaluation::Rated {
+                family: p1_family,
+                outcome: Outcome::P2Win,
+            },
+            (tango_pvp::stepper::BattleOutcome::Draw, tango_pvp::stepper::BattleOutcome::Draw) => Evaluation::Rated {
+                family: p1_family,
+                outcome: Outcome::Draw,
+            },
+            _ => {
+                log::warn!("both sides disagree on the outcome, not rating");
+                Evaluation::Disagreed
+            }
+        })
+    }
+
+    async fn rate(&self, id: &str) -> anyhow::Result<()> {
+        if self.ledger.lock().await.rated.contains(id) {
+            return Ok(());
+        }
+
+        let raw = if let Some(raw) = self.store.read(id).await? {
+            raw
+        } else {
+            anyhow::bail!("replay is gone");
+        };
+        let replay =
+            tokio::task::spawn_blocking(move || tango_pvp::replay::Replay::decode(std::io::Cursor::new(raw))).await??;
+        if !replay.is_complete {
+            // A more complete copy may still turn up from the other side.
+            return Ok(());
+        }
+
+        let (p1_nickname, p2_nickname) = {
+            let local_nickname = replay
+                .metadata
+                .local_side
+                .as_ref()
+                .map(|side| side.nickname.clone())
+                .unwrap_or_default();
+            let remote_nickname = replay
+                .metadata
+                .remote_side
+                .as_ref()
+                .map(|side| side.nickname.clone())
+                .unwrap_or_default();
+            if replay.local_player_index == 0 {
+                (local_nickname, remote_nickname)
+            } else {
+                (remote_nickname, local_nickname)
+            }
+        };
+
+        let rated = match self.evaluate(replay).await? {
+            Evaluation::Rated { family, outcome } => Some((family, outcome)),
+            Evaluation::Disagreed | Evaluation::Unratable => None,
+            Evaluation::MissingRom => {
+                // Left unrated, so it's picked up again once the server is restarted with the ROM.
+                return Ok(());
+            }
+        };
+
+        let mut ledger = self.ledger.lock().await;
+        ledger.rated.insert(id.to_string());
+        if let Some((family, outcome)) = rated {
+            if p1_nickname != p2_nickname {
+                let ratings = ledger.families.entry(family.to_string()).or_default();
+                let p1 = ratings
+                    .get(&p1_nickname)
+                    .cloned()
+                    .unwrap_or_else(|| Rating::new(&p1_nickname));
+                let p2 = ratings
+                    .get(&p2_nickname)
+                    .cloned()
+                    .unwrap_or_else(|| Rating::new(&p2_nickname));
+                let (p1, p2) = update(p1, p2, outcome, self.k_factor);
+                log::info!(
+                    "rated replay {}: {} ({:.0}) vs {} ({:.0}): {:?}",
+                    id,
+                    p1.nickname,
+                    p1.rating,
+                    p2.nickname,
+                    p2.rating,
+                    outcome
+                );
+                ratings.insert(p1_nickname, p1);
+                ratings.insert(p2_nickname, p2);
+            }
+        }
+
+        let tmp_path = self.path.with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, serde_json::to_vec(&*ledger)?).await?;
+        tokio::fs::rename(&tmp_path, &self.path).await?;
+        Ok(())
+    }
+
+    /// Returns the family's players by rating, highest first.
+    pub async fn leaderboard(&self, family: &str, limit: usize) -> Vec<Rating> {
+        let ledger = self.ledger.lock().await;
+        let mut ratings = ledger
+            .families
+            .get(family)
+            .map(|ratings| ratings.values().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+        ratings.sort_by(|a, b| b.rating.total_cmp(&a.rating));
+        ratings.truncate(limit);
+        ratings
+    }
+
+    /// Returns the player's rating in each family they've played.
+    pub async fn player(&self, nickname: &str) -> std::collections::BTreeMap<String, Rating> {
+        let ledger = self.ledger.lock().await;
+        ledger
+            .families
+            .iter()
+            .flat_map(|(family, ratings)| Some((family.clone(), ratings.get(nickname)?.clone())))
+            .collect()
+    }
+}
+
+fn update(mut p1: Rating, mut p2: Rating, outcome: Outcome, k_factor: f64) -> (Rating, Rating) {
+    let expected_p1 = 1.0 / (1.0 + 10.0f64.powf((p2.rating - p1.rating) / 400.0));
+    let score_p1 = match outcome {
+        Outcome::P1Win => {
+            p1.wins += 1;
+            p2.losses += 1;
+            1.0
+        }
+        Outcome::P2Win => {
+            p1.losses += 1;
+            p2.wins += 1;
+            0.0
+        }
+        Outcome::Draw => {
+            p1.draws += 1;
+            p2.draws += 1;
+            0.5
+        }
+    };
+    let delta = k_factor * (score_p1 - expected_p1);
+    p1.rating += delta;
+    p2.rating -= delta;
+    (p1, p2)
+}
@@ -0,0 +1,299 @@
+use sha2::Digest;
+
+/// One entry per line, appended as replays come in. A later line for the same ID supersedes an earlier one.
+const INDEX_FILENAME: &str = "index.jsonl";
+const REPLAY_EXTENSION: &str = "tangoreplay";
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Player {
+    pub nickname: String,
+    pub rom_family: String,
+    pub rom_variant: u32,
+    pub patch_name: Option<String>,
+    pub patch_version: Option<String>,
+}
+
+impl Player {
+    fn from_side(side: &tango_pvp::replay::metadata::Side) -> Self {
+        let game_info = side.game_info.as_ref();
+        let patch = game_info.and_then(|game_info| game_info.patch.as_ref());
+        Self {
+            nickname: side.nickname.clone(),
+            rom_family: game_info
+                .map(|game_info| game_info.rom_family.clone())
+                .unwrap_or_default(),
+            rom_variant: game_info.map(|game_info| game_info.rom_variant).unwrap_or_default(),
+            patch_name: patch.map(|patch| patch.name.clone()),
+            patch_version: patch.map(|patch| patch.version.clone()),
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Entry {
+    pub id: String,
+    pub uploaded_at: u64,
+    pub ts: u64,
+    pub link_code: String,
+    pub round: u32,
+    pub match_type: (u32, u32),
+    /// Players in P1, P2 order, regardless of who uploaded the replay.
+    pub players: [Player; 2],
+    pub is_complete: bool,
+    pub num_inputs: usize,
+    pub size: usize,
+}
+
+impl Entry {
+    pub fn matches(&self, query: &Query) -> bool {
+        if let Some(link_code) = query.link_code.as_ref() {
+            if self.link_code != *link_code {
+                return false;
+            }
+        }
+        if let Some(nickname) = query.nickname.as_ref() {
+            if !self.players.iter().any(|p| p.nickname == *nickname) {
+                return false;
+            }
+        }
+        if let Some(rom_family) = query.rom_family.as_ref() {
+            if !self.players.iter().any(|p| p.rom_family == *rom_family) {
+                return false;
+            }
+        }
+        if let Some(before) = query.before {
+            if self.uploaded_at >= before {
+                return false;
+            }
+        }
+        if query.complete_only && !self.is_complete {
+            return false;
+        }
+        true
+    }
+}
+
+#[derive(Default)]
+pub struct Query {
+    pub link_code: Option<String>,
+    pub nickname: Option<String>,
+    pub rom_family: Option<String>,
+    pub before: Option<u64>,
+    pub complete_only: bool,
+    pub limit: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum InsertOutcome {
+    Inserted,
+    Replaced,
+    Duplicate,
+    /// The other peer's copy is already here and this one disagrees with it, so one of them was tampered with.
+    Mismatched,
+}
+
+/// Replays on disk, keyed by round so that both peers' uploads of the same round end up as a single entry.
+pub struct Store {
+    path: std::path::PathBuf,
+    entries: tokio::sync::Mutex<std::collections::HashMap<String, Entry>>,
+    /// Held while a round's file is compared against or written, so uploads of the same round go one at a time without holding up anything else.
+    round_locks: std::sync::Mutex<std::collections::HashMap<String, std::sync::Weak<tokio::sync::Mutex<()>>>>,
+    index_lock: tokio::sync::Mutex<()>,
+}
+
+/// Both peers record the same pair of starting states for a round, so the ID doesn't depend on who uploaded it.
+fn round_id(replay: &tango_pvp::replay::Replay) -> String {
+    let (p1_state, p2_state) = if replay.local_player_index == 0 {
+        (&replay.local_state, &replay.remote_state)
+    } else {
+        (&replay.remote_state, &replay.local_state)
+    };
+
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(replay.metadata.link_code.as_bytes());
+    hasher.update(replay.metadata.round.to_le_bytes());
+    hasher.update(p1_state.as_slice());
+    hasher.update(p2_state.as_slice());
+    format!("{:x}", hasher.finalize())
+}
+
+/// The inputs of a round as (tick, P1 joyflags, P2 joyflags), which both peers' copies agree on.
+fn round_inputs(replay: &tango_pvp::replay::Replay) -> Vec<(u32, u16, u16)> {
+    replay
+        .input_pairs
+        .iter()
+        .map(|ip| {
+            if replay.local_player_index == 0 {
+                (ip.local.local_tick, ip.local.joyflags, ip.remote.joyflags)
+            } else {
+                (ip.local.local_tick, ip.remote.joyflags, ip.local.joyflags)
+            }
+        })
+        .collect()
+}
+
+impl Store {
+    pub fn open(path: &std::path::Path) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(path)?;
+        let mut entries = std::collections::HashMap::new();
+        match std::fs::read_to_string(path.join(INDEX_FILENAME)) {
+            Ok(raw) => {
+                let lines = raw.lines().filter(|line| !line.is_empty()).collect::<Vec<_>>();
+                for (i, line) in lines.iter().enumerate() {
+                    let entry = match serde_json::from_str::<Entry>(line) {
+                        Ok(entry) => entry,
+                        Err(e) if i == lines.len() - 1 => {
+                            // Most likely we crashed halfway through appending it, in which case the replay it's for was never acknowledged either.
+                            log::warn!("ignoring unreadable last index entry: {}", e);
+                            break;
+                        }
+                        Err(e) => {
+                            return Err(e.into());
+                        }
+                    };
+                    entries.insert(entry.id.clone(), entry);
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(e.into());
+            }
+        }
+        log::info!("loaded {} replays from {}", entries.len(), path.display());
+        Ok(Self {
+            path: path.to_owned(),
+            entries: tokio::sync::Mutex::new(entries),
+            round_locks: std::sync::Mutex::new(std::collections::HashMap::new()),
+            index_lock: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    fn replay_path(&self, id: &str) -> std::path::PathBuf {
+        self.path.join(format!("{}.{}", id, REPLAY_EXTENSION))
+    }
+
+    fn round_lock(&self, id: &str) -> std::sync::Arc<tokio::sync::Mutex<()>> {
+        let mut round_locks = self.round_locks.lock().unwrap();
+        round_locks.retain(|_, lock| lock.strong_count() > 0);
+        if let Some(lock) = round_locks.get(id).and_then(|lock| lock.upgrade()) {
+            return lock;
+        }
+        let lock = std::sync::Arc::new(tokio::sync::Mutex::new(()));
+        round_locks.insert(id.to_owned(), std::sync::Arc::downgrade(&lock));
+        lock
+    }
+
+    async fn append_to_index(&self, entry: &Entry) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        let _index_guard = self.index_lock.lock().await;
+        let mut f = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path.join(INDEX_FILENAME))
+            .await?;
+        tokio::io::AsyncWriteExt::write_all(&mut f, &line).await?;
+        f.sync_data().await?;
+        Ok(())
+    }
+
+    /// Checks that a copy of a stored round tells the same story as the stored one, i.e. that whichever is shorter is a prefix of the other.
+    async fn agrees_with_stored(&self, id: &str, replay: &tango_pvp::replay::Replay) -> anyhow::Result<bool> {
+        let raw = tokio::fs::read(self.replay_path(id)).await?;
+        let stored =
+            tokio::task::spawn_blocking(move || tango_pvp::replay::Replay::decode(std::io::Cursor::new(&raw[..])))
+                .await??;
+        let stored_inputs = round_inputs(&stored);
+        let inputs = round_inputs(replay);
+        let n = std::cmp::min(inputs.len(), stored_inputs.len());
+        Ok(inputs[..n] == stored_inputs[..n])
+    }
+
+    /// Stores a replay that has already been decoded.
+    ///
+    /// The first copy of a round is kept, unless the other peer's copy is more complete. Either way, a copy that disagrees with the stored one on any input they both have is rejected.
+    pub async fn insert(
+        &self,
+        raw: &[u8],
+        replay: &tango_pvp::replay::Replay,
+    ) -> anyhow::Result<(String, InsertOutcome)> {
+        let id = round_id(replay);
+        let (p1_side, p2_side) = if replay.local_player_index == 0 {
+            (&replay.metadata.local_side, &replay.metadata.remote_side)
+        } else {
+            (&replay.metadata.remote_side, &replay.metadata.local_side)
+        };
+        let default_side = tango_pvp::replay::metadata::Side::default();
+
+        let entry = Entry {
+            id: id.clone(),
+            uploaded_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_secs(),
+            ts: replay.metadata.ts,
+            link_code: replay.metadata.link_code.clone(),
+            round: replay.metadata.round,
+            match_type: (replay.metadata.match_type, replay.metadata.match_subtype),
+            players: [
+                Player::from_side(p1_side.as_ref().unwrap_or(&default_side)),
+                Player::from_side(p2_side.as_ref().unwrap_or(&default_side)),
+            ],
+            is_complete: replay.is_complete,
+            num_inputs: replay.input_pairs.len(),
+            size: raw.len(),
+        };
+
+        let round_lock = self.round_lock(&id);
+        let _round_guard = round_lock.lock().await;
+
+        let existing = self.entries.lock().await.get(&id).cloned();
+        let outcome = if let Some(existing) = existing {
+            if !self.agrees_with_stored(&id, replay).await? {
+                return Ok((id, InsertOutcome::Mismatched));
+            }
+            if (existing.is_complete, existing.num_inputs) >= (entry.is_complete, entry.num_inputs) {
+                return Ok((id, InsertOutcome::Duplicate));
+            }
+            InsertOutcome::Replaced
+        } else {
+            InsertOutcome::Inserted
+        };
+
+        // Write the replay out in full before it's indexed, so that the index never points at half a file.
+        let tmp_path = self.path.join(format!("{}.{}.tmp", id, REPLAY_EXTENSION));
+        tokio::fs::write(&tmp_path, raw).await?;
+        tokio::fs::rename(&tmp_path, self.replay_path(&id)).await?;
+        self.append_to_index(&entry).await?;
+        self.entries.lock().await.insert(id.clone(), entry);
+        Ok((id, outcome))
+    }
+
+    /// Returns matching entries, newest first.
+    pub async fn query(&self, query: &Query) -> Vec<Entry> {
+        let entries = self.entries.lock().await;
+        let mut matches = entries
+            .values()
+            .filter(|entry| entry.matches(query))
+            .cloned()
+            .collect::<Vec<_>>();
+        matches.sort_by_key(|entry| std::cmp::Reverse((entry.uploaded_at, entry.ts)));
+        matches.truncate(query.limit);
+        matches
+    }
+
+    /// Returns the IDs of all complete replays, oldest first.
+    pub async fn complete_ids(&self) -> Vec<String> {
+        let entries = self.entries.lock().await;
+        let mut complete = entries.values().filter(|entry| entry.is_complete).collect::<Vec<_>>();
+        complete.sort_by_key(|entry| (entry.uploaded_at, entry.ts));
+        complete.into_iter().map(|entry| entry.id.clone()).collect()
+    }
+
+    pub async fn read(&self, id: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        // Only hand out files that are in the index, so arbitrary paths can't be requested.
+        if !self.entries.lock().await.contains_key(id) {
+            return Ok(None);
+        }
+        Ok(Some(tokio::fs::read(self.replay_path(id)).await?))
+    }
+}
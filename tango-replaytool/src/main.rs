@@ -58,6 +58,12 @@ pub enum Command {
 
     /// Evaluate the result of a replay.
     Eval { rom_path: std::path::PathBuf },
+
+    /// Check that the other peer's replay of the same round agrees with this one: same inputs, same starting state, and the same state and result at the end.
+    Sync {
+        other_path: std::path::PathBuf,
+        rom_path: std::path::PathBuf,
+    },
 }
 
 #[tokio::main]
@@ -100,6 +106,17 @@ pub async fn main() -> Result<(), anyhow::Error> {
             .await
         }
         Command::Eval { rom_path } => cmd_eval(replay, rom_path).await,
+        Command::Sync { other_path, rom_path } => {
+            let mut f = std::fs::File::open(&other_path)?;
+            let mut other = tango_pvp::replay::Replay::decode(&mut f)?;
+
+            // The other peer's replay has the sides the other way around, so this makes both describe the same side.
+            if !args.invert {
+                other = other.into_remote();
+            }
+
+            cmd_sync(replay, other, rom_path).await
+        }
     }
 }
 
@@ -256,3 +273,80 @@ async fn cmd_eval(replay: tango_pvp::replay::Replay, rom_path: std::path::PathBu
 
     Ok(())
 }
+
+async fn cmd_sync(
+    replay: tango_pvp::replay::Replay,
+    other: tango_pvp::replay::Replay,
+    rom_path: std::path::PathBuf,
+) -> Result<(), anyhow::Error> {
+    if replay.local_player_index != other.local_player_index {
+        anyhow::bail!(
+            "player index mismatch: {} != {}",
+            replay.local_player_index,
+            other.local_player_index
+        );
+    }
+
+    if replay.input_pairs.len() != other.input_pairs.len() {
+        anyhow::bail!(
+            "input length mismatch: {} != {}",
+            replay.input_pairs.len(),
+            other.input_pairs.len()
+        );
+    }
+
+    for (i, (ip, other_ip)) in replay.input_pairs.iter().zip(other.input_pairs.iter()).enumerate() {
+        if ip.local.joyflags != other_ip.local.joyflags
+            || ip.remote.joyflags != other_ip.remote.joyflags
+            || ip.local.packet != other_ip.local.packet
+            || ip.remote.packet != other_ip.remote.packet
+        {
+            anyhow::bail!("inputs diverged at pair {} (tick {})", i, ip.local.local_tick);
+        }
+    }
+
+    if replay.local_state.wram() != other.local_state.wram() {
+        anyhow::bail!("starting states differ");
+    }
+
+    let rom = std::fs::read(&rom_path)?;
+    let detected_game = tango_gamedb::detect(&rom).ok_or(anyhow::anyhow!("rom detection failed"))?;
+    let game_info = replay
+        .metadata
+        .local_side
+        .as_ref()
+        .and_then(|side| side.game_info.as_ref())
+        .ok_or(anyhow::anyhow!("missing local game info"))?;
+    let game = tango_gamedb::find_by_family_and_variant(&game_info.rom_family, game_info.rom_variant as u8).unwrap();
+    let hooks = tango_pvp::hooks::hooks_for_gamedb_entry(game).unwrap();
+    if game != detected_game {
+        return Err(anyhow::format_err!(
+            "expected game {:?}, got {:?}",
+            game.family_and_variant,
+            detected_game.family_and_variant
+        ));
+    }
+
+    let (result, state) = tango_pvp::eval::eval(&replay, &rom, hooks, Vec::new).await?;
+    let (other_result, other_state) = tango_pvp::eval::eval(&other, &rom, hooks, Vec::new).await?;
+    if result.tick != other_result.tick || result.outcome != other_result.outcome {
+        anyhow::bail!(
+            "results differ: {} at tick {} != {} at tick {}",
+            result.outcome as i8,
+            result.tick,
+            other_result.outcome as i8,
+            other_result.tick
+        );
+    }
+    if state.wram() != other_state.wram() {
+        anyhow::bail!("final states differ");
+    }
+
+    println!(
+        "in sync: {} input pairs, result {} at tick {}",
+        replay.input_pairs.len(),
+        result.outcome as i8,
+        result.tick
+    );
+    Ok(())
+}
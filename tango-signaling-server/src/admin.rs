@@ -0,0 +1,412 @@
+use routerify::ext::RequestExt;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Cidr {
+    addr: std::net::IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: &std::net::IpAddr) -> bool {
+        // Behind a dual-stack listener, IPv4 clients show up as IPv4-mapped IPv6 addresses.
+        match (self.addr, &ip.to_canonical()) {
+            (std::net::IpAddr::V4(net), std::net::IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix_len as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(*ip) & mask
+            }
+            (std::net::IpAddr::V6(net), std::net::IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix_len as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(*ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl std::str::FromStr for Cidr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (addr.parse::<std::net::IpAddr>()?, Some(prefix_len.parse::<u8>()?)),
+            None => (s.parse::<std::net::IpAddr>()?, None),
+        };
+        let max_prefix_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = prefix_len.unwrap_or(max_prefix_len);
+        if prefix_len > max_prefix_len {
+            anyhow::bail!("prefix length {} is too long", prefix_len);
+        }
+        // Store IPv4-mapped ranges as plain IPv4 ones, since that's what addresses are compared as.
+        if let (std::net::IpAddr::V4(v4), true) = (addr.to_canonical(), addr.is_ipv6() && prefix_len >= 96) {
+            return Ok(Self {
+                addr: std::net::IpAddr::V4(v4),
+                prefix_len: prefix_len - 96,
+            });
+        }
+        Ok(Self { addr, prefix_len })
+    }
+}
+
+impl std::fmt::Display for Cidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// What of the admin state survives a restart, as stored at the state path.
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+struct PersistedState {
+    bans: Vec<String>,
+    maintenance_message: Option<String>,
+}
+
+/// Knobs that server operators can turn at runtime through the admin API.
+pub struct Admin {
+    token: String,
+    state_path: Option<std::path::PathBuf>,
+    save_lock: std::sync::Mutex<()>,
+    bans: std::sync::RwLock<Vec<Cidr>>,
+    maintenance_message: std::sync::RwLock<Option<String>>,
+}
+
+impl Admin {
+    /// If `state_path` is set, bans and maintenance mode are loaded from it and written back to it whenever they change.
+    pub fn new(token: String, state_path: Option<std::path::PathBuf>) -> anyhow::Result<Self> {
+        let persisted_state = match state_path.as_ref() {
+            Some(path) => match std::fs::File::open(path) {
+                Ok(f) => serde_json::from_reader::<_, PersistedState>(f)?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => PersistedState::default(),
+                Err(e) => {
+                    return Err(e.into());
+                }
+            },
+            None => PersistedState::default(),
+        };
+        let bans = persisted_state
+            .bans
+            .iter()
+            .map(|cidr| cidr.parse::<Cidr>())
+            .collect::<Result<Vec<_>, _>>()?;
+        if !bans.is_empty() || persisted_state.maintenance_message.is_some() {
+            log::info!(
+                "loaded {} bans, maintenance mode {}",
+                bans.len(),
+                if persisted_state.maintenance_message.is_some() {
+                    "on"
+                } else {
+                    "off"
+                }
+            );
+        }
+        Ok(Self {
+            token,
+            state_path,
+            save_lock: std::sync::Mutex::new(()),
+            bans: std::sync::RwLock::new(bans),
+            maintenance_message: std::sync::RwLock::new(persisted_state.maintenance_message),
+        })
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let path = if let Some(path) = self.state_path.as_ref() {
+            path
+        } else {
+            return Ok(());
+        };
+
+        // Snapshot under the lock, so a slower save can't overwrite a newer one.
+        let _save_lock = self.save_lock.lock().unwrap();
+        let persisted_state = PersistedState {
+            bans: self.bans.read().unwrap().iter().map(|cidr| cidr.to_string()).collect(),
+            maintenance_message: self.maintenance_message.read().unwrap().clone(),
+        };
+
+        // Write it out in full before replacing the old one, so a crash midway doesn't lose everything.
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec(&persisted_state)?)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    pub fn is_banned(&self, ip: &std::net::IpAddr) -> bool {
+        self.bans.read().unwrap().iter().any(|cidr| cidr.contains(ip))
+    }
+
+    /// If set, new connections are turned away with this message.
+    pub fn maintenance_message(&self) -> Option<String> {
+        self.maintenance_message.read().unwrap().clone()
+    }
+
+    /// The admin API is only served if a token was configured.
+    pub fn has_token(&self) -> bool {
+        !self.token.is_empty()
+    }
+
+    fn is_authorized(&self, request: &hyper::Request<hyper::Body>) -> bool {
+        let token = if let Some(token) = request
+            .headers()
+            .get(hyper::header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+        {
+            token
+        } else {
+            return false;
+        };
+
+        // Compare in constant time so the token can't be guessed byte by byte.
+        token.len() == self.token.len()
+            && token
+                .bytes()
+                .zip(self.token.bytes())
+                .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+}
+
+#[derive(serde::Serialize)]
+struct MaintenanceInfo {
+    message: Option<String>,
+}
+
+fn json_response<T: serde::Serialize>(v: &T) -> Result<hyper::Response<hyper::Body>, anyhow::Error> {
+    Ok(hyper::Response::builder()
+        .status(hyper::StatusCode::OK)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(hyper::Body::from(serde_json::to_vec(v)?))
+        .unwrap())
+}
+
+fn status_response(status: hyper::StatusCode, body: &'static str) -> hyper::Response<hyper::Body> {
+    hyper::Response::builder()
+        .status(status)
+        .body(hyper::Body::from(body))
+        .unwrap()
+}
+
+fn query_param(request: &hyper::Request<hyper::Body>, key: &str) -> Option<String> {
+    request.uri().query().and_then(|query| {
+        url::form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    })
+}
+
+fn unauthorized(request: &hyper::Request<hyper::Body>) -> Option<hyper::Response<hyper::Body>> {
+    if request.data::<super::State>().unwrap().admin.is_authorized(request) {
+        return None;
+    }
+    Some(status_response(hyper::StatusCode::UNAUTHORIZED, "unauthorized"))
+}
+
+pub async fn handle_list_sessions(
+    request: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, anyhow::Error> {
+    if let Some(response) = unauthorized(&request) {
+        return Ok(response);
+    }
+    let state = request.data::<super::State>().unwrap();
+    json_response(&state.matchmaking_server.list_sessions().await)
+}
+
+pub async fn handle_close_session(
+    request: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, anyhow::Error> {
+    if let Some(response) = unauthorized(&request) {
+        return Ok(response);
+    }
+    let state = request.data::<super::State>().unwrap();
+    let session_id = request.param("session_id").unwrap();
+    if !state
+        .matchmaking_server
+        .close_session(
+            session_id,
+            tango_signaling::proto::signaling::packet::abort::Reason::ClosedByAdmin,
+            None,
+        )
+        .await
+    {
+        return Ok(status_response(hyper::StatusCode::NOT_FOUND, "no such session"));
+    }
+    log::info!("admin closed session {}", session_id);
+    Ok(status_response(hyper::StatusCode::OK, "ok"))
+}
+
+pub async fn handle_list_bans(
+    request: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, anyhow::Error> {
+    if let Some(response) = unauthorized(&request) {
+        return Ok(response);
+    }
+    let state = request.data::<super::State>().unwrap();
+    json_response(
+        &state
+            .admin
+            .bans
+            .read()
+            .unwrap()
+            .iter()
+            .map(|cidr| cidr.to_string())
+            .collect::<Vec<_>>(),
+    )
+}
+
+pub async fn handle_add_ban(
+    request: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, anyhow::Error> {
+    if let Some(response) = unauthorized(&request) {
+        return Ok(response);
+    }
+    let state = request.data::<super::State>().unwrap();
+    let cidr = if let Some(cidr) = query_param(&request, "cidr").and_then(|cidr| cidr.parse::<Cidr>().ok()) {
+        cidr
+    } else {
+        return Ok(status_response(
+            hyper::StatusCode::BAD_REQUEST,
+            "missing or invalid cidr",
+        ));
+    };
+    {
+        let mut bans = state.admin.bans.write().unwrap();
+        if !bans.contains(&cidr) {
+            bans.push(cidr);
+        }
+    }
+    state.admin.save()?;
+    let closed = state
+        .matchmaking_server
+        .close_sessions_from(
+            |ip| cidr.contains(ip),
+            tango_signaling::proto::signaling::packet::abort::Reason::Banned,
+        )
+        .await;
+    log::info!("admin banned {} ({} sessions closed)", cidr, closed);
+    Ok(status_response(hyper::StatusCode::OK, "ok"))
+}
+
+pub async fn handle_remove_ban(
+    request: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, anyhow::Error> {
+    if let Some(response) = unauthorized(&request) {
+        return Ok(response);
+    }
+    let state = request.data::<super::State>().unwrap();
+    let cidr = if let Some(cidr) = query_param(&request, "cidr").and_then(|cidr| cidr.parse::<Cidr>().ok()) {
+        cidr
+    } else {
+        return Ok(status_response(
+            hyper::StatusCode::BAD_REQUEST,
+            "missing or invalid cidr",
+        ));
+    };
+    {
+        let mut bans = state.admin.bans.write().unwrap();
+        let len = bans.len();
+        bans.retain(|ban| *ban != cidr);
+        if bans.len() == len {
+            return Ok(status_response(hyper::StatusCode::NOT_FOUND, "no such ban"));
+        }
+    }
+    state.admin.save()?;
+    log::info!("admin unbanned {}", cidr);
+    Ok(status_response(hyper::StatusCode::OK, "ok"))
+}
+
+pub async fn handle_get_maintenance(
+    request: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, anyhow::Error> {
+    if let Some(response) = unauthorized(&request) {
+        return Ok(response);
+    }
+    let state = request.data::<super::State>().unwrap();
+    json_response(&MaintenanceInfo {
+        message: state.admin.maintenance_message(),
+    })
+}
+
+/// The request body is the notice shown to players. Everyone still waiting for an opponent is sent it too.
+pub async fn handle_set_maintenance(
+    request: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, anyhow::Error> {
+    if let Some(response) = unauthorized(&request) {
+        return Ok(response);
+    }
+    let (admin, matchmaking_server) = {
+        let state = request.data::<super::State>().unwrap();
+        (state.admin.clone(), state.matchmaking_server.clone())
+    };
+    let message = String::from_utf8(hyper::body::to_bytes(request.into_body()).await?.to_vec())?;
+    *admin.maintenance_message.write().unwrap() = Some(message.clone());
+    admin.save()?;
+    let closed = matchmaking_server
+        .close_all_sessions(
+            tango_signaling::proto::signaling::packet::abort::Reason::Maintenance,
+            Some(message.clone()),
+        )
+        .await;
+    log::info!(
+        "admin enabled maintenance mode ({} sessions closed): {}",
+        closed,
+        message
+    );
+    Ok(status_response(hyper::StatusCode::OK, "ok"))
+}
+
+pub async fn handle_clear_maintenance(
+    request: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, anyhow::Error> {
+    if let Some(response) = unauthorized(&request) {
+        return Ok(response);
+    }
+    let state = request.data::<super::State>().unwrap();
+    *state.admin.maintenance_message.write().unwrap() = None;
+    state.admin.save()?;
+    log::info!("admin disabled maintenance mode");
+    Ok(status_response(hyper::StatusCode::OK, "ok"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> std::net::IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_cidr_contains() {
+        let cidr = "10.1.0.0/16".parse::<Cidr>().unwrap();
+        assert!(cidr.contains(&ip("10.1.2.3")));
+        assert!(!cidr.contains(&ip("10.2.2.3")));
+        assert!(!cidr.contains(&ip("2001:db8::1")));
+
+        let cidr = "2001:db8::/32".parse::<Cidr>().unwrap();
+        assert!(cidr.contains(&ip("2001:db8:1::1")));
+        assert!(!cidr.contains(&ip("2001:db9::1")));
+        assert!(!cidr.contains(&ip("10.1.2.3")));
+
+        let cidr = "0.0.0.0/0".parse::<Cidr>().unwrap();
+        assert!(cidr.contains(&ip("192.0.2.1")));
+    }
+
+    #[test]
+    fn test_cidr_contains_mapped() {
+        let cidr = "10.1.0.0/16".parse::<Cidr>().unwrap();
+        assert!(cidr.contains(&ip("::ffff:10.1.2.3")));
+        assert!(!cidr.contains(&ip("::ffff:10.2.2.3")));
+
+        let cidr = "::ffff:10.1.0.0/112".parse::<Cidr>().unwrap();
+        assert_eq!(cidr.to_string(), "10.1.0.0/16");
+        assert!(cidr.contains(&ip("10.1.2.3")));
+        assert!(cidr.contains(&ip("::ffff:10.1.2.3")));
+    }
+
+    #[test]
+    fn test_cidr_from_str_errors() {
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("::/129".parse::<Cidr>().is_err());
+        assert!("nonsense".parse::<Cidr>().is_err());
+    }
+}
@@ -1,6 +1,8 @@
 pub mod cloudflare;
+pub mod coturn;
 pub mod metered;
 pub mod opentok;
+pub mod static_list;
 pub mod twilio;
 
 #[async_trait::async_trait]
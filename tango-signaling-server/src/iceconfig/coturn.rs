@@ -0,0 +1,43 @@
+use base64::Engine;
+use hmac::Mac;
+
+/// Mints time-limited credentials for a self-hosted TURN server using the TURN REST API scheme (coturn's `use-auth-secret`).
+pub struct Backend {
+    shared_secret: String,
+    urls: Vec<String>,
+    ttl: std::time::Duration,
+}
+
+impl Backend {
+    pub fn new(shared_secret: String, urls: Vec<String>, ttl: std::time::Duration) -> Self {
+        Self {
+            shared_secret,
+            urls,
+            ttl,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl super::Backend for Backend {
+    async fn get(
+        &self,
+        remote_ip: &std::net::IpAddr,
+    ) -> anyhow::Result<Vec<tango_signaling::proto::signaling::packet::hello::IceServer>> {
+        let expiry = (std::time::SystemTime::now() + self.ttl)
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        // The expiry and the user ID are separated by a colon, so the user ID must not contain any itself (as IPv6 addresses do).
+        let username = format!("{}:{}", expiry, remote_ip.to_string().replace(':', "-"));
+
+        let mut mac = hmac::Hmac::<sha1::Sha1>::new_from_slice(self.shared_secret.as_bytes())?;
+        mac.update(username.as_bytes());
+        let credential = base64::engine::general_purpose::STANDARD.encode(mac.finalize().into_bytes());
+
+        Ok(vec![tango_signaling::proto::signaling::packet::hello::IceServer {
+            credential: Some(credential),
+            username: Some(username),
+            urls: self.urls.clone(),
+        }])
+    }
+}
@@ -0,0 +1,37 @@
+#[derive(serde::Deserialize)]
+struct ICEServer {
+    username: Option<String>,
+    credential: Option<String>,
+    urls: Vec<String>,
+}
+
+/// Hands out a fixed list of ICE servers, read from a JSON file of the form `[{"urls": [...], "username": ..., "credential": ...}]`.
+pub struct Backend {
+    ice_servers: Vec<tango_signaling::proto::signaling::packet::hello::IceServer>,
+}
+
+impl Backend {
+    pub fn new(path: &std::path::Path) -> anyhow::Result<Self> {
+        let ice_servers = serde_json::from_reader::<_, Vec<ICEServer>>(std::fs::File::open(path)?)?
+            .into_iter()
+            .map(
+                |ice_server| tango_signaling::proto::signaling::packet::hello::IceServer {
+                    credential: ice_server.credential,
+                    username: ice_server.username,
+                    urls: ice_server.urls,
+                },
+            )
+            .collect();
+        Ok(Self { ice_servers })
+    }
+}
+
+#[async_trait::async_trait]
+impl super::Backend for Backend {
+    async fn get(
+        &self,
+        _remote_ip: &std::net::IpAddr,
+    ) -> anyhow::Result<Vec<tango_signaling::proto::signaling::packet::hello::IceServer>> {
+        Ok(self.ice_servers.clone())
+    }
+}
@@ -1,6 +1,10 @@
+mod admin;
 mod httputil;
 mod iceconfig;
 mod matchmaking;
+mod metrics;
+mod queue;
+mod ratelimit;
 use envconfig::Envconfig;
 use prost::Message;
 use routerify::ext::RequestExt;
@@ -40,11 +44,51 @@ struct Config {
 
     #[envconfig(from = "METERED_API_KEY", default = "")]
     metered_api_key: String,
+
+    #[envconfig(from = "COTURN_SHARED_SECRET", default = "")]
+    coturn_shared_secret: String,
+
+    // Comma-separated, e.g. "turn:turn.example.com:3478?transport=udp,turns:turn.example.com:5349".
+    #[envconfig(from = "COTURN_URLS", default = "")]
+    coturn_urls: String,
+
+    #[envconfig(from = "COTURN_CREDENTIAL_TTL_SECS", default = "86400")]
+    coturn_credential_ttl_secs: u64,
+
+    #[envconfig(from = "STATIC_ICE_SERVERS_PATH", default = "")]
+    static_ice_servers_path: String,
+
+    #[envconfig(from = "MAX_CONNECTIONS_PER_IP", default = "8")]
+    max_connections_per_ip: usize,
+
+    #[envconfig(from = "MAX_REQUESTS_PER_MINUTE_PER_IP", default = "60")]
+    max_requests_per_minute_per_ip: usize,
+
+    #[envconfig(from = "MAX_SESSIONS", default = "10000")]
+    max_sessions: usize,
+
+    #[envconfig(from = "OFFERER_IDLE_TIMEOUT_SECS", default = "900")]
+    offerer_idle_timeout_secs: u64,
+
+    #[envconfig(from = "ENABLE_METRICS", default = "false")]
+    enable_metrics: bool,
+
+    // The admin API under /admin is only served if this is set, and requires it as a bearer token.
+    #[envconfig(from = "ADMIN_TOKEN", default = "")]
+    admin_token: String,
+
+    // Where bans and maintenance mode are kept across restarts. If unset, they only last until the server exits.
+    #[envconfig(from = "ADMIN_STATE_PATH", default = "")]
+    admin_state_path: String,
 }
 
 struct State {
     real_ip_getter: httputil::RealIPGetter,
+    limiter: std::sync::Arc<ratelimit::Limiter>,
+    metrics: std::sync::Arc<metrics::Metrics>,
     matchmaking_server: std::sync::Arc<matchmaking::Server>,
+    queue_server: std::sync::Arc<queue::Server>,
+    admin: std::sync::Arc<admin::Admin>,
 }
 
 async fn handle_healthcheck_request(
@@ -56,7 +100,59 @@ async fn handle_healthcheck_request(
         .unwrap())
 }
 
-pub const EXPECTED_PROTOCOL_VERSION: u8 = 0x3a;
+pub const EXPECTED_PROTOCOL_VERSION: u8 = 0x3b;
+
+fn abort_response(
+    request: &hyper::Request<hyper::Body>,
+    status: hyper::StatusCode,
+    reason: tango_signaling::proto::signaling::packet::abort::Reason,
+) -> hyper::Response<hyper::Body> {
+    abort_response_with_message(request, status, reason, None)
+}
+
+fn abort_response_with_message(
+    request: &hyper::Request<hyper::Body>,
+    status: hyper::StatusCode,
+    reason: tango_signaling::proto::signaling::packet::abort::Reason,
+    message: Option<String>,
+) -> hyper::Response<hyper::Body> {
+    request.data::<State>().unwrap().metrics.record_abort(reason);
+    hyper::Response::builder()
+        .status(status)
+        .body(hyper::Body::from(
+            tango_signaling::proto::signaling::packet::Abort {
+                reason: reason as i32,
+                message,
+            }
+            .encode_to_vec(),
+        ))
+        .unwrap()
+}
+
+/// Turns away banned IPs, and everyone if the server is in maintenance mode.
+fn admission_response(
+    request: &hyper::Request<hyper::Body>,
+    remote_ip: std::net::IpAddr,
+) -> Option<hyper::Response<hyper::Body>> {
+    let admin = &request.data::<State>().unwrap().admin;
+    if admin.is_banned(&remote_ip) {
+        log::warn!("rejecting banned ip {}", remote_ip);
+        return Some(abort_response(
+            request,
+            hyper::StatusCode::FORBIDDEN,
+            tango_signaling::proto::signaling::packet::abort::Reason::Banned,
+        ));
+    }
+    if let Some(message) = admin.maintenance_message() {
+        return Some(abort_response_with_message(
+            request,
+            hyper::StatusCode::SERVICE_UNAVAILABLE,
+            tango_signaling::proto::signaling::packet::abort::Reason::Maintenance,
+            Some(message),
+        ));
+    }
+    None
+}
 
 async fn handle_matchmaking_request(
     mut request: hyper::Request<hyper::Body>,
@@ -75,6 +171,19 @@ async fn handle_matchmaking_request(
             .unwrap());
     };
 
+    if let Some(response) = admission_response(&request, remote_ip) {
+        return Ok(response);
+    }
+
+    if !request.data::<State>().unwrap().limiter.check_request(remote_ip) {
+        log::warn!("{} is making too many requests", remote_ip);
+        return Ok(abort_response(
+            &request,
+            hyper::StatusCode::TOO_MANY_REQUESTS,
+            tango_signaling::proto::signaling::packet::abort::Reason::RateLimited,
+        ));
+    }
+
     let session_id = if let Some(session_id) = request.uri().query().and_then(|query| {
         url::form_urlencoded::parse(query.as_bytes())
             .into_owned()
@@ -83,15 +192,11 @@ async fn handle_matchmaking_request(
     }) {
         session_id
     } else {
-        return Ok(hyper::Response::builder()
-            .status(hyper::StatusCode::BAD_REQUEST)
-            .body(hyper::Body::from(
-                tango_signaling::proto::signaling::packet::Abort {
-                    reason: tango_signaling::proto::signaling::packet::abort::Reason::MissingSessionId as i32,
-                }
-                .encode_to_vec(),
-            ))
-            .unwrap());
+        return Ok(abort_response(
+            &request,
+            hyper::StatusCode::BAD_REQUEST,
+            tango_signaling::proto::signaling::packet::abort::Reason::MissingSessionId,
+        ));
     };
 
     let protocol_version = request
@@ -101,34 +206,44 @@ async fn handle_matchmaking_request(
         .and_then(|v| u32::from_str_radix(v, 16).ok());
     if let Some(protocol_version) = protocol_version {
         if protocol_version as u8 != EXPECTED_PROTOCOL_VERSION {
-            return Ok(hyper::Response::builder()
-                .status(hyper::StatusCode::BAD_REQUEST)
-                .body(hyper::Body::from(
-                    tango_signaling::proto::signaling::packet::Abort {
-                        reason: if (protocol_version as u8) < EXPECTED_PROTOCOL_VERSION {
-                            tango_signaling::proto::signaling::packet::abort::Reason::ProtocolVersionTooOld
-                        } else {
-                            tango_signaling::proto::signaling::packet::abort::Reason::ProtocolVersionTooNew
-                        } as i32,
-                    }
-                    .encode_to_vec(),
-                ))
-                .unwrap());
+            // Clients that make it past this point are counted once they send their start message instead.
+            request
+                .data::<State>()
+                .unwrap()
+                .metrics
+                .record_protocol_version(protocol_version);
+            return Ok(abort_response(
+                &request,
+                hyper::StatusCode::BAD_REQUEST,
+                if (protocol_version as u8) < EXPECTED_PROTOCOL_VERSION {
+                    tango_signaling::proto::signaling::packet::abort::Reason::ProtocolVersionTooOld
+                } else {
+                    tango_signaling::proto::signaling::packet::abort::Reason::ProtocolVersionTooNew
+                },
+            ));
         }
     }
 
     if !hyper_tungstenite::is_upgrade_request(&request) {
-        return Ok(hyper::Response::builder()
-            .status(hyper::StatusCode::BAD_REQUEST)
-            .body(hyper::Body::from(
-                tango_signaling::proto::signaling::packet::Abort {
-                    reason: tango_signaling::proto::signaling::packet::abort::Reason::NotUpgrade as i32,
-                }
-                .encode_to_vec(),
-            ))
-            .unwrap());
+        return Ok(abort_response(
+            &request,
+            hyper::StatusCode::BAD_REQUEST,
+            tango_signaling::proto::signaling::packet::abort::Reason::NotUpgrade,
+        ));
     }
 
+    let connection_guard =
+        if let Some(connection_guard) = request.data::<State>().unwrap().limiter.acquire_connection(remote_ip) {
+            connection_guard
+        } else {
+            log::warn!("{} has too many open connections", remote_ip);
+            return Ok(abort_response(
+                &request,
+                hyper::StatusCode::TOO_MANY_REQUESTS,
+                tango_signaling::proto::signaling::packet::abort::Reason::RateLimited,
+            ));
+        };
+
     let (response, websocket) = hyper_tungstenite::upgrade(
         &mut request,
         Some(tungstenite::protocol::WebSocketConfig {
@@ -140,6 +255,7 @@ async fn handle_matchmaking_request(
 
     let matchmaking_server = request.data::<State>().unwrap().matchmaking_server.clone();
     tokio::spawn(async move {
+        let _connection_guard = connection_guard;
         let websocket = match websocket.await {
             Ok(websocket) => websocket,
             Err(e) => {
@@ -159,19 +275,172 @@ async fn handle_matchmaking_request(
     Ok(response)
 }
 
+async fn handle_metrics_request(
+    request: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, anyhow::Error> {
+    let state = request.data::<State>().unwrap();
+    let body = state.metrics.render(
+        state.matchmaking_server.active_sessions().await,
+        state.queue_server.queued_players().await,
+    );
+    Ok(hyper::Response::builder()
+        .status(hyper::StatusCode::OK)
+        .header(hyper::header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(hyper::Body::from(body))
+        .unwrap())
+}
+
+async fn handle_rooms_request(
+    request: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, anyhow::Error> {
+    let state = request.data::<State>().unwrap();
+    if let Some(remote_ip) = state.real_ip_getter.get_remote_real_ip(&request) {
+        if state.admin.is_banned(&remote_ip) {
+            return Ok(abort_response(
+                &request,
+                hyper::StatusCode::FORBIDDEN,
+                tango_signaling::proto::signaling::packet::abort::Reason::Banned,
+            ));
+        }
+        if !state.limiter.check_request(remote_ip) {
+            return Ok(abort_response(
+                &request,
+                hyper::StatusCode::TOO_MANY_REQUESTS,
+                tango_signaling::proto::signaling::packet::abort::Reason::RateLimited,
+            ));
+        }
+    }
+
+    let rooms = state.matchmaking_server.list_rooms().await;
+    Ok(hyper::Response::builder()
+        .status(hyper::StatusCode::OK)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(hyper::Body::from(serde_json::to_vec(&rooms)?))
+        .unwrap())
+}
+
+async fn handle_queue_request(
+    mut request: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, anyhow::Error> {
+    let remote_ip = if let Some(remote_ip) = request
+        .data::<State>()
+        .unwrap()
+        .real_ip_getter
+        .get_remote_real_ip(&request)
+    {
+        remote_ip
+    } else {
+        return Ok(hyper::Response::builder()
+            .status(hyper::StatusCode::INTERNAL_SERVER_ERROR)
+            .body(hyper::Body::from("internal error"))
+            .unwrap());
+    };
+
+    if let Some(response) = admission_response(&request, remote_ip) {
+        return Ok(response);
+    }
+
+    if !request.data::<State>().unwrap().limiter.check_request(remote_ip) {
+        log::warn!("{} is making too many requests", remote_ip);
+        return Ok(abort_response(
+            &request,
+            hyper::StatusCode::TOO_MANY_REQUESTS,
+            tango_signaling::proto::signaling::packet::abort::Reason::RateLimited,
+        ));
+    }
+
+    if !hyper_tungstenite::is_upgrade_request(&request) {
+        return Ok(abort_response(
+            &request,
+            hyper::StatusCode::BAD_REQUEST,
+            tango_signaling::proto::signaling::packet::abort::Reason::NotUpgrade,
+        ));
+    }
+
+    let connection_guard =
+        if let Some(connection_guard) = request.data::<State>().unwrap().limiter.acquire_connection(remote_ip) {
+            connection_guard
+        } else {
+            log::warn!("{} has too many open connections", remote_ip);
+            return Ok(abort_response(
+                &request,
+                hyper::StatusCode::TOO_MANY_REQUESTS,
+                tango_signaling::proto::signaling::packet::abort::Reason::RateLimited,
+            ));
+        };
+
+    let (response, websocket) = hyper_tungstenite::upgrade(
+        &mut request,
+        Some(tungstenite::protocol::WebSocketConfig {
+            max_message_size: Some(64 * 1024),
+            max_frame_size: Some(64 * 1024),
+            ..Default::default()
+        }),
+    )?;
+
+    let queue_server = request.data::<State>().unwrap().queue_server.clone();
+    tokio::spawn(async move {
+        let _connection_guard = connection_guard;
+        let websocket = match websocket.await {
+            Ok(websocket) => websocket,
+            Err(e) => {
+                log::error!("error in websocket connection: {}", e);
+                return;
+            }
+        };
+
+        if let Err(e) = queue_server.handle_stream(websocket, remote_ip).await {
+            log::error!("error in queue connection: {}", e);
+        }
+    });
+
+    Ok(response)
+}
+
 fn router(
     real_ip_getter: httputil::RealIPGetter,
+    limiter: std::sync::Arc<ratelimit::Limiter>,
     iceconfig_backend: Option<Box<dyn iceconfig::Backend + Send + Sync + 'static>>,
+    max_sessions: usize,
+    offerer_idle_timeout: std::time::Duration,
+    enable_metrics: bool,
+    admin: admin::Admin,
 ) -> routerify::Router<hyper::Body, anyhow::Error> {
-    routerify::Router::builder()
+    let has_admin_api = admin.has_token();
+    let metrics = std::sync::Arc::new(metrics::Metrics::new());
+    let mut builder = routerify::Router::builder()
         .data(State {
             real_ip_getter,
-            matchmaking_server: std::sync::Arc::new(matchmaking::Server::new(iceconfig_backend)),
+            limiter,
+            metrics: metrics.clone(),
+            matchmaking_server: std::sync::Arc::new(matchmaking::Server::new(
+                iceconfig_backend,
+                max_sessions,
+                offerer_idle_timeout,
+                metrics.clone(),
+            )),
+            queue_server: std::sync::Arc::new(queue::Server::new(metrics)),
+            admin: std::sync::Arc::new(admin),
         })
         .get("/", handle_matchmaking_request)
-        .get("/ok", handle_healthcheck_request)
-        .build()
-        .unwrap()
+        .get("/queue", handle_queue_request)
+        .get("/rooms", handle_rooms_request)
+        .get("/ok", handle_healthcheck_request);
+    if enable_metrics {
+        builder = builder.get("/metrics", handle_metrics_request);
+    }
+    if has_admin_api {
+        builder = builder
+            .get("/admin/sessions", admin::handle_list_sessions)
+            .delete("/admin/sessions/:session_id", admin::handle_close_session)
+            .get("/admin/bans", admin::handle_list_bans)
+            .post("/admin/bans", admin::handle_add_ban)
+            .delete("/admin/bans", admin::handle_remove_ban)
+            .get("/admin/maintenance", admin::handle_get_maintenance)
+            .put("/admin/maintenance", admin::handle_set_maintenance)
+            .delete("/admin/maintenance", admin::handle_clear_maintenance);
+    }
+    builder.build().unwrap()
 }
 
 #[tokio::main]
@@ -184,42 +453,89 @@ async fn main() -> anyhow::Result<()> {
     let real_ip_getter = httputil::RealIPGetter::new(config.use_x_real_ip);
     let addr = config.listen_addr.parse()?;
 
-    let iceconfig_backend: Option<Box<dyn iceconfig::Backend + Send + Sync + 'static>> = if !config
-        .twilio_account_sid
-        .is_empty()
-        && !config.twilio_api_sid.is_empty()
-        && !config.twilio_api_secret.is_empty()
-    {
-        log::info!("using twilio iceconfig backend");
-        Some(Box::new(iceconfig::twilio::Backend::new(
-            config.twilio_account_sid.clone(),
-            config.twilio_api_sid.clone(),
-            config.twilio_api_secret.clone(),
-        )))
-    } else if !config.cloudflare_turn_service_id.is_empty() && !config.cloudflare_turn_service_api_token.is_empty() {
-        log::info!("using cloudflare iceconfig backend");
-        Some(Box::new(iceconfig::cloudflare::Backend::new(
-            config.cloudflare_turn_service_id.clone(),
-            config.cloudflare_turn_service_api_token.clone(),
-        )))
-    } else if !config.opentok_api_key.is_empty() && !config.opentok_api_secret.is_empty() {
-        log::info!("using opentok iceconfig backend");
-        Some(Box::new(iceconfig::opentok::Backend::new(
-            config.opentok_api_key.clone(),
-            config.opentok_api_secret.clone(),
-        )))
-    } else if !config.metered_application_name.is_empty() && !config.metered_api_key.is_empty() {
-        log::info!("using metered iceconfig backend");
-        Some(Box::new(iceconfig::metered::Backend::new(
-            config.metered_application_name.clone(),
-            config.metered_api_key.clone(),
-        )))
-    } else {
-        log::warn!("no iceconfig backend, will not service iceconfig requests");
-        None
-    };
+    let iceconfig_backend: Option<Box<dyn iceconfig::Backend + Send + Sync + 'static>> =
+        if !config.coturn_shared_secret.is_empty() && !config.coturn_urls.is_empty() {
+            log::info!("using coturn iceconfig backend");
+            Some(Box::new(iceconfig::coturn::Backend::new(
+                config.coturn_shared_secret.clone(),
+                config
+                    .coturn_urls
+                    .split(',')
+                    .map(|url| url.trim().to_string())
+                    .filter(|url| !url.is_empty())
+                    .collect(),
+                std::time::Duration::from_secs(config.coturn_credential_ttl_secs),
+            )))
+        } else if !config.static_ice_servers_path.is_empty() {
+            log::info!("using static iceconfig backend");
+            Some(Box::new(iceconfig::static_list::Backend::new(std::path::Path::new(
+                &config.static_ice_servers_path,
+            ))?))
+        } else if !config.twilio_account_sid.is_empty()
+            && !config.twilio_api_sid.is_empty()
+            && !config.twilio_api_secret.is_empty()
+        {
+            log::info!("using twilio iceconfig backend");
+            Some(Box::new(iceconfig::twilio::Backend::new(
+                config.twilio_account_sid.clone(),
+                config.twilio_api_sid.clone(),
+                config.twilio_api_secret.clone(),
+            )))
+        } else if !config.cloudflare_turn_service_id.is_empty() && !config.cloudflare_turn_service_api_token.is_empty()
+        {
+            log::info!("using cloudflare iceconfig backend");
+            Some(Box::new(iceconfig::cloudflare::Backend::new(
+                config.cloudflare_turn_service_id.clone(),
+                config.cloudflare_turn_service_api_token.clone(),
+            )))
+        } else if !config.opentok_api_key.is_empty() && !config.opentok_api_secret.is_empty() {
+            log::info!("using opentok iceconfig backend");
+            Some(Box::new(iceconfig::opentok::Backend::new(
+                config.opentok_api_key.clone(),
+                config.opentok_api_secret.clone(),
+            )))
+        } else if !config.metered_application_name.is_empty() && !config.metered_api_key.is_empty() {
+            log::info!("using metered iceconfig backend");
+            Some(Box::new(iceconfig::metered::Backend::new(
+                config.metered_application_name.clone(),
+                config.metered_api_key.clone(),
+            )))
+        } else {
+            log::warn!("no iceconfig backend, will not service iceconfig requests");
+            None
+        };
+
+    let limiter = std::sync::Arc::new(ratelimit::Limiter::new(
+        config.max_connections_per_ip,
+        config.max_requests_per_minute_per_ip,
+    ));
+    tokio::spawn({
+        let limiter = limiter.clone();
+        async move {
+            let mut prune_timer = tokio::time::interval(std::time::Duration::from_secs(60));
+            loop {
+                prune_timer.tick().await;
+                limiter.prune();
+            }
+        }
+    });
 
-    let router = router(real_ip_getter, iceconfig_backend);
+    let router = router(
+        real_ip_getter,
+        limiter,
+        iceconfig_backend,
+        config.max_sessions,
+        std::time::Duration::from_secs(config.offerer_idle_timeout_secs),
+        config.enable_metrics,
+        admin::Admin::new(
+            config.admin_token,
+            if !config.admin_state_path.is_empty() {
+                Some(std::path::PathBuf::from(config.admin_state_path))
+            } else {
+                None
+            },
+        )?,
+    );
 
     let service = routerify::RouterService::new(router).unwrap();
     hyper::Server::bind(&addr).serve(service).await?;
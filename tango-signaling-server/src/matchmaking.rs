@@ -2,44 +2,237 @@ use byteorder::WriteBytesExt;
 use futures_util::{SinkExt, StreamExt, TryStreamExt};
 use prost::Message;
 
-use crate::iceconfig;
+use crate::{iceconfig, metrics};
 
 const ICECONFIG_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+const RX_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+const TX_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
 
-struct Session {
-    offer_sdp: String,
-    offerer_tx: std::sync::Arc<
-        tokio::sync::Mutex<
-            futures_util::stream::SplitSink<
-                hyper_tungstenite::WebSocketStream<hyper::upgrade::Upgraded>,
-                tungstenite::Message,
-            >,
+const MAX_NICKNAME_LENGTH: usize = 40;
+
+const MAX_PENDING_CANDIDATES: usize = 64;
+
+type Tx = std::sync::Arc<
+    tokio::sync::Mutex<
+        futures_util::stream::SplitSink<
+            hyper_tungstenite::WebSocketStream<hyper::upgrade::Upgraded>,
+            tungstenite::Message,
         >,
     >,
+>;
+
+/// Where the offerer's trickled ICE candidates go: they are held here until someone answers, and forwarded straight to the answerer after that.
+#[derive(Default)]
+struct OffererRelay {
+    answerer_tx: Option<Tx>,
+    pending_candidates: Vec<tango_signaling::proto::signaling::packet::IceCandidate>,
+}
+
+/// Why an admin closed a session, as relayed to the offerer.
+type Kill = (tango_signaling::proto::signaling::packet::abort::Reason, Option<String>);
+
+struct Session {
+    /// The connection that registered this session, so that only it can unregister it: a client that reconnects with the same session ID re-registers before its old connection is cleaned up.
+    connection_id: u64,
+    offer_sdp: String,
+    room: Option<tango_signaling::proto::signaling::Room>,
+    remote_ip: std::net::IpAddr,
+    protocol_version: u32,
+    created_at: std::time::Instant,
+    offerer_tx: Tx,
+    offerer_relay: std::sync::Arc<tokio::sync::Mutex<OffererRelay>>,
+    kill_tx: tokio::sync::oneshot::Sender<Kill>,
+}
+
+enum Role {
+    Offerer(std::sync::Arc<tokio::sync::Mutex<OffererRelay>>),
+    Answerer(Tx),
+}
+
+async fn send_ice_candidate(
+    tx: &Tx,
+    cand: tango_signaling::proto::signaling::packet::IceCandidate,
+) -> anyhow::Result<()> {
+    tokio::time::timeout(
+        TX_TIMEOUT,
+        tx.lock().await.send(tungstenite::Message::Binary(
+            tango_signaling::proto::signaling::Packet {
+                which: Some(tango_signaling::proto::signaling::packet::Which::IceCandidate(cand)),
+            }
+            .encode_to_vec(),
+        )),
+    )
+    .await??;
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+pub struct RoomInfo {
+    pub session_id: String,
+    pub nickname: String,
+    pub game_family: String,
+    pub game_variant: u32,
+    pub patch_name: Option<String>,
+    pub patch_version: Option<String>,
+    pub netplay_compatibility: String,
+    pub match_type: (u32, u32),
+    pub open_for_secs: u64,
+}
+
+#[derive(serde::Serialize)]
+pub struct SessionInfo {
+    pub session_id: String,
+    pub remote_ip: std::net::IpAddr,
+    pub protocol_version: u32,
+    pub age_secs: u64,
+    pub is_room: bool,
 }
 
 pub struct Server {
     sessions: tokio::sync::Mutex<std::collections::HashMap<String, Session>>,
+    next_connection_id: std::sync::atomic::AtomicU64,
     iceconfig_backend: Option<Box<dyn iceconfig::Backend + Send + Sync + 'static>>,
+    max_sessions: usize,
+    offerer_idle_timeout: std::time::Duration,
+    metrics: std::sync::Arc<metrics::Metrics>,
 }
 
 impl Server {
-    pub fn new(iceconfig_backend: Option<Box<dyn iceconfig::Backend + Send + Sync + 'static>>) -> Server {
+    pub fn new(
+        iceconfig_backend: Option<Box<dyn iceconfig::Backend + Send + Sync + 'static>>,
+        max_sessions: usize,
+        offerer_idle_timeout: std::time::Duration,
+        metrics: std::sync::Arc<metrics::Metrics>,
+    ) -> Server {
         Server {
             sessions: tokio::sync::Mutex::new(std::collections::HashMap::new()),
+            next_connection_id: std::sync::atomic::AtomicU64::new(0),
             iceconfig_backend,
+            max_sessions,
+            offerer_idle_timeout,
+            metrics,
         }
     }
 
+    pub async fn active_sessions(&self) -> usize {
+        self.sessions.lock().await.len()
+    }
+
+    /// Lists all sessions that are waiting for an opponent, oldest first.
+    pub async fn list_sessions(&self) -> Vec<SessionInfo> {
+        let sessions = self.sessions.lock().await;
+        let mut infos = sessions
+            .iter()
+            .map(|(session_id, session)| SessionInfo {
+                session_id: session_id.clone(),
+                remote_ip: session.remote_ip,
+                protocol_version: session.protocol_version,
+                age_secs: session.created_at.elapsed().as_secs(),
+                is_room: session.room.is_some(),
+            })
+            .collect::<Vec<_>>();
+        infos.sort_by_key(|info| std::cmp::Reverse(info.age_secs));
+        infos
+    }
+
+    /// Aborts a waiting session with the given reason. Returns false if there is no such session.
+    pub async fn close_session(
+        &self,
+        session_id: &str,
+        reason: tango_signaling::proto::signaling::packet::abort::Reason,
+        message: Option<String>,
+    ) -> bool {
+        let session = if let Some(session) = self.sessions.lock().await.remove(session_id) {
+            session
+        } else {
+            return false;
+        };
+        let _ = session.kill_tx.send((reason, message));
+        true
+    }
+
+    /// Aborts every waiting session with the given reason, returning how many there were.
+    pub async fn close_all_sessions(
+        &self,
+        reason: tango_signaling::proto::signaling::packet::abort::Reason,
+        message: Option<String>,
+    ) -> usize {
+        let sessions = std::mem::take(&mut *self.sessions.lock().await);
+        let n = sessions.len();
+        for (_, session) in sessions {
+            let _ = session.kill_tx.send((reason, message.clone()));
+        }
+        n
+    }
+
+    /// Aborts every waiting session whose offerer's IP matches, returning how many there were.
+    pub async fn close_sessions_from(
+        &self,
+        matches: impl Fn(&std::net::IpAddr) -> bool,
+        reason: tango_signaling::proto::signaling::packet::abort::Reason,
+    ) -> usize {
+        let mut sessions = self.sessions.lock().await;
+        let session_ids = sessions
+            .iter()
+            .filter(|(_, session)| matches(&session.remote_ip))
+            .map(|(session_id, _)| session_id.clone())
+            .collect::<Vec<_>>();
+        for session_id in session_ids.iter() {
+            let session = sessions.remove(session_id).unwrap();
+            let _ = session.kill_tx.send((reason, None));
+        }
+        session_ids.len()
+    }
+
+    /// Lists sessions that are waiting for an opponent and were registered as public rooms.
+    pub async fn list_rooms(&self) -> Vec<RoomInfo> {
+        let sessions = self.sessions.lock().await;
+        let mut rooms = sessions
+            .iter()
+            .flat_map(|(session_id, session)| {
+                let room = session.room.as_ref()?;
+                Some(RoomInfo {
+                    session_id: session_id.clone(),
+                    nickname: room.nickname.chars().take(MAX_NICKNAME_LENGTH).collect(),
+                    game_family: room.game_family.clone(),
+                    game_variant: room.game_variant,
+                    patch_name: room.patch_name.clone(),
+                    patch_version: room.patch_version.clone(),
+                    netplay_compatibility: room.netplay_compatibility.clone(),
+                    match_type: (room.match_type, room.match_subtype),
+                    open_for_secs: session.created_at.elapsed().as_secs(),
+                })
+            })
+            .collect::<Vec<_>>();
+        rooms.sort_by_key(|room| room.open_for_secs);
+        rooms
+    }
+
+    /// Unregisters a session, but only if it's still the one the given connection registered. Returns false otherwise.
+    async fn remove_own_session(&self, session_id: &str, connection_id: u64) -> bool {
+        let mut sessions = self.sessions.lock().await;
+        if !sessions
+            .get(session_id)
+            .map(|session| session.connection_id == connection_id)
+            .unwrap_or(false)
+        {
+            return false;
+        }
+        sessions.remove(session_id);
+        true
+    }
+
     pub async fn handle_stream(
         &self,
         ws: hyper_tungstenite::WebSocketStream<hyper::upgrade::Upgraded>,
         remote_ip: std::net::IpAddr,
         session_id: &str,
     ) -> anyhow::Result<()> {
-        let r = self.handle_stream_inner(ws, remote_ip, session_id).await;
-        let mut sessions = self.sessions.lock().await;
-        sessions.remove(session_id);
+        let connection_id = self
+            .next_connection_id
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let r = self.handle_stream_inner(ws, remote_ip, session_id, connection_id).await;
+        self.remove_own_session(session_id, connection_id).await;
         r
     }
 
@@ -48,15 +241,18 @@ impl Server {
         ws: hyper_tungstenite::WebSocketStream<hyper::upgrade::Upgraded>,
         remote_ip: std::net::IpAddr,
         session_id: &str,
+        connection_id: u64,
     ) -> anyhow::Result<()> {
         let (mut tx, mut rx) = ws.split();
 
         let ice_servers = if let Some(backend) = self.iceconfig_backend.as_ref() {
-            match tokio::time::timeout(ICECONFIG_TIMEOUT, backend.get(&remote_ip))
+            let start_time = std::time::Instant::now();
+            let r = tokio::time::timeout(ICECONFIG_TIMEOUT, backend.get(&remote_ip))
                 .await
                 .map_err(|e| anyhow::Error::from(e))
-                .and_then(|r| r)
-            {
+                .and_then(|r| r);
+            self.metrics.record_iceconfig_request(start_time.elapsed(), r.is_ok());
+            match r {
                 Ok(ice_servers) => Some(ice_servers),
                 Err(e) => {
                     log::error!("failed to request ICE servers: {:?}", e);
@@ -112,9 +308,6 @@ impl Server {
         )
         .await??;
 
-        const RX_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
-        const TX_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
-
         // Wait for start message.
         let start = match tokio::time::timeout(RX_TIMEOUT, rx.try_next())
             .await??
@@ -131,18 +324,23 @@ impl Server {
             }
         };
 
+        self.metrics.record_protocol_version(start.protocol_version);
+
         if start.protocol_version as u8 != super::EXPECTED_PROTOCOL_VERSION {
+            let reason = if (start.protocol_version as u8) < super::EXPECTED_PROTOCOL_VERSION {
+                tango_signaling::proto::signaling::packet::abort::Reason::ProtocolVersionTooOld
+            } else {
+                tango_signaling::proto::signaling::packet::abort::Reason::ProtocolVersionTooNew
+            };
+            self.metrics.record_abort(reason);
             tokio::time::timeout(
                 TX_TIMEOUT,
                 tx.send(tungstenite::Message::Binary(
                     tango_signaling::proto::signaling::Packet {
                         which: Some(tango_signaling::proto::signaling::packet::Which::Abort(
                             tango_signaling::proto::signaling::packet::Abort {
-                                reason: if (start.protocol_version as u8) < super::EXPECTED_PROTOCOL_VERSION {
-                                    tango_signaling::proto::signaling::packet::abort::Reason::ProtocolVersionTooOld
-                                } else {
-                                    tango_signaling::proto::signaling::packet::abort::Reason::ProtocolVersionTooNew
-                                } as i32,
+                                reason: reason as i32,
+                                message: None,
                             },
                         )),
                     }
@@ -155,7 +353,9 @@ impl Server {
 
         let tx = std::sync::Arc::new(tokio::sync::Mutex::new(tx));
 
-        let offerer_tx = {
+        // Only set for offerers, whose sessions can be killed while they wait.
+        let mut kill_rx = None;
+        let role = {
             let mut sessions = self.sessions.lock().await;
             if let Some(session) = sessions.remove(session_id) {
                 tokio::time::timeout(
@@ -173,24 +373,134 @@ impl Server {
                 )
                 .await??;
 
-                Some(session.offerer_tx)
+                // Hand over anything the offerer has trickled in so far, and have the rest sent straight to us.
+                let mut offerer_relay = session.offerer_relay.lock().await;
+                for cand in std::mem::take(&mut offerer_relay.pending_candidates) {
+                    send_ice_candidate(&tx, cand).await?;
+                }
+                offerer_relay.answerer_tx = Some(tx.clone());
+
+                Role::Answerer(session.offerer_tx)
+            } else if sessions.len() >= self.max_sessions {
+                log::warn!("rejecting session from {}: too many sessions", remote_ip);
+                self.metrics
+                    .record_abort(tango_signaling::proto::signaling::packet::abort::Reason::ServerFull);
+                tokio::time::timeout(
+                    TX_TIMEOUT,
+                    tx.lock().await.send(tungstenite::Message::Binary(
+                        tango_signaling::proto::signaling::Packet {
+                            which: Some(tango_signaling::proto::signaling::packet::Which::Abort(
+                                tango_signaling::proto::signaling::packet::Abort {
+                                    reason: tango_signaling::proto::signaling::packet::abort::Reason::ServerFull as i32,
+                                    message: None,
+                                },
+                            )),
+                        }
+                        .encode_to_vec(),
+                    )),
+                )
+                .await??;
+                return Ok(());
             } else {
+                let offerer_relay = std::sync::Arc::new(tokio::sync::Mutex::new(OffererRelay::default()));
+                let (kill_tx, rx) = tokio::sync::oneshot::channel();
                 sessions.insert(
                     session_id.to_string(),
                     Session {
+                        connection_id,
                         offer_sdp: start.offer_sdp,
+                        room: start.room,
+                        remote_ip,
+                        protocol_version: start.protocol_version,
+                        created_at: std::time::Instant::now(),
                         offerer_tx: std::sync::Arc::clone(&tx),
+                        offerer_relay: offerer_relay.clone(),
+                        kill_tx,
                     },
                 );
-                None
+                kill_rx = Some(rx);
+                Role::Offerer(offerer_relay)
             }
         };
 
         const PING_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
         let mut ping_timer = tokio::time::interval(PING_TIMEOUT);
 
-        let answer = loop {
+        // Offerers that nobody answers shouldn't be able to hold onto a session forever.
+        let mut idle_deadline = if let Role::Offerer(_) = role {
+            Some(tokio::time::Instant::now() + self.offerer_idle_timeout)
+        } else {
+            None
+        };
+
+        // Relay the answer and trickled candidates until the client hangs up, which it does once its peer connection is up.
+        loop {
             tokio::select! {
+                _ = async {
+                    match idle_deadline {
+                        Some(deadline) => tokio::time::sleep_until(deadline).await,
+                        None => std::future::pending().await,
+                    }
+                } => {
+                    if !self.remove_own_session(session_id, connection_id).await {
+                        // An answerer has already picked up this session, so it's no longer idle.
+                        idle_deadline = None;
+                        continue;
+                    }
+                    log::info!("session {} from {} timed out waiting for an answer", session_id, remote_ip);
+                    self.metrics
+                        .record_abort(tango_signaling::proto::signaling::packet::abort::Reason::IdleTimeout);
+                    tokio::time::timeout(
+                        TX_TIMEOUT,
+                        tx.lock().await.send(tungstenite::Message::Binary(
+                            tango_signaling::proto::signaling::Packet {
+                                which: Some(tango_signaling::proto::signaling::packet::Which::Abort(
+                                    tango_signaling::proto::signaling::packet::Abort {
+                                        reason: tango_signaling::proto::signaling::packet::abort::Reason::IdleTimeout as i32,
+                                        message: None,
+                                    },
+                                )),
+                            }
+                            .encode_to_vec(),
+                        )),
+                    )
+                    .await??;
+                    return Ok(());
+                }
+
+                kill = async {
+                    match kill_rx.as_mut() {
+                        Some(kill_rx) => kill_rx.await,
+                        None => std::future::pending().await,
+                    }
+                } => {
+                    let (reason, message) = if let Ok(kill) = kill {
+                        kill
+                    } else {
+                        // The session was handed to an answerer, so nobody can kill it anymore.
+                        kill_rx = None;
+                        continue;
+                    };
+                    log::info!("session {} from {} closed: {:?}", session_id, remote_ip, reason);
+                    self.metrics.record_abort(reason);
+                    tokio::time::timeout(
+                        TX_TIMEOUT,
+                        tx.lock().await.send(tungstenite::Message::Binary(
+                            tango_signaling::proto::signaling::Packet {
+                                which: Some(tango_signaling::proto::signaling::packet::Which::Abort(
+                                    tango_signaling::proto::signaling::packet::Abort {
+                                        reason: reason as i32,
+                                        message,
+                                    },
+                                )),
+                            }
+                            .encode_to_vec(),
+                        )),
+                    )
+                    .await??;
+                    return Ok(());
+                }
+
                 _ = ping_timer.tick() => {
                     let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
                     let mut buf = vec![];
@@ -201,11 +511,45 @@ impl Server {
                 msg = tokio::time::timeout(RX_TIMEOUT, rx.try_next()) => {
                     match msg?? {
                         Some(tungstenite::Message::Binary(d)) => {
-                            match tango_signaling::proto::signaling::Packet::decode(d.as_slice())?.which {
-                                Some(tango_signaling::proto::signaling::packet::Which::Answer(answer)) => {
-                                    break answer;
-                                },
-                                m => anyhow::bail!("unexpected message: {:?}", m),
+                            match (tango_signaling::proto::signaling::Packet::decode(d.as_slice())?.which, &role) {
+                                (Some(tango_signaling::proto::signaling::packet::Which::Answer(answer)), Role::Answerer(offerer_tx)) => {
+                                    tokio::time::timeout(
+                                        TX_TIMEOUT,
+                                        offerer_tx.lock().await.send(tungstenite::Message::Binary(
+                                            tango_signaling::proto::signaling::Packet {
+                                                which: Some(tango_signaling::proto::signaling::packet::Which::Answer(
+                                                    tango_signaling::proto::signaling::packet::Answer { sdp: answer.sdp },
+                                                )),
+                                            }
+                                            .encode_to_vec(),
+                                        )),
+                                    )
+                                    .await??;
+                                    self.metrics.record_pairing();
+                                }
+                                (Some(tango_signaling::proto::signaling::packet::Which::IceCandidate(cand)), Role::Answerer(_)) if cand.generation == 0 => {
+                                    // The answerer gathered this for its own offer, which it has rolled back since: it's no use to the offerer.
+                                    log::debug!("dropping stale candidate from answerer: {:?}", cand);
+                                }
+                                (Some(tango_signaling::proto::signaling::packet::Which::IceCandidate(cand)), Role::Answerer(offerer_tx)) => {
+                                    if let Err(e) = send_ice_candidate(offerer_tx, cand).await {
+                                        // The offerer may have already connected and hung up.
+                                        log::debug!("failed to forward candidate to offerer: {:?}", e);
+                                    }
+                                }
+                                (Some(tango_signaling::proto::signaling::packet::Which::IceCandidate(cand)), Role::Offerer(offerer_relay)) => {
+                                    let mut offerer_relay = offerer_relay.lock().await;
+                                    if let Some(answerer_tx) = offerer_relay.answerer_tx.as_ref() {
+                                        if let Err(e) = send_ice_candidate(answerer_tx, cand).await {
+                                            log::debug!("failed to forward candidate to answerer: {:?}", e);
+                                        }
+                                    } else if offerer_relay.pending_candidates.len() < MAX_PENDING_CANDIDATES {
+                                        offerer_relay.pending_candidates.push(cand);
+                                    } else {
+                                        anyhow::bail!("too many pending candidates");
+                                    }
+                                }
+                                (m, _) => anyhow::bail!("unexpected message: {:?}", m),
                             }
                         }
                         Some(tungstenite::Message::Pong(_)) => {
@@ -220,29 +564,6 @@ impl Server {
                     }
                 }
             }
-        };
-
-        let offerer_tx = if let Some(offerer_tx) = offerer_tx {
-            offerer_tx
-        } else {
-            anyhow::bail!("unexpected answer from offerer");
-        };
-
-        let mut offerer_tx = offerer_tx.lock().await;
-        tokio::time::timeout(
-            TX_TIMEOUT,
-            offerer_tx.send(tungstenite::Message::Binary(
-                tango_signaling::proto::signaling::Packet {
-                    which: Some(tango_signaling::proto::signaling::packet::Which::Answer(
-                        tango_signaling::proto::signaling::packet::Answer { sdp: answer.sdp },
-                    )),
-                }
-                .encode_to_vec(),
-            )),
-        )
-        .await??;
-        tokio::time::timeout(TX_TIMEOUT, offerer_tx.close()).await??;
-
-        Ok(())
+        }
     }
 }
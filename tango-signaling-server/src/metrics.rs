@@ -0,0 +1,206 @@
+use std::fmt::Write;
+
+const ICECONFIG_LATENCY_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+/// Counters exported at /metrics in the Prometheus text format.
+pub struct Metrics {
+    pairings: std::sync::atomic::AtomicU64,
+    queue_pairings: std::sync::atomic::AtomicU64,
+    aborts: std::sync::Mutex<std::collections::BTreeMap<String, u64>>,
+    expected_protocol_versions: std::sync::atomic::AtomicU64,
+    other_protocol_versions: std::sync::atomic::AtomicU64,
+    iceconfig_latency: std::sync::Mutex<Histogram>,
+    iceconfig_failures: std::sync::atomic::AtomicU64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            pairings: std::sync::atomic::AtomicU64::new(0),
+            queue_pairings: std::sync::atomic::AtomicU64::new(0),
+            aborts: std::sync::Mutex::new(std::collections::BTreeMap::new()),
+            expected_protocol_versions: std::sync::atomic::AtomicU64::new(0),
+            other_protocol_versions: std::sync::atomic::AtomicU64::new(0),
+            iceconfig_latency: std::sync::Mutex::new(Histogram {
+                buckets: vec![0; ICECONFIG_LATENCY_BUCKETS.len()],
+                ..Default::default()
+            }),
+            iceconfig_failures: std::sync::atomic::AtomicU64::new(0),
+        }
+    }
+
+    pub fn record_pairing(&self) {
+        self.pairings.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn record_queue_pairing(&self) {
+        self.queue_pairings.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn record_abort(&self, reason: tango_signaling::proto::signaling::packet::abort::Reason) {
+        *self.aborts.lock().unwrap().entry(format!("{:?}", reason)).or_default() += 1;
+    }
+
+    /// Versions are reported by clients, so anything but the expected one is bucketed together to keep the label set bounded.
+    pub fn record_protocol_version(&self, protocol_version: u32) {
+        if protocol_version == super::EXPECTED_PROTOCOL_VERSION as u32 {
+            &self.expected_protocol_versions
+        } else {
+            &self.other_protocol_versions
+        }
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn record_iceconfig_request(&self, latency: std::time::Duration, ok: bool) {
+        let secs = latency.as_secs_f64();
+        let mut histogram = self.iceconfig_latency.lock().unwrap();
+        for (bucket, le) in histogram.buckets.iter_mut().zip(ICECONFIG_LATENCY_BUCKETS) {
+            if secs <= *le {
+                *bucket += 1;
+            }
+        }
+        histogram.sum += secs;
+        histogram.count += 1;
+        drop(histogram);
+
+        if !ok {
+            self.iceconfig_failures
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    pub fn render(&self, active_sessions: usize, queued_players: usize) -> String {
+        let mut out = String::new();
+
+        writeln!(
+            out,
+            "# HELP tango_signaling_active_sessions Sessions waiting for an answer."
+        )
+        .unwrap();
+        writeln!(out, "# TYPE tango_signaling_active_sessions gauge").unwrap();
+        writeln!(out, "tango_signaling_active_sessions {}", active_sessions).unwrap();
+
+        writeln!(
+            out,
+            "# HELP tango_signaling_queued_players Players waiting in the matchmaking queue."
+        )
+        .unwrap();
+        writeln!(out, "# TYPE tango_signaling_queued_players gauge").unwrap();
+        writeln!(out, "tango_signaling_queued_players {}", queued_players).unwrap();
+
+        writeln!(out, "# HELP tango_signaling_pairings_total Offers that were answered.").unwrap();
+        writeln!(out, "# TYPE tango_signaling_pairings_total counter").unwrap();
+        writeln!(
+            out,
+            "tango_signaling_pairings_total {}",
+            self.pairings.load(std::sync::atomic::Ordering::Relaxed)
+        )
+        .unwrap();
+
+        writeln!(
+            out,
+            "# HELP tango_signaling_queue_pairings_total Players paired by the matchmaking queue."
+        )
+        .unwrap();
+        writeln!(out, "# TYPE tango_signaling_queue_pairings_total counter").unwrap();
+        writeln!(
+            out,
+            "tango_signaling_queue_pairings_total {}",
+            self.queue_pairings.load(std::sync::atomic::Ordering::Relaxed)
+        )
+        .unwrap();
+
+        writeln!(
+            out,
+            "# HELP tango_signaling_aborts_total Aborts sent to clients, by reason."
+        )
+        .unwrap();
+        writeln!(out, "# TYPE tango_signaling_aborts_total counter").unwrap();
+        for (reason, count) in self.aborts.lock().unwrap().iter() {
+            writeln!(out, "tango_signaling_aborts_total{{reason=\"{}\"}} {}", reason, count).unwrap();
+        }
+
+        writeln!(
+            out,
+            "# HELP tango_signaling_protocol_versions_total Connections by client protocol version."
+        )
+        .unwrap();
+        writeln!(out, "# TYPE tango_signaling_protocol_versions_total counter").unwrap();
+        writeln!(
+            out,
+            "tango_signaling_protocol_versions_total{{version=\"{:#x}\"}} {}",
+            super::EXPECTED_PROTOCOL_VERSION,
+            self.expected_protocol_versions
+                .load(std::sync::atomic::Ordering::Relaxed)
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "tango_signaling_protocol_versions_total{{version=\"other\"}} {}",
+            self.other_protocol_versions.load(std::sync::atomic::Ordering::Relaxed)
+        )
+        .unwrap();
+
+        writeln!(
+            out,
+            "# HELP tango_signaling_iceconfig_request_duration_seconds Latency of iceconfig backend requests."
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "# TYPE tango_signaling_iceconfig_request_duration_seconds histogram"
+        )
+        .unwrap();
+        {
+            let histogram = self.iceconfig_latency.lock().unwrap();
+            for (bucket, le) in histogram.buckets.iter().zip(ICECONFIG_LATENCY_BUCKETS) {
+                writeln!(
+                    out,
+                    "tango_signaling_iceconfig_request_duration_seconds_bucket{{le=\"{}\"}} {}",
+                    le, bucket
+                )
+                .unwrap();
+            }
+            writeln!(
+                out,
+                "tango_signaling_iceconfig_request_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+                histogram.count
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "tango_signaling_iceconfig_request_duration_seconds_sum {}",
+                histogram.sum
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "tango_signaling_iceconfig_request_duration_seconds_count {}",
+                histogram.count
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            out,
+            "# HELP tango_signaling_iceconfig_failures_total Iceconfig backend requests that failed or timed out."
+        )
+        .unwrap();
+        writeln!(out, "# TYPE tango_signaling_iceconfig_failures_total counter").unwrap();
+        writeln!(
+            out,
+            "tango_signaling_iceconfig_failures_total {}",
+            self.iceconfig_failures.load(std::sync::atomic::Ordering::Relaxed)
+        )
+        .unwrap();
+
+        out
+    }
+}
@@ -0,0 +1,196 @@
+use byteorder::WriteBytesExt;
+use futures_util::{SinkExt, StreamExt, TryStreamExt};
+use prost::Message;
+use rand::RngCore;
+
+const RX_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+const TX_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+const PING_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+struct Key {
+    protocol_version: u32,
+    netplay_compatibility: String,
+    match_type: (u32, u32),
+}
+
+struct Ticket {
+    session_id_tx: tokio::sync::oneshot::Sender<String>,
+}
+
+/// Pairs up players looking for a random opponent with compatible settings.
+pub struct Server {
+    waiting: tokio::sync::Mutex<std::collections::HashMap<Key, std::collections::VecDeque<Ticket>>>,
+    metrics: std::sync::Arc<crate::metrics::Metrics>,
+}
+
+fn generate_session_id() -> String {
+    let mut buf = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut buf);
+    format!("queue-{}", buf.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+}
+
+impl Server {
+    pub fn new(metrics: std::sync::Arc<crate::metrics::Metrics>) -> Self {
+        Self {
+            waiting: tokio::sync::Mutex::new(std::collections::HashMap::new()),
+            metrics,
+        }
+    }
+
+    pub async fn queued_players(&self) -> usize {
+        self.waiting
+            .lock()
+            .await
+            .values()
+            .flat_map(|tickets| tickets.iter())
+            .filter(|ticket| !ticket.session_id_tx.is_closed())
+            .count()
+    }
+
+    /// Either pairs with a waiting player immediately, returning the shared session ID, or joins the queue.
+    async fn enqueue(&self, key: Key) -> Result<String, tokio::sync::oneshot::Receiver<String>> {
+        let mut waiting = self.waiting.lock().await;
+        let tickets = waiting.entry(key.clone()).or_default();
+
+        // Tickets whose players have gone away are only cleaned up here, when someone else is looking at the queue.
+        tickets.retain(|ticket| !ticket.session_id_tx.is_closed());
+
+        let mut paired = None;
+        while let Some(ticket) = tickets.pop_front() {
+            let session_id = generate_session_id();
+            if ticket.session_id_tx.send(session_id.clone()).is_ok() {
+                paired = Some(session_id);
+                break;
+            }
+        }
+
+        if let Some(session_id) = paired {
+            self.metrics.record_queue_pairing();
+            if tickets.is_empty() {
+                waiting.remove(&key);
+            }
+            return Ok(session_id);
+        }
+
+        let (session_id_tx, session_id_rx) = tokio::sync::oneshot::channel();
+        tickets.push_back(Ticket { session_id_tx });
+        Err(session_id_rx)
+    }
+
+    pub async fn handle_stream(
+        &self,
+        ws: hyper_tungstenite::WebSocketStream<hyper::upgrade::Upgraded>,
+        remote_ip: std::net::IpAddr,
+    ) -> anyhow::Result<()> {
+        let (mut tx, mut rx) = ws.split();
+
+        let request = match tokio::time::timeout(RX_TIMEOUT, rx.try_next())
+            .await??
+            .ok_or_else(|| anyhow::format_err!("unexpected end of stream"))?
+        {
+            tungstenite::Message::Binary(d) => tango_signaling::proto::signaling::QueueRequest::decode(d.as_slice())?,
+            m => {
+                anyhow::bail!("unexpected message: {:?}", m);
+            }
+        };
+
+        self.metrics.record_protocol_version(request.protocol_version);
+
+        if request.protocol_version as u8 != super::EXPECTED_PROTOCOL_VERSION {
+            let reason = if (request.protocol_version as u8) < super::EXPECTED_PROTOCOL_VERSION {
+                tango_signaling::proto::signaling::packet::abort::Reason::ProtocolVersionTooOld
+            } else {
+                tango_signaling::proto::signaling::packet::abort::Reason::ProtocolVersionTooNew
+            };
+            self.metrics.record_abort(reason);
+            tokio::time::timeout(
+                TX_TIMEOUT,
+                tx.send(tungstenite::Message::Binary(
+                    tango_signaling::proto::signaling::QueueResponse {
+                        which: Some(tango_signaling::proto::signaling::queue_response::Which::Abort(
+                            tango_signaling::proto::signaling::packet::Abort {
+                                reason: reason as i32,
+                                message: None,
+                            },
+                        )),
+                    }
+                    .encode_to_vec(),
+                )),
+            )
+            .await??;
+            return Ok(());
+        }
+
+        log::info!(
+            "{} joined queue: {} ({:?} v{:?}), compatibility = {}, match type = ({}, {})",
+            remote_ip,
+            request.game_family,
+            request.patch_name,
+            request.patch_version,
+            request.netplay_compatibility,
+            request.match_type,
+            request.match_subtype
+        );
+
+        let key = Key {
+            protocol_version: request.protocol_version,
+            netplay_compatibility: request.netplay_compatibility,
+            match_type: (request.match_type, request.match_subtype),
+        };
+
+        let session_id = match self.enqueue(key).await {
+            Ok(session_id) => session_id,
+            Err(mut session_id_rx) => {
+                let mut ping_timer = tokio::time::interval(PING_INTERVAL);
+                loop {
+                    tokio::select! {
+                        session_id = &mut session_id_rx => {
+                            break session_id?;
+                        }
+
+                        _ = ping_timer.tick() => {
+                            let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+                            let mut buf = vec![];
+                            buf.write_u64::<byteorder::LittleEndian>(now.as_millis() as u64)?;
+                            tokio::time::timeout(TX_TIMEOUT, tx.send(tungstenite::Message::Ping(buf))).await??;
+                        }
+
+                        msg = tokio::time::timeout(RX_TIMEOUT, rx.try_next()) => {
+                            match msg?? {
+                                Some(tungstenite::Message::Pong(_)) => {
+                                    continue;
+                                }
+                                Some(tungstenite::Message::Close(_)) | None => {
+                                    log::info!("{} left queue", remote_ip);
+                                    return Ok(());
+                                }
+                                m => {
+                                    anyhow::bail!("unexpected message: {:?}", m);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        };
+
+        log::info!("{} paired in queue: {}", remote_ip, session_id);
+
+        tokio::time::timeout(
+            TX_TIMEOUT,
+            tx.send(tungstenite::Message::Binary(
+                tango_signaling::proto::signaling::QueueResponse {
+                    which: Some(tango_signaling::proto::signaling::queue_response::Which::SessionId(
+                        session_id,
+                    )),
+                }
+                .encode_to_vec(),
+            )),
+        )
+        .await??;
+        tokio::time::timeout(TX_TIMEOUT, tx.close()).await??;
+
+        Ok(())
+    }
+}
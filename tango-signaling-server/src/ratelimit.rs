@@ -0,0 +1,93 @@
+const REQUEST_WINDOW: std::time::Duration = std::time::Duration::from_secs(60);
+
+#[derive(Default)]
+struct Client {
+    connections: usize,
+    recent_requests: std::collections::VecDeque<std::time::Instant>,
+}
+
+impl Client {
+    fn forget_old_requests(&mut self, now: std::time::Instant) {
+        while let Some(t) = self.recent_requests.front() {
+            if now.duration_since(*t) < REQUEST_WINDOW {
+                break;
+            }
+            self.recent_requests.pop_front();
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.connections == 0 && self.recent_requests.is_empty()
+    }
+}
+
+/// Tracks how many requests and open connections each remote IP has.
+pub struct Limiter {
+    max_connections_per_ip: usize,
+    max_requests_per_minute_per_ip: usize,
+    clients: std::sync::Mutex<std::collections::HashMap<std::net::IpAddr, Client>>,
+}
+
+impl Limiter {
+    pub fn new(max_connections_per_ip: usize, max_requests_per_minute_per_ip: usize) -> Self {
+        Self {
+            max_connections_per_ip,
+            max_requests_per_minute_per_ip,
+            clients: std::sync::Mutex::new(std::collections::HashMap::new()),
+        }
+    }
+
+    /// Records a request from the given IP, returning false if it has made too many recently.
+    pub fn check_request(&self, ip: std::net::IpAddr) -> bool {
+        let now = std::time::Instant::now();
+        let mut clients = self.clients.lock().unwrap();
+        let client = clients.entry(ip).or_default();
+        client.forget_old_requests(now);
+        if client.recent_requests.len() >= self.max_requests_per_minute_per_ip {
+            return false;
+        }
+        client.recent_requests.push_back(now);
+        true
+    }
+
+    /// Reserves a connection slot for the given IP, which is released when the returned guard is dropped.
+    pub fn acquire_connection(self: &std::sync::Arc<Self>, ip: std::net::IpAddr) -> Option<ConnectionGuard> {
+        let mut clients = self.clients.lock().unwrap();
+        let client = clients.entry(ip).or_default();
+        if client.connections >= self.max_connections_per_ip {
+            return None;
+        }
+        client.connections += 1;
+        Some(ConnectionGuard {
+            limiter: self.clone(),
+            ip,
+        })
+    }
+
+    /// Drops bookkeeping for IPs that have no open connections and no recent requests.
+    pub fn prune(&self) {
+        let now = std::time::Instant::now();
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain(|_, client| {
+            client.forget_old_requests(now);
+            !client.is_idle()
+        });
+    }
+}
+
+pub struct ConnectionGuard {
+    limiter: std::sync::Arc<Limiter>,
+    ip: std::net::IpAddr,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let mut clients = self.limiter.clients.lock().unwrap();
+        if let Some(client) = clients.get_mut(&self.ip) {
+            client.connections -= 1;
+            if client.is_idle() {
+                clients.remove(&self.ip);
+            }
+        }
+    }
+}
@@ -5,6 +5,8 @@ use prost::Message;
 use tokio_tungstenite::tungstenite::client::IntoClientRequest;
 
 pub type AbortReason = crate::proto::signaling::packet::abort::Reason;
+pub type QueueRequest = crate::proto::signaling::QueueRequest;
+pub type Room = crate::proto::signaling::Room;
 
 async fn create_data_channel(
     rtc_config: datachannel_wrapper::RtcConfig,
@@ -13,6 +15,7 @@ async fn create_data_channel(
         datachannel_wrapper::DataChannel,
         tokio::sync::mpsc::Receiver<datachannel_wrapper::PeerConnectionEvent>,
         datachannel_wrapper::PeerConnection,
+        Vec<datachannel_wrapper::IceCandidate>,
     ),
     std::io::Error,
 > {
@@ -32,16 +35,202 @@ async fn create_data_channel(
             .stream(0),
     )?;
 
+    // Candidates are trickled to the other side as they're gathered, so we only need to wait for the offer itself.
+    let mut early_candidates = vec![];
     loop {
-        if let Some(datachannel_wrapper::PeerConnectionEvent::GatheringStateChange(
-            datachannel_wrapper::GatheringState::Complete,
-        )) = event_rx.recv().await
-        {
-            break;
+        match event_rx.recv().await {
+            Some(datachannel_wrapper::PeerConnectionEvent::SessionDescription(_)) => {
+                break;
+            }
+            Some(datachannel_wrapper::PeerConnectionEvent::IceCandidate(cand)) => {
+                early_candidates.push(cand);
+            }
+            Some(_) => {}
+            None => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "peer connection closed before offer was created",
+                ));
+            }
         }
     }
 
-    Ok((dc, event_rx, peer_conn))
+    Ok((dc, event_rx, peer_conn, early_candidates))
+}
+
+fn make_ice_candidate_message(
+    cand: crate::proto::signaling::packet::IceCandidate,
+) -> tokio_tungstenite::tungstenite::Message {
+    tokio_tungstenite::tungstenite::Message::Binary(
+        crate::proto::signaling::Packet {
+            which: Some(crate::proto::signaling::packet::Which::IceCandidate(cand)),
+        }
+        .encode_to_vec(),
+    )
+}
+
+type SignalingStream = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+async fn open_signaling_stream(
+    addr: &str,
+    session_id: &str,
+    protocol_version: u32,
+) -> Result<(SignalingStream, crate::proto::signaling::packet::Hello), Error> {
+    let mut url = url::Url::parse(addr)?;
+    url.set_query(Some(
+        &url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("session_id", session_id)
+            .finish(),
+    ));
+
+    let mut req = url.to_string().into_client_request()?;
+    req.headers_mut().append(
+        "User-Agent",
+        tokio_tungstenite::tungstenite::http::HeaderValue::from_str(&format!(
+            "tango-signaling/{}",
+            env!("CARGO_PKG_VERSION")
+        ))
+        .map_err(|e| tokio_tungstenite::tungstenite::http::Error::from(e))?,
+    );
+    req.headers_mut().append(
+        "X-Tango-Protocol-Version",
+        tokio_tungstenite::tungstenite::http::HeaderValue::from_str(&format!("{:x}", protocol_version))
+            .map_err(|e| tokio_tungstenite::tungstenite::http::Error::from(e))?,
+    );
+    let mut signaling_stream = match tokio_tungstenite::connect_async(req).await {
+        Ok((signaling_stream, _)) => signaling_stream,
+        Err(tokio_tungstenite::tungstenite::Error::Http(e)) if is_abort_status(e.status()) => {
+            let abort = crate::proto::signaling::packet::Abort::decode(
+                e.body().as_ref().map(|b| b.as_bytes()).unwrap_or_default(),
+            )?;
+            return Err(abort_error(abort));
+        }
+        Err(e) => {
+            return Err(e.into());
+        }
+    };
+
+    let raw = if let Some(raw) = signaling_stream.try_next().await? {
+        raw
+    } else {
+        return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "stream ended early").into());
+    };
+
+    let packet = if let tokio_tungstenite::tungstenite::Message::Binary(d) = raw {
+        crate::proto::signaling::Packet::decode(d.as_slice())?
+    } else {
+        return Err(Error::InvalidPacket(raw));
+    };
+
+    let hello = if let Some(crate::proto::signaling::packet::Which::Hello(hello)) = packet.which {
+        hello
+    } else {
+        return Err(Error::UnexpectedPacket(packet));
+    };
+
+    Ok((signaling_stream, hello))
+}
+
+async fn send_start(
+    signaling_stream: &mut SignalingStream,
+    protocol_version: u32,
+    offer_sdp: &str,
+    room: Option<Room>,
+    candidates: &[crate::proto::signaling::packet::IceCandidate],
+) -> Result<(), Error> {
+    signaling_stream
+        .send(tokio_tungstenite::tungstenite::Message::Binary(
+            crate::proto::signaling::Packet {
+                which: Some(crate::proto::signaling::packet::Which::Start(
+                    crate::proto::signaling::packet::Start {
+                        protocol_version,
+                        offer_sdp: offer_sdp.to_string(),
+                        room,
+                    },
+                )),
+            }
+            .encode_to_vec(),
+        ))
+        .await?;
+
+    for cand in candidates {
+        signaling_stream.send(make_ice_candidate_message(cand.clone())).await?;
+    }
+
+    Ok(())
+}
+
+const INITIAL_RECONNECT_BACKOFF: std::time::Duration = std::time::Duration::from_secs(1);
+const MAX_RECONNECT_BACKOFF: std::time::Duration = std::time::Duration::from_secs(30);
+const MAX_RECONNECT_ATTEMPTS: u32 = 8;
+
+/// Reopens the signaling stream after it drops while we are still waiting for an opponent, re-registering the same session ID with the same offer.
+async fn reconnect(
+    addr: &str,
+    session_id: &str,
+    protocol_version: u32,
+    offer_sdp: &str,
+    room: Option<Room>,
+    candidates: &[crate::proto::signaling::packet::IceCandidate],
+    state_tx: &tokio::sync::watch::Sender<ConnectingState>,
+) -> Result<SignalingStream, Error> {
+    let mut backoff = INITIAL_RECONNECT_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        state_tx.send_replace(ConnectingState::Reconnecting { attempt });
+        tokio::time::sleep(backoff).await;
+
+        let r = async {
+            // The ICE servers in the new hello are ignored: the peer connection was already set up with the ones from the first one.
+            let (mut signaling_stream, _) = open_signaling_stream(addr, session_id, protocol_version).await?;
+            send_start(
+                &mut signaling_stream,
+                protocol_version,
+                offer_sdp,
+                room.clone(),
+                candidates,
+            )
+            .await?;
+            Ok::<_, Error>(signaling_stream)
+        }
+        .await;
+
+        match r {
+            Ok(signaling_stream) => {
+                log::info!("reconnected to signaling server after {} attempt(s)", attempt);
+                state_tx.send_replace(ConnectingState::Waiting);
+                return Ok(signaling_stream);
+            }
+            Err(e @ (Error::ServerAbort(_) | Error::ServerMaintenance(_))) => {
+                return Err(e);
+            }
+            Err(e) if attempt >= MAX_RECONNECT_ATTEMPTS => {
+                return Err(e);
+            }
+            Err(e) => {
+                log::warn!("failed to reconnect to signaling server (attempt {}): {:?}", attempt, e);
+                backoff = std::cmp::min(backoff * 2, MAX_RECONNECT_BACKOFF);
+            }
+        }
+    }
+}
+
+fn is_abort_status(status: http::StatusCode) -> bool {
+    matches!(
+        status,
+        http::StatusCode::BAD_REQUEST
+            | http::StatusCode::FORBIDDEN
+            | http::StatusCode::TOO_MANY_REQUESTS
+            | http::StatusCode::SERVICE_UNAVAILABLE
+    )
+}
+
+fn abort_error(abort: crate::proto::signaling::packet::Abort) -> Error {
+    match AbortReason::from_i32(abort.reason).unwrap_or_default() {
+        AbortReason::Maintenance => Error::ServerMaintenance(abort.message.unwrap_or_default()),
+        reason => Error::ServerAbort(reason),
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -49,6 +238,9 @@ pub enum Error {
     #[error("signaling abort: {0:?}")]
     ServerAbort(AbortReason),
 
+    #[error("signaling server is under maintenance: {0}")]
+    ServerMaintenance(String),
+
     #[error("tungstenite: {0:?}")]
     Tungstenite(#[from] tokio_tungstenite::tungstenite::Error),
 
@@ -83,25 +275,10 @@ pub enum Error {
     PeerConnectionClosed,
 }
 
-pub struct Connecting {
-    fut: futures_util::future::BoxFuture<
-        'static,
-        Result<(datachannel_wrapper::DataChannel, datachannel_wrapper::PeerConnection), Error>,
-    >,
-}
-
-pub async fn connect(
-    addr: &str,
-    session_id: &str,
-    use_relay: Option<bool>,
-    protocol_version: u32,
-) -> Result<Connecting, Error> {
+/// Waits on the server's queue until an opponent with compatible settings is found, returning the session ID to connect with.
+pub async fn queue(addr: &str, request: QueueRequest) -> Result<String, Error> {
     let mut url = url::Url::parse(addr)?;
-    url.set_query(Some(
-        &url::form_urlencoded::Serializer::new(String::new())
-            .append_pair("session_id", session_id)
-            .finish(),
-    ));
+    url.set_path("/queue");
 
     let mut req = url.to_string().into_client_request()?;
     req.headers_mut().append(
@@ -112,44 +289,83 @@ pub async fn connect(
         ))
         .map_err(|e| tokio_tungstenite::tungstenite::http::Error::from(e))?,
     );
-    req.headers_mut().append(
-        "X-Tango-Protocol-Version",
-        tokio_tungstenite::tungstenite::http::HeaderValue::from_str(&format!("{:x}", protocol_version))
-            .map_err(|e| tokio_tungstenite::tungstenite::http::Error::from(e))?,
-    );
-    let mut signaling_stream = match tokio_tungstenite::connect_async(req).await {
-        Ok((signaling_stream, _)) => signaling_stream,
-        Err(tokio_tungstenite::tungstenite::Error::Http(e)) if e.status() == http::StatusCode::BAD_REQUEST => {
+    let mut queue_stream = match tokio_tungstenite::connect_async(req).await {
+        Ok((queue_stream, _)) => queue_stream,
+        Err(tokio_tungstenite::tungstenite::Error::Http(e)) if is_abort_status(e.status()) => {
             let abort = crate::proto::signaling::packet::Abort::decode(
                 e.body().as_ref().map(|b| b.as_bytes()).unwrap_or_default(),
             )?;
-            return Err(Error::ServerAbort(
-                AbortReason::from_i32(abort.reason).unwrap_or_default(),
-            ));
+            return Err(abort_error(abort));
         }
         Err(e) => {
             return Err(e.into());
         }
     };
 
-    let raw = if let Some(raw) = signaling_stream.try_next().await? {
-        raw
-    } else {
-        return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "stream ended early").into());
-    };
+    queue_stream
+        .send(tokio_tungstenite::tungstenite::Message::Binary(request.encode_to_vec()))
+        .await?;
 
-    let packet = if let tokio_tungstenite::tungstenite::Message::Binary(d) = raw {
-        crate::proto::signaling::Packet::decode(d.as_slice())?
-    } else {
-        return Err(Error::InvalidPacket(raw));
-    };
+    loop {
+        let raw = if let Some(raw) = queue_stream.try_next().await? {
+            raw
+        } else {
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "stream ended early").into());
+        };
+
+        let response = match raw {
+            tokio_tungstenite::tungstenite::Message::Binary(d) => {
+                crate::proto::signaling::QueueResponse::decode(d.as_slice())?
+            }
+            tokio_tungstenite::tungstenite::Message::Ping(_) | tokio_tungstenite::tungstenite::Message::Pong(_) => {
+                continue;
+            }
+            raw => {
+                return Err(Error::InvalidPacket(raw));
+            }
+        };
 
-    let hello = if let Some(crate::proto::signaling::packet::Which::Hello(hello)) = packet.which {
-        hello
-    } else {
-        return Err(Error::UnexpectedPacket(packet));
-    };
+        return match response.which {
+            Some(crate::proto::signaling::queue_response::Which::SessionId(session_id)) => {
+                log::info!("found opponent in queue: {}", session_id);
+                let _ = queue_stream.close(None).await;
+                Ok(session_id)
+            }
+            Some(crate::proto::signaling::queue_response::Which::Abort(abort)) => Err(abort_error(abort)),
+            None => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "empty queue response").into()),
+        };
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectingState {
+    Waiting,
+    Reconnecting { attempt: u32 },
+}
+
+pub struct Connecting {
+    fut: futures_util::future::BoxFuture<
+        'static,
+        Result<(datachannel_wrapper::DataChannel, datachannel_wrapper::PeerConnection), Error>,
+    >,
+    state_rx: tokio::sync::watch::Receiver<ConnectingState>,
+}
+
+impl Connecting {
+    /// Changes whenever the signaling stream drops and is reconnected while waiting for an opponent.
+    pub fn state(&self) -> tokio::sync::watch::Receiver<ConnectingState> {
+        self.state_rx.clone()
+    }
+}
 
+pub async fn connect(
+    addr: &str,
+    session_id: &str,
+    use_relay: Option<bool>,
+    protocol_version: u32,
+    room: Option<Room>,
+) -> Result<Connecting, Error> {
+    let (mut signaling_stream, hello) = open_signaling_stream(addr, session_id, protocol_version).await?;
     log::info!("hello received from signaling stream: {:?}", hello);
 
     let mut rtc_config = datachannel_wrapper::RtcConfig::new(
@@ -198,97 +414,199 @@ pub async fn connect(
     if use_relay == Some(true) {
         rtc_config.ice_transport_policy = datachannel_wrapper::TransportPolicy::Relay;
     }
-    let (dc, mut event_rx, mut peer_conn) = create_data_channel(rtc_config).await?;
-
-    signaling_stream
-        .send(tokio_tungstenite::tungstenite::Message::Binary(
-            crate::proto::signaling::Packet {
-                which: Some(crate::proto::signaling::packet::Which::Start(
-                    crate::proto::signaling::packet::Start {
-                        protocol_version,
-                        offer_sdp: peer_conn.local_description().unwrap().sdp.to_string(),
-                    },
-                )),
-            }
-            .encode_to_vec(),
-        ))
-        .await?;
+    let (dc, mut event_rx, mut peer_conn, early_candidates) = create_data_channel(rtc_config).await?;
+
+    let offer_sdp = peer_conn.local_description().unwrap().sdp.to_string();
+    // Everything we've sent to the server so far, so it can be sent again if we have to reconnect.
+    let mut local_candidates = early_candidates
+        .into_iter()
+        .map(|cand| crate::proto::signaling::packet::IceCandidate {
+            candidate: cand.candidate,
+            mid: cand.mid,
+            generation: 0,
+        })
+        .collect::<Vec<_>>();
+    send_start(
+        &mut signaling_stream,
+        protocol_version,
+        &offer_sdp,
+        room.clone(),
+        &local_candidates,
+    )
+    .await?;
+
+    let (state_tx, state_rx) = tokio::sync::watch::channel(ConnectingState::Waiting);
+    let addr = addr.to_string();
+    let session_id = session_id.to_string();
 
     Ok(Connecting {
+        state_rx,
         fut: Box::pin(async move {
+            const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+            // Once both descriptions are exchanged the signaling stream is only used for trickling candidates, so if the server hangs up on us we carry on with whatever candidates we already have.
+            let mut signaling_open = true;
+            let mut have_remote_description = false;
+            let mut generation = 0;
+
             loop {
-                const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
-                let raw = if let Some(raw) = tokio::time::timeout(TIMEOUT, signaling_stream.try_next())
-                    .await
-                    .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out"))??
-                {
-                    raw
-                } else {
-                    return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "stream ended early").into());
-                };
-
-                let packet = match raw {
-                    tokio_tungstenite::tungstenite::Message::Binary(d) => {
-                        crate::proto::signaling::Packet::decode(d.as_slice())?
-                    }
-                    tokio_tungstenite::tungstenite::Message::Ping(_) => {
-                        // Note that upon receiving a ping message, tungstenite cues a pong reply automatically.
-                        // When you call either read_message, write_message or write_pending next it will try to send that pong out if the underlying connection can take more data.
-                        // This means you should not respond to ping frames manually.
-                        continue;
-                    }
-                    _ => {
-                        return Err(Error::InvalidPacket(raw));
-                    }
-                };
+                tokio::select! {
+                    raw = tokio::time::timeout(TIMEOUT, signaling_stream.try_next()), if signaling_open => {
+                        let raw = match raw {
+                            Ok(Ok(Some(raw))) => raw,
+                            _ if have_remote_description => {
+                                log::info!("signaling stream ended, continuing with candidates we already have");
+                                signaling_open = false;
+                                continue;
+                            }
+                            r => {
+                                log::warn!("signaling stream dropped while waiting for an opponent, reconnecting: {:?}", r);
+                                let _ = signaling_stream.close(None).await;
+                                signaling_stream = reconnect(
+                                    &addr,
+                                    &session_id,
+                                    protocol_version,
+                                    &offer_sdp,
+                                    room.clone(),
+                                    &local_candidates,
+                                    &state_tx,
+                                )
+                                .await?;
+                                continue;
+                            }
+                        };
 
-                match &packet.which {
-                    Some(crate::proto::signaling::packet::Which::Abort(abort)) => {
-                        return Err(Error::ServerAbort(
-                            AbortReason::from_i32(abort.reason).unwrap_or_default(),
-                        ))
+                        let packet = match raw {
+                            tokio_tungstenite::tungstenite::Message::Binary(d) => {
+                                crate::proto::signaling::Packet::decode(d.as_slice())?
+                            }
+                            tokio_tungstenite::tungstenite::Message::Ping(_) => {
+                                // Note that upon receiving a ping message, tungstenite cues a pong reply automatically.
+                                // When you call either read_message, write_message or write_pending next it will try to send that pong out if the underlying connection can take more data.
+                                // This means you should not respond to ping frames manually.
+                                continue;
+                            }
+                            tokio_tungstenite::tungstenite::Message::Close(_) => {
+                                // The stream ends right after this, which is handled above.
+                                continue;
+                            }
+                            _ => {
+                                return Err(Error::InvalidPacket(raw));
+                            }
+                        };
+
+                        match &packet.which {
+                            Some(crate::proto::signaling::packet::Which::Abort(abort)) => {
+                                return Err(abort_error(abort.clone()))
+                            }
+                            Some(crate::proto::signaling::packet::Which::Offer(offer)) if !have_remote_description && offer.sdp == offer_sdp => {
+                                // The server hadn't noticed our old connection was gone yet, so it paired us with ourselves.
+                                log::warn!("received our own offer back from a stale session, reconnecting");
+                                // Hang up first, so the server doesn't see this connection and the new one at the same time.
+                                let _ = signaling_stream.close(None).await;
+                                signaling_stream = reconnect(
+                                    &addr,
+                                    &session_id,
+                                    protocol_version,
+                                    &offer_sdp,
+                                    room.clone(),
+                                    &local_candidates,
+                                    &state_tx,
+                                )
+                                .await?;
+                            }
+                            Some(crate::proto::signaling::packet::Which::Offer(offer)) if !have_remote_description => {
+                                log::info!("received an offer, this is the polite side. rolling back our local description and switching to answer");
+
+                                peer_conn.set_local_description(datachannel_wrapper::SdpType::Rollback)?;
+                                generation += 1;
+                                peer_conn.set_remote_description(datachannel_wrapper::SessionDescription {
+                                    sdp_type: datachannel_wrapper::SdpType::Offer,
+                                    sdp: datachannel_wrapper::sdp::parse_sdp(&offer.sdp.to_string(), false)?,
+                                })?;
+                                have_remote_description = true;
+
+                                let local_description = peer_conn.local_description().unwrap();
+                                signaling_stream
+                                    .send(tokio_tungstenite::tungstenite::Message::Binary(
+                                        crate::proto::signaling::Packet {
+                                            which: Some(crate::proto::signaling::packet::Which::Answer(
+                                                crate::proto::signaling::packet::Answer {
+                                                    sdp: local_description.sdp.to_string(),
+                                                },
+                                            )),
+                                        }
+                                        .encode_to_vec(),
+                                    ))
+                                    .await?;
+                                log::info!("sent answer to impolite side");
+                            }
+                            Some(crate::proto::signaling::packet::Which::Answer(answer)) if !have_remote_description => {
+                                log::info!("received an answer, this is the impolite side");
+
+                                peer_conn.set_remote_description(datachannel_wrapper::SessionDescription {
+                                    sdp_type: datachannel_wrapper::SdpType::Answer,
+                                    sdp: datachannel_wrapper::sdp::parse_sdp(&answer.sdp, false)?,
+                                })?;
+                                have_remote_description = true;
+                            }
+                            Some(crate::proto::signaling::packet::Which::IceCandidate(cand)) => {
+                                log::debug!("received remote candidate: {:?}", cand);
+                                peer_conn.add_remote_candidate(datachannel_wrapper::IceCandidate {
+                                    candidate: cand.candidate.clone(),
+                                    mid: cand.mid.clone(),
+                                })?;
+                            }
+                            _ => {
+                                return Err(Error::UnexpectedPacket(packet));
+                            }
+                        }
                     }
-                    Some(crate::proto::signaling::packet::Which::Offer(offer)) => {
-                        log::info!("received an offer, this is the polite side. rolling back our local description and switching to answer");
-
-                        peer_conn.set_local_description(datachannel_wrapper::SdpType::Rollback)?;
-                        peer_conn.set_remote_description(datachannel_wrapper::SessionDescription {
-                            sdp_type: datachannel_wrapper::SdpType::Offer,
-                            sdp: datachannel_wrapper::sdp::parse_sdp(&offer.sdp.to_string(), false)?,
-                        })?;
-
-                        let local_description = peer_conn.local_description().unwrap();
-                        signaling_stream
-                            .send(tokio_tungstenite::tungstenite::Message::Binary(
-                                crate::proto::signaling::Packet {
-                                    which: Some(crate::proto::signaling::packet::Which::Answer(
-                                        crate::proto::signaling::packet::Answer {
-                                            sdp: local_description.sdp.to_string(),
-                                        },
-                                    )),
+
+                    signal = event_rx.recv() => {
+                        match signal {
+                            Some(datachannel_wrapper::PeerConnectionEvent::IceCandidate(cand)) => {
+                                log::debug!("sending local candidate: {:?}", cand);
+                                let cand = crate::proto::signaling::packet::IceCandidate {
+                                    candidate: cand.candidate,
+                                    mid: cand.mid,
+                                    generation,
+                                };
+                                if !have_remote_description {
+                                    local_candidates.push(cand.clone());
                                 }
-                                .encode_to_vec(),
-                            ))
-                            .await?;
-                        log::info!("sent answer to impolite side");
-                        break;
-                    }
-                    Some(crate::proto::signaling::packet::Which::Answer(answer)) => {
-                        log::info!("received an answer, this is the impolite side");
-
-                        peer_conn.set_remote_description(datachannel_wrapper::SessionDescription {
-                            sdp_type: datachannel_wrapper::SdpType::Answer,
-                            sdp: datachannel_wrapper::sdp::parse_sdp(&answer.sdp, false)?,
-                        })?;
-                        break;
-                    }
-                    _ => {
-                        return Err(Error::UnexpectedPacket(packet));
+                                if signaling_open {
+                                    // If this fails, the read side notices shortly and reconnects, resending this candidate with the rest.
+                                    let _ = signaling_stream.send(make_ice_candidate_message(cand)).await;
+                                }
+                            }
+                            Some(datachannel_wrapper::PeerConnectionEvent::ConnectionStateChange(c)) => match c {
+                                datachannel_wrapper::ConnectionState::Connected => {
+                                    break;
+                                }
+                                datachannel_wrapper::ConnectionState::Disconnected => {
+                                    return Err(Error::PeerConnectionDisconnected);
+                                }
+                                datachannel_wrapper::ConnectionState::Failed => {
+                                    return Err(Error::PeerConnectionFailed);
+                                }
+                                datachannel_wrapper::ConnectionState::Closed => {
+                                    return Err(Error::PeerConnectionClosed);
+                                }
+                                _ => {}
+                            },
+                            Some(_) => {}
+                            None => {
+                                return Err(Error::PeerConnectionClosed);
+                            }
+                        }
                     }
                 }
             }
 
-            signaling_stream.close(None).await?;
+            if signaling_open {
+                let _ = signaling_stream.close(None).await;
+            }
 
             log::debug!(
                 "local sdp (type = {:?}): {}",
@@ -301,28 +619,6 @@ pub async fn connect(
                 peer_conn.remote_description().expect("remote sdp").sdp
             );
 
-            loop {
-                let signal = event_rx.recv().await.unwrap();
-
-                if let datachannel_wrapper::PeerConnectionEvent::ConnectionStateChange(c) = signal {
-                    match c {
-                        datachannel_wrapper::ConnectionState::Connected => {
-                            break;
-                        }
-                        datachannel_wrapper::ConnectionState::Disconnected => {
-                            return Err(Error::PeerConnectionDisconnected);
-                        }
-                        datachannel_wrapper::ConnectionState::Failed => {
-                            return Err(Error::PeerConnectionFailed);
-                        }
-                        datachannel_wrapper::ConnectionState::Closed => {
-                            return Err(Error::PeerConnectionClosed);
-                        }
-                        _ => {}
-                    }
-                }
-            }
-
             Ok((dc, peer_conn))
         }),
     })
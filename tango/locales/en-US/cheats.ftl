@@ -0,0 +1,7 @@
+cheats = Cheats
+cheats-name = Name
+cheats-add = Add cheat
+cheats-remove = Remove
+cheats-format-raw = Raw (address:value)
+cheats-format-gameshark = GameShark v1/v2
+cheats-format-codebreaker = CodeBreaker
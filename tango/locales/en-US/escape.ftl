@@ -1,2 +1,14 @@
 escape-settings = Settings
 escape-end-game = End game
+escape-cheats = Cheats
+escape-save-states = Save states
+escape-save-state-slot = Slot { $slot }
+escape-save-state-empty = Empty
+escape-save-state-save = Save
+escape-save-state-load = Load
+
+save-state-slot = Save state slot { $slot }
+save-state-saved = Saved state to slot { $slot }
+save-state-loaded = Loaded state from slot { $slot }
+save-state-save-failed = Couldn't save state to slot { $slot }
+save-state-load-failed = Couldn't load state from slot { $slot }
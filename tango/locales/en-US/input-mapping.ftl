@@ -11,6 +11,11 @@ input-button-r = R
 input-button-start = START
 input-button-select = SELECT
 input-button-speed-change = Speed change
+input-button-rewind = Rewind (single player)
+input-button-save-state = Save state
+input-button-load-state = Load state
+input-button-prev-state-slot = Previous state slot
+input-button-next-state-slot = Next state slot
 input-button-menu = Menu
 
 physical-input-button-dpup = DPad ⬆
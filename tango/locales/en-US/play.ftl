@@ -14,13 +14,20 @@ play-cancel = Cancel
 play-details-game = Game
     .unrecognized = Unknown
 play-details-match-type = Match type
+play-details-best-of = Set
+    .unlimited = Unlimited
+    .format = Best of {$n}
+play-details-lockstep = Lockstep
+    .description = Wait for the opponent's input instead of predicting it and rolling back. Uses much less CPU, but the game will stall whenever the connection is slower than the input delay.
 play-details-reveal-setup = Reveal setup
 play-details-input-delay = Input delay
     .suggest = Suggest
 
 play-connection-task-starting = Starting connection...
+play-connection-task-queueing = Looking for an opponent...
 play-connection-task-signaling = Connecting to matchmaking server...
 play-connection-task-waiting = Waiting for opponent...
+play-connection-task-reconnecting = Lost connection to matchmaking server, reconnecting (attempt { $attempt })...
 
 select-save = Select save
     .select = Select
@@ -51,6 +58,9 @@ lobby-issue-no-remote-patches = The opponent does not have any copy of the patch
 lobby-issue-unrecognized-game = The opponent selected an unrecognized game.
 lobby-issue-incompatible = Game is not compatible with the opponent's.
 lobby-issue-match-type-mismatch = Match type does not match the opponent's.
+lobby-issue-best-of-mismatch = Set length does not match the opponent's.
+lobby-issue-lockstep-mismatch = Lockstep setting does not match the opponent's.
+lobby-issue-lockstep-unsupported = Lockstep is not supported for the selected games.
 lobby-issue-no-local-selection = You have not selected a game.
 lobby-issue-no-remote-selection = The opponent has not selected a game.
 
@@ -62,7 +72,33 @@ connection-error-remote-protocol-version-too-old = Unable to connect to the othe
 connection-error-remote-protocol-version-too-new = The other player is using a newer version of Tango. Please update.
 connection-error-protocol-version-too-old = Your version of Tango is too old to connect to the matchmaking server. Please update.
 connection-error-eof = The other player disconnected.
+connection-error-rate-limited = The matchmaking server is receiving too many connections from you. Please wait a bit and try again.
+connection-error-server-full = The matchmaking server is too busy right now. Please try again later.
+connection-error-idle-timeout = Nobody joined using your link code, so the matchmaking server gave up waiting.
+connection-error-banned = You have been banned from the matchmaking server.
+connection-error-closed-by-admin = Your session was closed by the matchmaking server's administrator.
+connection-error-maintenance = The matchmaking server is down for maintenance: { $message }
 connection-error-other = A connection error has occurred: { $error }
 connection-error-confirm = Damn!
 
 play-show-link-code = Show link code
+
+chat-input = Say something...
+chat-show = Show chat messages
+chat-hidden = Chat messages are hidden in streamer mode.
+play-queue = Find a random opponent
+play-bot = Practice against a bot
+play-bot-nickname = Bot
+play-host-room = Host a public room
+play-rooms = Browse public rooms
+
+rooms = Public rooms
+rooms-refresh = Refresh
+rooms-loading = Loading rooms...
+rooms-empty = There are no open rooms right now.
+rooms-error = Could not load rooms: { $error }
+rooms-join = Join
+rooms-open-for = { $minutes ->
+    [one] Open for 1 minute
+   *[other] Open for { $minutes } minutes
+}
@@ -22,6 +22,8 @@ replays-export-lossless = Lossless 1x
 
 replay-viewer-pause = Pause
 replay-viewer-step = Step
+replay-viewer-step-back = Step back
+replay-viewer-seek = Seek
 replay-viewer-speed = Speed
 replay-viewer-speed-up = Speed up
 replay-viewer-slow-down = Slow down
@@ -33,6 +33,7 @@ settings-ui-scale = UI scale
 settings-max-queue-length = Max queue length
 settings-matchmaking-endpoint = Matchmaking endpoint
 settings-replaycollector-endpoint = Replay collector endpoint
+settings-record-telemetry = Record netplay telemetry
 settings-patch-repo = Patches repository
 settings-enable-patch-autoupdate = Enable autoupdate
 settings-data-path = Data path
@@ -61,3 +62,14 @@ settings-use-relay = Use relay
     .always = Always
     .never = Never
 settings-speed-change = Speed change
+settings-rewind-buffer = Rewind buffer length
+settings-script = Script
+    .none = None
+    .tooltip = Rhai scripts (.rhai) in the scripts folder of your data folder. Scripts only run in single player and when watching replays.
+settings-bot-policy = Bot opponent
+    .idle = Idle
+    .random = Random inputs
+    .replay = Replay
+    .script = Script
+settings-bot-replay-path = Bot replay path
+settings-bot-script = Bot script
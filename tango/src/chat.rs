@@ -0,0 +1,102 @@
+pub const MAX_MESSAGE_LENGTH: usize = 200;
+
+const RATE_LIMIT_WINDOW: std::time::Duration = std::time::Duration::from_secs(10);
+const RATE_LIMIT_MESSAGES: usize = 5;
+const MAX_LOG_LENGTH: usize = 100;
+
+pub struct Message {
+    pub is_local: bool,
+    pub text: String,
+    pub ts: std::time::SystemTime,
+}
+
+struct RateLimiter {
+    marks: std::collections::VecDeque<std::time::Instant>,
+}
+
+impl RateLimiter {
+    fn new() -> Self {
+        Self {
+            marks: std::collections::VecDeque::with_capacity(RATE_LIMIT_MESSAGES),
+        }
+    }
+
+    fn try_mark(&mut self) -> bool {
+        let now = std::time::Instant::now();
+        while self
+            .marks
+            .front()
+            .map(|t| now - *t > RATE_LIMIT_WINDOW)
+            .unwrap_or(false)
+        {
+            self.marks.pop_front();
+        }
+        if self.marks.len() >= RATE_LIMIT_MESSAGES {
+            return false;
+        }
+        self.marks.push_back(now);
+        true
+    }
+}
+
+fn sanitize(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_MESSAGE_LENGTH)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+pub struct Chat {
+    messages: std::collections::VecDeque<Message>,
+    local_rate_limiter: RateLimiter,
+    remote_rate_limiter: RateLimiter,
+}
+
+impl Chat {
+    pub fn new() -> Self {
+        Self {
+            messages: std::collections::VecDeque::with_capacity(MAX_LOG_LENGTH),
+            local_rate_limiter: RateLimiter::new(),
+            remote_rate_limiter: RateLimiter::new(),
+        }
+    }
+
+    pub fn messages(&self) -> impl Iterator<Item = &Message> {
+        self.messages.iter()
+    }
+
+    fn push(&mut self, is_local: bool, text: String) {
+        while self.messages.len() >= MAX_LOG_LENGTH {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(Message {
+            is_local,
+            text,
+            ts: std::time::SystemTime::now(),
+        });
+    }
+
+    /// Logs a local message and returns the text to send, or None if it is empty or we are sending too quickly.
+    pub fn add_local(&mut self, text: &str) -> Option<String> {
+        let text = sanitize(text);
+        if text.is_empty() || !self.local_rate_limiter.try_mark() {
+            return None;
+        }
+        self.push(true, text.clone());
+        Some(text)
+    }
+
+    pub fn add_remote(&mut self, text: &str) {
+        let text = sanitize(text);
+        if text.is_empty() {
+            return;
+        }
+        if !self.remote_rate_limiter.try_mark() {
+            log::info!("remote is sending chat messages too quickly, dropping");
+            return;
+        }
+        self.push(false, text);
+    }
+}
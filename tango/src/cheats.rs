@@ -0,0 +1,232 @@
+use crate::{config, game};
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    /// `ADDRESS:VALUE` or `ADDRESS=VALUE` in hex, one per line. The value's width is taken from how many digits it has.
+    Raw,
+    /// Encrypted GameShark / Action Replay v1 and v2 codes.
+    GameShark,
+    /// Unencrypted CodeBreaker codes.
+    CodeBreaker,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Cheat {
+    pub name: String,
+    pub format: Format,
+    pub code: String,
+    pub enabled: bool,
+}
+
+impl Default for Cheat {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            format: Format::Raw,
+            code: String::new(),
+            enabled: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Op {
+    Write8(u32, u8),
+    Write16(u32, u16),
+    Write32(u32, u32),
+    Or16(u32, u16),
+    And16(u32, u16),
+    Add16(u32, u16),
+    /// Skips the next op unless the halfword at the address is equal to the value.
+    IfEqual16(u32, u16),
+    /// Skips the next op if the halfword at the address is equal to the value.
+    IfNotEqual16(u32, u16),
+}
+
+impl Op {
+    fn is_condition(&self) -> bool {
+        matches!(self, Op::IfEqual16(..) | Op::IfNotEqual16(..))
+    }
+}
+
+fn parse_hex(s: &str) -> anyhow::Result<u32> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    if s.is_empty() || s.len() > 8 {
+        anyhow::bail!("bad hex number: {}", s);
+    }
+    Ok(u32::from_str_radix(s, 16)?)
+}
+
+fn parse_raw(line: &str) -> anyhow::Result<Op> {
+    let (address, value) = if let Some(parts) = line.split_once(|c| c == ':' || c == '=') {
+        parts
+    } else {
+        anyhow::bail!("expected ADDRESS:VALUE");
+    };
+    let address = parse_hex(address.trim())?;
+    let value = value.trim();
+    let digits = value.strip_prefix("0x").unwrap_or(value).len();
+    let value = parse_hex(value)?;
+    Ok(match digits {
+        1..=2 => Op::Write8(address, value as u8),
+        3..=4 => Op::Write16(address, value as u16),
+        _ => Op::Write32(address, value),
+    })
+}
+
+const GAMESHARK_SEEDS: [u32; 4] = [0x09f4fbbd, 0x9681884a, 0x352027e9, 0xf3dee5a7];
+
+/// GameShark v1/v2 codes are TEA-encrypted with a fixed key.
+fn decrypt_gameshark(mut op1: u32, mut op2: u32) -> (u32, u32) {
+    let mut sum = 0xc6ef3720u32;
+    for _ in 0..32 {
+        op2 = op2.wrapping_sub(
+            (op1 << 4).wrapping_add(GAMESHARK_SEEDS[2])
+                ^ op1.wrapping_add(sum)
+                ^ (op1 >> 5).wrapping_add(GAMESHARK_SEEDS[3]),
+        );
+        op1 = op1.wrapping_sub(
+            (op2 << 4).wrapping_add(GAMESHARK_SEEDS[0])
+                ^ op2.wrapping_add(sum)
+                ^ (op2 >> 5).wrapping_add(GAMESHARK_SEEDS[1]),
+        );
+        sum = sum.wrapping_sub(0x9e3779b9);
+    }
+    (op1, op2)
+}
+
+fn parse_gameshark(line: &str) -> anyhow::Result<Option<Op>> {
+    let (op1, op2) = if let Some(parts) = line.split_once(char::is_whitespace) {
+        parts
+    } else {
+        anyhow::bail!("expected XXXXXXXX YYYYYYYY");
+    };
+    let (op1, op2) = decrypt_gameshark(parse_hex(op1.trim())?, parse_hex(op2.trim())?);
+    let address = op1 & 0x0fffffff;
+    Ok(Some(match op1 >> 28 {
+        0x0 => Op::Write8(address, op2 as u8),
+        0x1 => Op::Write16(address, op2 as u16),
+        0x2 => Op::Write32(address, op2),
+        // Master codes only matter to the real device, for hooking into the game.
+        0xf => {
+            return Ok(None);
+        }
+        t => anyhow::bail!("unsupported GameShark code type: {:x}", t),
+    }))
+}
+
+fn parse_codebreaker(line: &str) -> anyhow::Result<Option<Op>> {
+    let (op1, op2) = if let Some(parts) = line.split_once(char::is_whitespace) {
+        parts
+    } else {
+        anyhow::bail!("expected XAAAAAAA YYYY");
+    };
+    let op1 = parse_hex(op1.trim())?;
+    let value = parse_hex(op2.trim())? as u16;
+    let address = op1 & 0x0fffffff;
+    Ok(match op1 >> 28 {
+        // Master codes only matter to the real device, for hooking into the game.
+        0x0 | 0x1 => None,
+        0x2 => Some(Op::Or16(address, value)),
+        0x3 => Some(Op::Write8(address, value as u8)),
+        0x6 => Some(Op::And16(address, value)),
+        0x7 => Some(Op::IfEqual16(address, value)),
+        0x8 => Some(Op::Write16(address, value)),
+        0x9 => anyhow::bail!("encrypted CodeBreaker codes aren't supported"),
+        0xa => Some(Op::IfNotEqual16(address, value)),
+        0xe => Some(Op::Add16(address, value)),
+        t => anyhow::bail!("unsupported CodeBreaker code type: {:x}", t),
+    })
+}
+
+pub fn parse(format: Format, code: &str) -> anyhow::Result<Vec<Op>> {
+    let mut ops = vec![];
+    for (i, line) in code.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let op = match format {
+            Format::Raw => parse_raw(line).map(Some),
+            Format::GameShark => parse_gameshark(line),
+            Format::CodeBreaker => parse_codebreaker(line),
+        }
+        .map_err(|e| anyhow::anyhow!("line {}: {}", i + 1, e))?;
+        ops.extend(op);
+    }
+    if ops.last().map(|op| op.is_condition()).unwrap_or(false) {
+        anyhow::bail!("the last code is a condition with nothing after it");
+    }
+    Ok(ops)
+}
+
+/// Returns the ops for every enabled cheat, leaving out any that don't parse.
+pub fn compile(cheats: &[Cheat]) -> Vec<Op> {
+    cheats
+        .iter()
+        .filter(|cheat| cheat.enabled)
+        .flat_map(|cheat| match parse(cheat.format, &cheat.code) {
+            Ok(ops) => ops,
+            Err(e) => {
+                log::warn!("skipping cheat {:?}: {:?}", cheat.name, e);
+                vec![]
+            }
+        })
+        .collect()
+}
+
+/// Called at the end of every frame from the emulator thread.
+pub fn apply(core: &mut mgba::core::CoreMutRef, ops: &[Op]) {
+    let mut skip = false;
+    for op in ops {
+        if std::mem::take(&mut skip) {
+            continue;
+        }
+        match *op {
+            Op::Write8(address, value) => core.raw_write_8(address, -1, value),
+            Op::Write16(address, value) => core.raw_write_16(address, -1, value),
+            Op::Write32(address, value) => core.raw_write_32(address, -1, value),
+            Op::Or16(address, value) => {
+                let old = core.raw_read_16(address, -1);
+                core.raw_write_16(address, -1, old | value);
+            }
+            Op::And16(address, value) => {
+                let old = core.raw_read_16(address, -1);
+                core.raw_write_16(address, -1, old & value);
+            }
+            Op::Add16(address, value) => {
+                let old = core.raw_read_16(address, -1);
+                core.raw_write_16(address, -1, old.wrapping_add(value));
+            }
+            Op::IfEqual16(address, value) => {
+                skip = core.raw_read_16(address, -1) != value;
+            }
+            Op::IfNotEqual16(address, value) => {
+                skip = core.raw_read_16(address, -1) == value;
+            }
+        }
+    }
+}
+
+/// Cheats are kept per game, across all of its saves and patches.
+pub fn path(config: &config::Config, game: &'static (dyn game::Game + Send + Sync)) -> std::path::PathBuf {
+    let (family, variant) = game.gamedb_entry().family_and_variant;
+    config.cheats_path().join(format!("{}_{}.json", family, variant))
+}
+
+pub fn load(path: &std::path::Path) -> anyhow::Result<Vec<Cheat>> {
+    match std::fs::read(path) {
+        Ok(raw) => Ok(serde_json::from_slice(&raw)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn save(path: &std::path::Path, cheats: &[Cheat]) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_vec_pretty(cheats)?)?;
+    Ok(())
+}
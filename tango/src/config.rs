@@ -65,6 +65,20 @@ impl Default for Theme {
     }
 }
 
+#[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq, Eq)]
+pub enum BotPolicy {
+    Idle,
+    Random,
+    Replay,
+    Script,
+}
+
+impl Default for BotPolicy {
+    fn default() -> Self {
+        Self::Random
+    }
+}
+
 fn serialize_language_identifier<S>(v: &unic_langid::LanguageIdentifier, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: serde::Serializer,
@@ -96,10 +110,12 @@ pub struct Config {
     pub input_mapping: input::Mapping,
     pub matchmaking_endpoint: String,
     pub replaycollector_endpoint: String,
+    pub record_telemetry: bool,
     pub patch_repo: String,
     pub enable_patch_autoupdate: bool,
     pub input_delay: u32,
     pub default_match_type: u8,
+    pub default_best_of: u8,
     pub data_path: std::path::PathBuf,
     pub full_screen: bool,
     pub streamer_mode: bool,
@@ -123,7 +139,13 @@ pub struct Config {
     pub last_export_folder: Option<std::path::PathBuf>,
     pub use_relay: Option<bool>,
     pub speed_change_percent: u32,
+    pub rewind_buffer_secs: u32,
+    pub script: Option<String>,
     pub starred_patches: std::collections::HashSet<String>,
+    #[serde(deserialize_with = "ok_or_default")]
+    pub bot_policy: BotPolicy,
+    pub bot_replay_path: String,
+    pub bot_script: String,
 }
 
 impl Default for Config {
@@ -140,10 +162,12 @@ impl Default for Config {
             input_mapping: Default::default(),
             matchmaking_endpoint: "".to_string(),
             replaycollector_endpoint: "https://replaycollector.tango.n1gp.net".to_string(),
+            record_telemetry: false,
             patch_repo: "".to_string(),
             enable_patch_autoupdate: true,
             input_delay: 2,
             default_match_type: 1,
+            default_best_of: 0,
             data_path: "".into(),
             full_screen: false,
             streamer_mode: false,
@@ -165,7 +189,12 @@ impl Default for Config {
             last_export_folder: Default::default(),
             use_relay: None,
             speed_change_percent: 300,
+            rewind_buffer_secs: 20,
+            script: None,
             starred_patches: Default::default(),
+            bot_policy: Default::default(),
+            bot_replay_path: "".to_string(),
+            bot_script: "".to_string(),
         }
     }
 }
@@ -282,6 +311,22 @@ impl Config {
         self.data_path.join("crashstates")
     }
 
+    pub fn telemetry_path(&self) -> std::path::PathBuf {
+        self.data_path.join("telemetry")
+    }
+
+    pub fn savestates_path(&self) -> std::path::PathBuf {
+        self.data_path.join("savestates")
+    }
+
+    pub fn cheats_path(&self) -> std::path::PathBuf {
+        self.data_path.join("cheats")
+    }
+
+    pub fn scripts_path(&self) -> std::path::PathBuf {
+        self.data_path.join("scripts")
+    }
+
     pub fn ensure_dirs(&self) -> Result<(), anyhow::Error> {
         std::fs::create_dir_all(self.saves_path())?;
         std::fs::create_dir_all(self.replays_path())?;
@@ -289,6 +334,8 @@ impl Config {
         std::fs::create_dir_all(self.roms_path())?;
         std::fs::create_dir_all(self.logs_path())?;
         std::fs::create_dir_all(self.crashstates_path())?;
+        std::fs::create_dir_all(self.telemetry_path())?;
+        std::fs::create_dir_all(self.scripts_path())?;
         Ok(())
     }
 }
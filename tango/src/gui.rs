@@ -3,6 +3,7 @@ use fluent_templates::Loader;
 use crate::{audio, config, discord, game, i18n, input, patch, rom, save, session, stats, updater};
 use std::str::FromStr;
 
+mod cheats_window;
 mod debug_window;
 mod escape_window;
 mod language_select;
@@ -12,6 +13,7 @@ mod patches_pane;
 mod play_pane;
 mod replay_dump_window;
 mod replays_pane;
+mod rooms_window;
 mod save_select_view;
 mod save_view;
 mod session_view;
@@ -100,6 +102,7 @@ pub struct State {
     main_view: main_view::State,
     show_escape_window: Option<escape_window::State>,
     show_settings: Option<settings_window::State>,
+    show_cheats: Option<cheats_window::State>,
     font_data: std::collections::BTreeMap<String, egui::FontData>,
     themes: Themes,
     current_language: Option<unic_langid::LanguageIdentifier>,
@@ -210,6 +213,7 @@ impl State {
             main_view,
             steal_input: None,
             show_settings: None,
+            show_cheats: None,
             show_escape_window: None,
             session_view: None,
             welcome: None,
@@ -396,9 +400,11 @@ pub fn show(
         ctx,
         &mut state.shared,
         &mut state.show_escape_window,
-        &config.language,
+        config,
         &mut state.show_settings,
+        &mut state.show_cheats,
     );
+    cheats_window::show(ctx, &config.language, &state.shared.session, &mut state.show_cheats);
 
     // take ui windows to allow state to be passed to each window
     let mut ui_windows = std::mem::take(&mut state.shared.ui_windows);
@@ -0,0 +1,182 @@
+use crate::{cheats, i18n, session};
+use fluent_templates::Loader;
+
+/// How long to wait after the last edit before writing the cheats out, so typing doesn't rewrite the file on every keystroke.
+const SAVE_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+
+pub struct State {
+    path: std::path::PathBuf,
+    cheats: Vec<cheats::Cheat>,
+    errors: Vec<Option<String>>,
+    save_error: Option<String>,
+    unsaved_since: Option<std::time::Instant>,
+}
+
+fn check(cheat: &cheats::Cheat) -> Option<String> {
+    cheats::parse(cheat.format, &cheat.code).err().map(|e| e.to_string())
+}
+
+impl State {
+    pub fn new(path: std::path::PathBuf) -> Self {
+        let cheats = cheats::load(&path).unwrap_or_else(|e| {
+            log::error!("failed to load cheats: {:?}", e);
+            vec![]
+        });
+        let errors = cheats.iter().map(check).collect();
+        Self {
+            path,
+            cheats,
+            errors,
+            save_error: None,
+            unsaved_since: None,
+        }
+    }
+
+    fn save(&mut self) {
+        self.unsaved_since = None;
+        self.save_error = cheats::save(&self.path, &self.cheats).err().map(|e| e.to_string());
+    }
+}
+
+fn format_label(language: &unic_langid::LanguageIdentifier, format: cheats::Format) -> String {
+    i18n::LOCALES
+        .lookup(
+            language,
+            match format {
+                cheats::Format::Raw => "cheats-format-raw",
+                cheats::Format::GameShark => "cheats-format-gameshark",
+                cheats::Format::CodeBreaker => "cheats-format-codebreaker",
+            },
+        )
+        .unwrap()
+}
+
+pub fn show(
+    ctx: &egui::Context,
+    language: &unic_langid::LanguageIdentifier,
+    session: &parking_lot::Mutex<Option<session::Session>>,
+    show_cheats: &mut Option<State>,
+) {
+    if !session
+        .lock()
+        .as_ref()
+        .map(|session| matches!(session.mode(), session::Mode::SinglePlayer(_)))
+        .unwrap_or(false)
+    {
+        if let Some(mut state) = show_cheats.take() {
+            if state.unsaved_since.is_some() {
+                state.save();
+            }
+        }
+        return;
+    }
+
+    let state = if let Some(state) = show_cheats.as_mut() {
+        state
+    } else {
+        return;
+    };
+
+    let mut open = true;
+    let mut changed = false;
+    egui::Window::new(i18n::LOCALES.lookup(language, "cheats").unwrap())
+        .id(egui::Id::new("cheats-window"))
+        .open(&mut open)
+        .default_width(400.0)
+        .show(ctx, |ui| {
+            egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                let mut to_remove = None;
+                for (i, (cheat, error)) in state.cheats.iter_mut().zip(state.errors.iter_mut()).enumerate() {
+                    ui.push_id(i, |ui| {
+                        ui.horizontal(|ui| {
+                            changed |= ui.checkbox(&mut cheat.enabled, "").changed();
+                            changed |= ui
+                                .add(
+                                    egui::TextEdit::singleline(&mut cheat.name)
+                                        .hint_text(i18n::LOCALES.lookup(language, "cheats-name").unwrap()),
+                                )
+                                .changed();
+                            let format = cheat.format;
+                            egui::ComboBox::from_id_source("cheats-window-format")
+                                .selected_text(format_label(language, cheat.format))
+                                .show_ui(ui, |ui| {
+                                    for f in [
+                                        cheats::Format::Raw,
+                                        cheats::Format::GameShark,
+                                        cheats::Format::CodeBreaker,
+                                    ] {
+                                        ui.selectable_value(&mut cheat.format, f, format_label(language, f));
+                                    }
+                                });
+                            if cheat.format != format {
+                                changed = true;
+                                *error = check(cheat);
+                            }
+                            if ui
+                                .button(i18n::LOCALES.lookup(language, "cheats-remove").unwrap())
+                                .clicked()
+                            {
+                                to_remove = Some(i);
+                            }
+                        });
+                        if ui
+                            .add(
+                                egui::TextEdit::multiline(&mut cheat.code)
+                                    .code_editor()
+                                    .desired_rows(2)
+                                    .desired_width(f32::INFINITY),
+                            )
+                            .changed()
+                        {
+                            changed = true;
+                            *error = check(cheat);
+                        }
+                        if let Some(error) = error.as_ref() {
+                            ui.colored_label(egui::Color32::RED, error);
+                        }
+                        ui.separator();
+                    });
+                }
+                if let Some(i) = to_remove {
+                    state.cheats.remove(i);
+                    state.errors.remove(i);
+                    changed = true;
+                }
+            });
+
+            if ui
+                .button(i18n::LOCALES.lookup(language, "cheats-add").unwrap())
+                .clicked()
+            {
+                state.cheats.push(cheats::Cheat::default());
+                state.errors.push(None);
+                changed = true;
+            }
+
+            if let Some(save_error) = state.save_error.as_ref() {
+                ui.colored_label(egui::Color32::RED, save_error);
+            }
+        });
+
+    if changed {
+        state.unsaved_since = Some(std::time::Instant::now());
+        if let Some(session) = session.lock().as_ref() {
+            if let Err(e) = session.set_cheats(cheats::compile(&state.cheats)) {
+                log::error!("failed to set cheats: {:?}", e);
+            }
+        }
+    }
+
+    // Saved without the session locked, so a slow disk doesn't hold up the emulator.
+    if let Some(unsaved_since) = state.unsaved_since {
+        if !open || unsaved_since.elapsed() >= SAVE_DELAY {
+            state.save();
+        } else {
+            ctx.request_repaint_after(SAVE_DELAY.saturating_sub(unsaved_since.elapsed()));
+        }
+    }
+
+    if !open {
+        *show_cheats = None;
+    }
+}
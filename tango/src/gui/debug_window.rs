@@ -2,21 +2,84 @@ use fluent_templates::Loader;
 
 use crate::{i18n, session};
 
+#[derive(PartialEq)]
+enum Tab {
+    Memory,
+    Cpu,
+    Netplay,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum Region {
+    All,
+    Ewram,
+    Iwram,
+    Rom,
+}
+
+impl Region {
+    const ALL: [Region; 4] = [Region::All, Region::Ewram, Region::Iwram, Region::Rom];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Region::All => "All",
+            Region::Ewram => "EWRAM",
+            Region::Iwram => "IWRAM",
+            Region::Rom => "ROM",
+        }
+    }
+
+    fn range(&self) -> std::ops::Range<u32> {
+        match self {
+            Region::All => 0x00000000..0x10000000,
+            Region::Ewram => 0x02000000..0x02040000,
+            Region::Iwram => 0x03000000..0x03008000,
+            Region::Rom => 0x08000000..0x0a000000,
+        }
+    }
+}
+
 pub struct State {
+    tab: Tab,
     jump_to: String,
+    region: Region,
+    /// The byte being edited in the hex view and what's been typed so far.
+    editing: Option<(u32, String)>,
+    focus_editor: bool,
+    /// A telemetry file opened from disk, plotted instead of the live session's samples.
+    telemetry_file: Option<(std::path::PathBuf, std::io::Result<Vec<tango_pvp::telemetry::Sample>>)>,
 }
 
 impl State {
     pub fn new() -> Self {
         Self {
+            tab: Tab::Memory,
             jump_to: "".to_string(),
+            region: Region::Ewram,
+            editing: None,
+            focus_editor: false,
+            telemetry_file: None,
         }
     }
 }
 
+/// Sessions whose CPU can be inspected: in a match, the core is forever rolling back and forth, so there's nothing steady to show.
+fn is_debuggable(session: &session::Session) -> bool {
+    matches!(
+        session.mode(),
+        session::Mode::SinglePlayer(_) | session::Mode::Replayer(_)
+    )
+}
+
+/// Writing to memory in a match or a replay would desync it from its inputs, so that's only allowed in single player.
+fn is_editable(session: &session::Session) -> bool {
+    matches!(session.mode(), session::Mode::SinglePlayer(_))
+}
+
 pub fn show(
     ctx: &egui::Context,
     language: &unic_langid::LanguageIdentifier,
+    telemetry_path: &std::path::Path,
     session: &session::Session,
     state: &mut Option<State>,
 ) {
@@ -25,101 +88,387 @@ pub fn show(
         .id(egui::Id::new("debug"))
         .open(&mut open)
         .show(ctx, |ui| {
+            let state = state.as_mut().unwrap();
+
             ui.horizontal(|ui| {
-                let _ = ui.selectable_label(true, "Memory");
+                ui.selectable_value(&mut state.tab, Tab::Memory, "Memory");
+                if is_debuggable(session) {
+                    ui.selectable_value(&mut state.tab, Tab::Cpu, "CPU");
+                }
+                ui.selectable_value(&mut state.tab, Tab::Netplay, "Netplay");
             });
 
             ui.separator();
 
-            let state = state.as_mut().unwrap();
+            match state.tab {
+                Tab::Memory => show_memory_tab(ui, session, state),
+                Tab::Cpu => show_cpu_tab(ui, session),
+                Tab::Netplay => show_netplay_tab(ui, telemetry_path, session, state),
+            }
+        });
+    if !open {
+        *state = None;
+    }
+}
 
-            let mut jumping = false;
-            ui.horizontal(|ui| {
-                let input_resp = ui.add(
-                    egui::TextEdit::singleline(&mut state.jump_to)
-                        .desired_width(8.0 * FONT_WIDTH)
-                        .hint_text("Jump to")
-                        .font(egui::TextStyle::Monospace),
-                );
-                state.jump_to = state
-                    .jump_to
-                    .chars()
-                    .filter(|c| "0123456789abcdefABCDEF".chars().any(|c2| c2 == *c))
-                    .collect();
-                if input_resp.lost_focus() && ui.ctx().input(|i| i.key_pressed(egui::Key::Enter)) {
-                    jumping = true;
-                }
+const FONT_WIDTH: f32 = 8.0;
+
+fn show_memory_tab(ui: &mut egui::Ui, session: &session::Session, state: &mut State) {
+    let editable = is_editable(session);
+    if !editable {
+        state.editing = None;
+    }
 
-                if ui.button("Go!").clicked() {
-                    jumping = true;
+    let mut jump_to = None;
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_source("debug-window-memory-region")
+            .selected_text(state.region.name())
+            .show_ui(ui, |ui| {
+                for region in Region::ALL {
+                    if ui.selectable_value(&mut state.region, region, region.name()).changed() {
+                        jump_to = Some(region.range().start);
+                    }
                 }
             });
 
-            let thread_handle = session.thread_handle();
-            let mut audio_guard = thread_handle.lock_audio();
+        let input_resp = ui.add(
+            egui::TextEdit::singleline(&mut state.jump_to)
+                .desired_width(8.0 * FONT_WIDTH)
+                .hint_text("Jump to")
+                .font(egui::TextStyle::Monospace),
+        );
+        state.jump_to = state.jump_to.chars().filter(|c| c.is_ascii_hexdigit()).collect();
+        let mut jumping = false;
+        if input_resp.lost_focus() && ui.ctx().input(|i| i.key_pressed(egui::Key::Enter)) {
+            jumping = true;
+        }
+
+        if ui.button("Go!").clicked() {
+            jumping = true;
+        }
 
-            let row_height = ui.text_style_height(&egui::TextStyle::Body);
-            let mut sa = egui::ScrollArea::vertical().auto_shrink([true, false]);
-            if jumping {
-                if let Ok(jump_to) = u32::from_str_radix(&state.jump_to, 16) {
-                    sa =
-                        sa.vertical_scroll_offset((row_height + ui.spacing().item_spacing.y) * (jump_to / 0x10) as f32);
+        if jumping {
+            if let Ok(address) = u32::from_str_radix(&state.jump_to, 16) {
+                if !state.region.range().contains(&address) {
+                    state.region = Region::ALL[1..]
+                        .iter()
+                        .copied()
+                        .find(|region| region.range().contains(&address))
+                        .unwrap_or(Region::All);
                 }
+                jump_to = Some(address);
             }
+        }
+    });
 
-            const FONT_WIDTH: f32 = 8.0;
-            sa.show_rows(ui, row_height, 0x0fffffff / 0x10, |ui, range| {
-                egui_extras::StripBuilder::new(ui)
-                    .sizes(egui_extras::Size::exact(row_height), range.len())
-                    .vertical(|mut outer_strip| {
-                        for i in range {
-                            outer_strip.cell(|ui| {
-                                let rect = ui.available_rect_before_wrap().expand(ui.spacing().item_spacing.y);
-                                if i % 2 == 0 {
-                                    ui.painter().rect_filled(rect, 0.0, ui.visuals().faint_bg_color);
-                                }
-
-                                egui_extras::StripBuilder::new(ui)
-                                    .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
-                                    .size(egui_extras::Size::exact(8.0 * FONT_WIDTH))
-                                    .size(egui_extras::Size::exact(48.0 * FONT_WIDTH))
-                                    .size(egui_extras::Size::remainder())
-                                    .horizontal(|mut strip| {
-                                        let offset = i * 16;
-                                        strip.cell(|ui| {
-                                            ui.label(egui::RichText::new(format!("{:08x}", offset)).monospace().weak());
-                                        });
-                                        let mut buf = [0u8; 0x10];
-                                        audio_guard.core_mut().raw_read_range(offset as u32, -1, &mut buf[..]);
-                                        strip.cell(|ui| {
-                                            ui.add(
-                                                egui::TextEdit::singleline(
-                                                    &mut buf
-                                                        .iter()
-                                                        .map(|b| format!("{:02x}", b))
-                                                        .collect::<Vec<_>>()
-                                                        .join(" "),
-                                                )
-                                                .desired_width(ui.available_width())
-                                                .frame(false)
-                                                .font(egui::TextStyle::Monospace),
-                                            );
-                                        });
-
-                                        strip.cell(|ui| {
-                                            ui.monospace(
-                                                buf.map(|b| if (32..127).contains(&b) { b as char } else { '.' })
-                                                    .iter()
-                                                    .collect::<String>(),
-                                            );
-                                        });
-                                    });
-                            });
+    let thread_handle = session.thread_handle();
+    let mut audio_guard = thread_handle.lock_audio();
+
+    let range = state.region.range();
+    let row_height = ui.text_style_height(&egui::TextStyle::Body);
+    let mut sa = egui::ScrollArea::vertical().auto_shrink([true, false]);
+    if let Some(address) = jump_to {
+        sa = sa.vertical_scroll_offset(
+            (row_height + ui.spacing().item_spacing.y) * ((address.max(range.start) - range.start) / 0x10) as f32,
+        );
+    }
+
+    sa.show_rows(ui, row_height, (range.len() / 0x10) as usize, |ui, rows| {
+        egui_extras::StripBuilder::new(ui)
+            .sizes(egui_extras::Size::exact(row_height), rows.len())
+            .vertical(|mut outer_strip| {
+                for i in rows {
+                    outer_strip.cell(|ui| {
+                        let rect = ui.available_rect_before_wrap().expand(ui.spacing().item_spacing.y);
+                        if i % 2 == 0 {
+                            ui.painter().rect_filled(rect, 0.0, ui.visuals().faint_bg_color);
                         }
+
+                        egui_extras::StripBuilder::new(ui)
+                            .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                            .size(egui_extras::Size::exact(8.0 * FONT_WIDTH))
+                            .size(egui_extras::Size::exact(48.0 * FONT_WIDTH))
+                            .size(egui_extras::Size::remainder())
+                            .horizontal(|mut strip| {
+                                let offset = range.start + i as u32 * 16;
+                                strip.cell(|ui| {
+                                    ui.label(egui::RichText::new(format!("{:08x}", offset)).monospace().weak());
+                                });
+                                let mut buf = [0u8; 0x10];
+                                audio_guard.core_mut().raw_read_range(offset, -1, &mut buf[..]);
+                                strip.cell(|ui| {
+                                    ui.spacing_mut().item_spacing.x = FONT_WIDTH;
+                                    for (j, b) in buf.iter().enumerate() {
+                                        let address = offset + j as u32;
+                                        if let Some(write) = show_byte(ui, state, editable, address, *b) {
+                                            audio_guard.core_mut().raw_write_range(address, -1, &[write]);
+                                        }
+                                    }
+                                });
+
+                                strip.cell(|ui| {
+                                    ui.monospace(
+                                        buf.map(|b| if (32..127).contains(&b) { b as char } else { '.' })
+                                            .iter()
+                                            .collect::<String>(),
+                                    );
+                                });
+                            });
                     });
+                }
             });
-        });
-    if !open {
-        *state = None;
+    });
+}
+
+/// Shows one byte of the hex view, returning a new value for it once one has been typed in.
+fn show_byte(ui: &mut egui::Ui, state: &mut State, editable: bool, address: u32, b: u8) -> Option<u8> {
+    let text = if let Some((_, text)) = state.editing.as_mut().filter(|(a, _)| *a == address) {
+        text
+    } else {
+        let resp = ui.add(
+            egui::Label::new(egui::RichText::new(format!("{:02x}", b)).monospace()).sense(if editable {
+                egui::Sense::click()
+            } else {
+                egui::Sense::hover()
+            }),
+        );
+        if resp.clicked() {
+            state.editing = Some((address, String::new()));
+            state.focus_editor = true;
+        }
+        return None;
+    };
+
+    let resp = ui.add(
+        egui::TextEdit::singleline(text)
+            .desired_width(2.0 * FONT_WIDTH)
+            .hint_text(format!("{:02x}", b))
+            .char_limit(2)
+            .frame(false)
+            .font(egui::TextStyle::Monospace),
+    );
+    if std::mem::take(&mut state.focus_editor) {
+        resp.request_focus();
+    }
+    text.retain(|c| c.is_ascii_hexdigit());
+
+    if text.len() == 2 {
+        let value = u8::from_str_radix(text, 16).unwrap();
+        // Move on to the next byte, like a hex editor would.
+        state.editing = Some((address.wrapping_add(1), String::new()));
+        state.focus_editor = true;
+        return Some(value);
+    }
+
+    if resp.lost_focus() {
+        state.editing = None;
+    }
+    None
+}
+
+fn show_cpu_tab(ui: &mut egui::Ui, session: &session::Session) {
+    const BEFORE: u32 = 8;
+    const AFTER: u32 = 24;
+
+    let thread_handle = session.thread_handle();
+    let mut audio_guard = thread_handle.lock_audio();
+    let mut core = audio_guard.core_mut();
+
+    let (gprs, cpsr, execution_mode) = {
+        let core_ref = core.as_ref();
+        let gba = core_ref.gba();
+        let cpu = gba.cpu();
+        (
+            (0..16).map(|r| cpu.gpr(r) as u32).collect::<Vec<_>>(),
+            cpu.cpsr() as u32,
+            cpu.execution_mode(),
+        )
+    };
+
+    egui::Grid::new("debug-window-registers").num_columns(4).show(ui, |ui| {
+        for (r, v) in gprs.iter().enumerate() {
+            ui.monospace(format!("{:>4} {:08x}", format!("r{}", r), v));
+            if r % 4 == 3 {
+                ui.end_row();
+            }
+        }
+        let flags = [(31, 'N'), (30, 'Z'), (29, 'C'), (28, 'V'), (7, 'I'), (6, 'F'), (5, 'T')]
+            .into_iter()
+            .map(|(bit, name)| if cpsr & (1 << bit) != 0 { name } else { '-' })
+            .collect::<String>();
+        ui.monospace(format!("cpsr {:08x}", cpsr));
+        ui.monospace(flags);
+        ui.monospace(format!("mode {:02x}", cpsr & 0x1f));
+        ui.end_row();
+    });
+
+    ui.separator();
+
+    let mut lines = vec![];
+    match execution_mode {
+        mgba::arm_core::ExecutionMode::Thumb => {
+            let pc = core.as_ref().gba().cpu().thumb_pc();
+            let mut address = pc.wrapping_sub(BEFORE * 2);
+            while address < pc.wrapping_add(AFTER * 2) {
+                let opcode = core.raw_read_16(address, -1);
+                // Don't let a BL swallow the instruction at the PC.
+                let next = if address.wrapping_add(2) != pc {
+                    Some(core.raw_read_16(address.wrapping_add(2), -1))
+                } else {
+                    None
+                };
+                let (text, size) = core.as_ref().gba().cpu().disassemble_thumb(address, opcode, next);
+                let bytes = if size == 4 {
+                    format!("{:04x} {:04x}", opcode, next.unwrap())
+                } else {
+                    format!("{:04x}", opcode)
+                };
+                lines.push((address, bytes, text));
+                address = address.wrapping_add(size);
+            }
+            show_disassembly(ui, pc, &lines);
+        }
+        mgba::arm_core::ExecutionMode::ARM => {
+            let pc = core.as_ref().gba().cpu().arm_pc();
+            let mut address = pc.wrapping_sub(BEFORE * 4);
+            while address < pc.wrapping_add(AFTER * 4) {
+                let opcode = core.raw_read_32(address, -1);
+                let text = core.as_ref().gba().cpu().disassemble_arm(address, opcode);
+                lines.push((address, format!("{:08x}", opcode), text));
+                address = address.wrapping_add(4);
+            }
+            show_disassembly(ui, pc, &lines);
+        }
     }
 }
+
+fn show_disassembly(ui: &mut egui::Ui, pc: u32, lines: &[(u32, String, String)]) {
+    egui::ScrollArea::vertical().auto_shrink([true, false]).show(ui, |ui| {
+        for (address, bytes, text) in lines {
+            let line = egui::RichText::new(format!(
+                "{} {:08x}  {:<9}  {}",
+                if *address == pc { "▶" } else { " " },
+                address,
+                bytes,
+                text
+            ))
+            .monospace();
+            ui.label(if *address == pc { line.strong() } else { line });
+        }
+    });
+}
+
+fn plot(ui: &mut egui::Ui, label: &str, unit: &str, values: &[f32]) {
+    let last = values.last().copied().unwrap_or(0.0);
+    let max = values.iter().copied().fold(0.0f32, f32::max);
+    ui.monospace(format!("{:<16} {:8.1}{} (max {:8.1}{})", label, last, unit, max, unit));
+
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(ui.available_width(), 48.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+    if values.len() < 2 {
+        return;
+    }
+
+    let scale = if max > 0.0 { max } else { 1.0 };
+    painter.add(egui::Shape::line(
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                egui::pos2(
+                    rect.left() + rect.width() * i as f32 / (values.len() - 1) as f32,
+                    rect.bottom() - rect.height() * v / scale,
+                )
+            })
+            .collect(),
+        egui::Stroke::new(1.0, ui.visuals().text_color()),
+    ));
+}
+
+fn show_netplay_tab(
+    ui: &mut egui::Ui,
+    telemetry_path: &std::path::Path,
+    session: &session::Session,
+    state: &mut State,
+) {
+    ui.horizontal(|ui| {
+        if ui.button("Open telemetry file…").clicked() {
+            if let Some(path) = rfd::FileDialog::new()
+                .set_directory(telemetry_path)
+                .add_filter("JSON Lines", &["jsonl"])
+                .pick_file()
+            {
+                let samples =
+                    std::fs::File::open(&path).and_then(|f| tango_pvp::telemetry::read(std::io::BufReader::new(f)));
+                state.telemetry_file = Some((path, samples));
+            }
+        }
+        if let Some((path, _)) = state.telemetry_file.as_ref() {
+            ui.monospace(path.file_name().unwrap_or_default().to_string_lossy().to_string());
+            if ui.button("Close").clicked() {
+                state.telemetry_file = None;
+            }
+        }
+    });
+
+    let live_samples;
+    let samples = match (state.telemetry_file.as_ref(), session.mode()) {
+        (Some((_, Ok(samples))), _) => samples,
+        (Some((_, Err(e))), _) => {
+            ui.label(format!("Failed to read telemetry file: {}", e));
+            return;
+        }
+        (None, session::Mode::PvP(pvp)) => {
+            live_samples = pvp.telemetry().recent_samples();
+            ui.ctx().request_repaint();
+            &live_samples
+        }
+        (None, _) => {
+            return;
+        }
+    };
+
+    let mut rtts = vec![];
+    let mut rollback_depths = vec![];
+    let mut local_queue_lengths = vec![];
+    let mut remote_queue_lengths = vec![];
+    let mut frame_times = vec![];
+    let mut late_inputs = 0;
+    let mut dropped_inputs = 0;
+    for sample in samples {
+        match &sample.event {
+            tango_pvp::telemetry::Event::Frame {
+                rollback_depth,
+                local_queue_length,
+                remote_queue_length,
+                frame_time_us,
+                ..
+            } => {
+                rollback_depths.push(*rollback_depth as f32);
+                local_queue_lengths.push(*local_queue_length as f32);
+                remote_queue_lengths.push(*remote_queue_length as f32);
+                frame_times.push(*frame_time_us as f32 / 1000.0);
+            }
+            tango_pvp::telemetry::Event::Rtt { rtt_us } => {
+                rtts.push(*rtt_us as f32 / 1000.0);
+            }
+            tango_pvp::telemetry::Event::LateInput { .. } => {
+                late_inputs += 1;
+            }
+            tango_pvp::telemetry::Event::DroppedInput { .. } => {
+                dropped_inputs += 1;
+            }
+        }
+    }
+
+    ui.monospace(format!(
+        "late inputs {:5}  dropped inputs {:5}",
+        late_inputs, dropped_inputs
+    ));
+    plot(ui, "rtt", "ms", &rtts);
+    plot(ui, "rollback depth", "", &rollback_depths);
+    plot(ui, "local qlen", "", &local_queue_lengths);
+    plot(ui, "remote qlen", "", &remote_queue_lengths);
+    plot(ui, "frame time", "ms", &frame_times);
+}
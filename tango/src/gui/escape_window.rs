@@ -1,23 +1,155 @@
-use crate::{gui, i18n};
+use crate::{cheats, config, gui, i18n, savestate, session};
 use fluent_templates::Loader;
 
-pub struct State {}
+struct Slot {
+    saved_at: std::time::SystemTime,
+    thumbnail: Option<egui::TextureHandle>,
+}
+
+pub struct State {
+    slots: Option<Vec<Option<Slot>>>,
+    save_state_error: Option<String>,
+}
 
 impl State {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            slots: None,
+            save_state_error: None,
+        }
     }
 }
 
+fn show_save_state_slots(
+    ui: &mut egui::Ui,
+    language: &unic_langid::LanguageIdentifier,
+    session: &session::Session,
+    slots_path: &std::path::Path,
+    state: &mut State,
+) -> bool {
+    let slots = state.slots.get_or_insert_with(|| {
+        savestate::list(slots_path)
+            .into_iter()
+            .enumerate()
+            .map(|(i, slot)| {
+                let slot = slot?;
+                Some(Slot {
+                    saved_at: slot.saved_at,
+                    thumbnail: slot.thumbnail.map(|thumbnail| {
+                        ui.ctx().load_texture(
+                            format!("save-state-slot-{}", i),
+                            egui::ColorImage::from_rgba_unmultiplied(
+                                [thumbnail.width() as usize, thumbnail.height() as usize],
+                                &thumbnail,
+                            ),
+                            egui::TextureOptions::NEAREST,
+                        )
+                    }),
+                })
+            })
+            .collect()
+    });
+
+    let mut saved = false;
+    let mut loaded = false;
+    egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+        egui::Grid::new("save-state-slots").num_columns(4).show(ui, |ui| {
+            for (i, slot) in slots.iter().enumerate() {
+                let slot_arg = std::collections::HashMap::from([("slot", (i + 1).into())]);
+                ui.label(
+                    i18n::LOCALES
+                        .lookup_with_args(language, "escape-save-state-slot", &slot_arg)
+                        .unwrap(),
+                );
+                if let Some(slot) = slot.as_ref() {
+                    if let Some(thumbnail) = slot.thumbnail.as_ref() {
+                        ui.image((
+                            thumbnail.id(),
+                            egui::Vec2::new(
+                                mgba::gba::SCREEN_WIDTH as f32 / 3.0,
+                                mgba::gba::SCREEN_HEIGHT as f32 / 3.0,
+                            ),
+                        ));
+                    } else {
+                        ui.label("");
+                    }
+                    ui.label(
+                        chrono::DateTime::<chrono::Local>::from(slot.saved_at)
+                            .format("%Y-%m-%d %H:%M:%S")
+                            .to_string(),
+                    );
+                } else {
+                    ui.label("");
+                    ui.label(i18n::LOCALES.lookup(language, "escape-save-state-empty").unwrap());
+                }
+                ui.horizontal(|ui| {
+                    if ui
+                        .button(i18n::LOCALES.lookup(language, "escape-save-state-save").unwrap())
+                        .clicked()
+                    {
+                        if let Err(e) = savestate::save_from_session(session, slots_path, i) {
+                            log::error!("failed to save state: {:?}", e);
+                            state.save_state_error = Some(
+                                i18n::LOCALES
+                                    .lookup_with_args(language, "save-state-save-failed", &slot_arg)
+                                    .unwrap(),
+                            );
+                        } else {
+                            saved = true;
+                        }
+                    }
+                    if ui
+                        .add_enabled(
+                            slot.is_some(),
+                            egui::Button::new(i18n::LOCALES.lookup(language, "escape-save-state-load").unwrap()),
+                        )
+                        .clicked()
+                    {
+                        if let Err(e) = savestate::load_into_session(session, slots_path, i) {
+                            log::error!("failed to load state: {:?}", e);
+                            state.save_state_error = Some(
+                                i18n::LOCALES
+                                    .lookup_with_args(language, "save-state-load-failed", &slot_arg)
+                                    .unwrap(),
+                            );
+                        } else {
+                            loaded = true;
+                        }
+                    }
+                });
+                ui.end_row();
+            }
+        });
+    });
+
+    if let Some(error) = state.save_state_error.as_ref() {
+        ui.colored_label(egui::Color32::RED, error);
+    }
+
+    if saved {
+        // Pick up the new thumbnail next frame.
+        state.slots = None;
+        state.save_state_error = None;
+    }
+    loaded
+}
+
 pub fn show(
     ctx: &egui::Context,
     shared_root_state: &mut gui::SharedRootState,
     show_escape_window: &mut Option<State>,
-    language: &unic_langid::LanguageIdentifier,
+    config: &config::Config,
     show_settings: &mut Option<gui::settings_window::State>,
+    show_cheats: &mut Option<gui::cheats_window::State>,
 ) {
+    let language = &config.language;
     let session = shared_root_state.session.clone();
     let selection = &mut shared_root_state.selection;
+    let is_single_player = session
+        .lock()
+        .as_ref()
+        .map(|session| matches!(session.mode(), session::Mode::SinglePlayer(_)))
+        .unwrap_or(false);
 
     let mut open = show_escape_window.is_some();
     egui::Window::new("")
@@ -35,6 +167,19 @@ pub fn show(
                     *show_settings = Some(gui::settings_window::State::new());
                     *show_escape_window = None;
                 }
+                if is_single_player {
+                    if let Some(selection) = selection.as_ref() {
+                        if ui
+                            .button(
+                                egui::RichText::new(i18n::LOCALES.lookup(language, "escape-cheats").unwrap()).heading(),
+                            )
+                            .clicked()
+                        {
+                            *show_cheats = Some(gui::cheats_window::State::new(cheats::path(config, selection.game)));
+                            *show_escape_window = None;
+                        }
+                    }
+                }
                 if ui
                     .button(egui::RichText::new(i18n::LOCALES.lookup(language, "escape-end-game").unwrap()).heading())
                     .clicked()
@@ -49,6 +194,33 @@ pub fn show(
                     *show_escape_window = None;
                 }
             });
+
+            // Save states would let one side desync the other, so they're only offered outside of netplay.
+            let session = session.lock();
+            let session = if let Some(session) = session.as_ref() {
+                session
+            } else {
+                return;
+            };
+            if !matches!(session.mode(), session::Mode::SinglePlayer(_)) {
+                return;
+            }
+            let slots_path = if let Some(selection) = selection.as_ref() {
+                savestate::slots_path(config, selection)
+            } else {
+                return;
+            };
+            let state = if let Some(state) = show_escape_window.as_mut() {
+                state
+            } else {
+                return;
+            };
+
+            ui.separator();
+            ui.heading(i18n::LOCALES.lookup(language, "escape-save-states").unwrap());
+            if show_save_state_slots(ui, language, session, &slots_path, state) {
+                *show_escape_window = None;
+            }
         });
     if !open {
         *show_escape_window = None;
@@ -1,4 +1,6 @@
-use crate::{audio, config, discord, game, gui, i18n, net, patch, randomcode, rom, session, stats, sync};
+use crate::{
+    audio, chat, cheats, config, discord, game, gui, i18n, net, patch, randomcode, rom, scripting, session, stats, sync,
+};
 use fluent_templates::Loader;
 use rand::RngCore;
 use sha3::digest::{ExtendableOutput, Update};
@@ -195,13 +197,21 @@ struct RemoteSelection {
 struct Lobby {
     attention_requested: bool,
     link_code: String,
-    sender: Option<net::Sender>,
+    sender: Option<std::sync::Arc<tokio::sync::Mutex<net::Sender>>>,
     local_selection: Option<LocalSelection>,
     remote_selection: Option<RemoteSelection>,
     nickname: String,
     match_type: (u8, u8),
+    best_of: u8,
+    lockstep: bool,
+    capabilities: net::protocol::Capabilities,
+    chat: std::sync::Arc<parking_lot::Mutex<chat::Chat>>,
+    chat_input: String,
+    show_chat: bool,
     reveal_setup: bool,
     remote_settings: net::protocol::Settings,
+    remote_best_of: u8,
+    remote_lockstep: bool,
     remote_commitment: Option<[u8; 16]>,
     latencies: crate::stats::LatencyCounter,
     local_negotiated_state: Option<(net::protocol::NegotiatedState, Vec<u8>)>,
@@ -236,6 +246,16 @@ pub fn get_netplay_compatibility_from_game_info(
     })
 }
 
+fn is_lockstep_supported(settings: &net::protocol::Settings) -> bool {
+    settings
+        .game_info
+        .as_ref()
+        .and_then(|gi| game::find_by_family_and_variant(&gi.family_and_variant.0, gi.family_and_variant.1))
+        .and_then(|game| tango_pvp::hooks::hooks_for_gamedb_entry(game.gamedb_entry()))
+        .map(|hooks| hooks.supports_lockstep())
+        .unwrap_or(false)
+}
+
 fn are_settings_compatible(
     local_settings: &net::protocol::Settings,
     remote_settings: &net::protocol::Settings,
@@ -315,6 +335,55 @@ fn are_settings_compatible(
         && local_simplified_settings == remote_simplified_settings
 }
 
+fn show_lobby_chat(ui: &mut egui::Ui, config: &config::Config, lobby: &mut Lobby) {
+    let hidden = config.streamer_mode && !lobby.show_chat;
+
+    egui::ScrollArea::vertical()
+        .id_source("lobby-chat")
+        .max_height(100.0)
+        .auto_shrink([false, true])
+        .stick_to_bottom(true)
+        .show(ui, |ui| {
+            if hidden {
+                ui.weak(i18n::LOCALES.lookup(&config.language, "chat-hidden").unwrap());
+                return;
+            }
+            for message in lobby.chat.lock().messages() {
+                ui.horizontal_wrapped(|ui| {
+                    ui.strong(if message.is_local {
+                        lobby.nickname.as_str()
+                    } else {
+                        lobby.remote_settings.nickname.as_str()
+                    });
+                    ui.label(&message.text);
+                });
+            }
+        });
+
+    ui.horizontal(|ui| {
+        if config.streamer_mode
+            && ui
+                .selectable_label(lobby.show_chat, "👁️")
+                .on_hover_text(i18n::LOCALES.lookup(&config.language, "chat-show").unwrap())
+                .clicked()
+        {
+            lobby.show_chat = !lobby.show_chat;
+        }
+
+        let input_resp = ui.add(
+            egui::TextEdit::singleline(&mut lobby.chat_input)
+                .char_limit(chat::MAX_MESSAGE_LENGTH)
+                .hint_text(i18n::LOCALES.lookup(&config.language, "chat-input").unwrap())
+                .desired_width(f32::INFINITY),
+        );
+        if input_resp.lost_focus() && ui.ctx().input(|i| i.key_pressed(egui::Key::Enter)) {
+            let text = std::mem::take(&mut lobby.chat_input);
+            let _ = sync::block_on(lobby.send_chat(&text));
+            input_resp.request_focus();
+        }
+    });
+}
+
 fn make_commitment(buf: &[u8]) -> [u8; 16] {
     let mut shake128 = sha3::Shake128::default();
     shake128.update(b"tango:lobby:");
@@ -326,13 +395,13 @@ fn make_commitment(buf: &[u8]) -> [u8; 16] {
 
 impl Lobby {
     async fn uncommit(&mut self) -> Result<(), anyhow::Error> {
-        let sender = if let Some(sender) = self.sender.as_mut() {
+        let sender = if let Some(sender) = self.sender.as_ref() {
             sender
         } else {
             anyhow::bail!("no sender?")
         };
 
-        sender.send_uncommit().await?;
+        sender.lock().await.send_uncommit().await?;
         self.local_negotiated_state = None;
         Ok(())
     }
@@ -352,12 +421,12 @@ impl Lobby {
 
         log::info!("nonce = {:02x?}, commitment = {:02x?}", nonce, commitment);
 
-        let sender = if let Some(sender) = self.sender.as_mut() {
+        let sender = if let Some(sender) = self.sender.as_ref() {
             sender
         } else {
             anyhow::bail!("no sender?")
         };
-        sender.send_commit(commitment).await?;
+        sender.lock().await.send_commit(commitment).await?;
         self.local_negotiated_state = Some((negotiated_state, buf));
         Ok(())
     }
@@ -398,12 +467,12 @@ impl Lobby {
     }
 
     async fn send_settings(&mut self, settings: net::protocol::Settings) -> Result<(), anyhow::Error> {
-        let sender = if let Some(sender) = self.sender.as_mut() {
+        let sender = if let Some(sender) = self.sender.as_ref() {
             sender
         } else {
             anyhow::bail!("no sender?")
         };
-        sender.send_settings(settings).await?;
+        sender.lock().await.send_settings(settings).await?;
         Ok(())
     }
 
@@ -436,6 +505,50 @@ impl Lobby {
         Ok(())
     }
 
+    async fn send_set_scoring(&mut self, best_of: u8) -> Result<(), anyhow::Error> {
+        if !self.capabilities.contains(net::protocol::Capabilities::SET_SCORING) {
+            return Ok(());
+        }
+        let sender = if let Some(sender) = self.sender.as_ref() {
+            sender
+        } else {
+            anyhow::bail!("no sender?")
+        };
+        sender.lock().await.send_set_scoring(best_of).await?;
+        Ok(())
+    }
+
+    async fn set_best_of(&mut self, best_of: u8) -> Result<(), anyhow::Error> {
+        if best_of == self.best_of {
+            return Ok(());
+        }
+        self.send_set_scoring(best_of).await?;
+        self.best_of = best_of;
+        Ok(())
+    }
+
+    async fn send_lockstep(&mut self, lockstep: bool) -> Result<(), anyhow::Error> {
+        if !self.capabilities.contains(net::protocol::Capabilities::LOCKSTEP) {
+            return Ok(());
+        }
+        let sender = if let Some(sender) = self.sender.as_ref() {
+            sender
+        } else {
+            anyhow::bail!("no sender?")
+        };
+        sender.lock().await.send_lockstep(lockstep).await?;
+        Ok(())
+    }
+
+    async fn set_lockstep(&mut self, lockstep: bool) -> Result<(), anyhow::Error> {
+        if lockstep == self.lockstep {
+            return Ok(());
+        }
+        self.send_lockstep(lockstep).await?;
+        self.lockstep = lockstep;
+        Ok(())
+    }
+
     async fn set_local_selection(&mut self, selection: &Option<gui::Selection>) -> Result<(), anyhow::Error> {
         if selection.as_ref().map(|selection| {
             (
@@ -506,12 +619,19 @@ impl Lobby {
         Ok(())
     }
 
+    fn lockstep_supported(&self) -> bool {
+        is_lockstep_supported(&self.make_local_settings()) && is_lockstep_supported(&self.remote_settings)
+    }
+
     fn can_ready(&self) -> bool {
-        are_settings_compatible(
-            &self.make_local_settings(),
-            &self.remote_settings,
-            &self.patches_scanner.read(),
-        )
+        self.best_of == self.remote_best_of
+            && self.lockstep == self.remote_lockstep
+            && (!self.lockstep || self.lockstep_supported())
+            && are_settings_compatible(
+                &self.make_local_settings(),
+                &self.remote_settings,
+                &self.patches_scanner.read(),
+            )
     }
 
     fn set_remote_settings(&mut self, settings: net::protocol::Settings, patches_path: &std::path::Path) {
@@ -567,23 +687,38 @@ impl Lobby {
         }
     }
 
+    async fn send_chat(&mut self, text: &str) -> Result<(), anyhow::Error> {
+        let sender = if let Some(sender) = self.sender.as_ref() {
+            sender
+        } else {
+            anyhow::bail!("no sender?")
+        };
+        let text = if let Some(text) = self.chat.lock().add_local(text) {
+            text
+        } else {
+            return Ok(());
+        };
+        sender.lock().await.send_chat(text).await?;
+        Ok(())
+    }
+
     async fn send_pong(&mut self, ts: std::time::SystemTime) -> Result<(), anyhow::Error> {
-        let sender = if let Some(sender) = self.sender.as_mut() {
+        let sender = if let Some(sender) = self.sender.as_ref() {
             sender
         } else {
             anyhow::bail!("no sender?")
         };
-        sender.send_pong(ts).await?;
+        sender.lock().await.send_pong(ts).await?;
         Ok(())
     }
 
     async fn send_ping(&mut self) -> Result<(), anyhow::Error> {
-        let sender = if let Some(sender) = self.sender.as_mut() {
+        let sender = if let Some(sender) = self.sender.as_ref() {
             sender
         } else {
             anyhow::bail!("no sender?")
         };
-        sender.send_ping(std::time::SystemTime::now()).await?;
+        sender.lock().await.send_ping(std::time::SystemTime::now()).await?;
         Ok(())
     }
 }
@@ -598,6 +733,8 @@ async fn run_connection_task(
     patches_scanner: patch::Scanner,
     matchmaking_addr: String,
     link_code: String,
+    queue_request: Option<tango_signaling::QueueRequest>,
+    room: Option<tango_signaling::Room>,
     nickname: String,
     patches_path: std::path::PathBuf,
     replays_path: std::path::PathBuf,
@@ -612,6 +749,18 @@ async fn run_connection_task(
                 let connection_task = connection_task.clone();
                 let cancellation_token = cancellation_token.clone();
                 async move {
+                    let link_code = if let Some(queue_request) = queue_request {
+                        *connection_task.lock().await =
+                            Some(ConnectionTask::InProgress {
+                                state: ConnectionState::Queueing,
+                                cancellation_token:
+                                    cancellation_token.clone(),
+                            });
+                        tango_signaling::queue(&matchmaking_addr, queue_request).await?
+                    } else {
+                        link_code
+                    };
+
                     *connection_task.lock().await =
                         Some(ConnectionTask::InProgress {
                             state: ConnectionState::Signaling,
@@ -631,6 +780,7 @@ async fn run_connection_task(
                             &link_code,
                             use_relay,
                             crate::net::protocol::VERSION as u32,
+                            room,
                         ),
                     )
                     .await.map_err(|e| std::io::Error::new(std::io::ErrorKind::TimedOut, e))??;
@@ -642,27 +792,66 @@ async fn run_connection_task(
                                 cancellation_token.clone(),
                         });
 
-                    let (dc, peer_conn) = pending_conn.await?;
+                    let (dc, peer_conn) = {
+                        let mut pending_conn = pending_conn;
+                        let mut connecting_state = pending_conn.state();
+                        loop {
+                            tokio::select! {
+                                r = &mut pending_conn => {
+                                    break r?;
+                                }
+                                Ok(()) = connecting_state.changed() => {
+                                    let state = match *connecting_state.borrow() {
+                                        tango_signaling::ConnectingState::Waiting => ConnectionState::Waiting,
+                                        tango_signaling::ConnectingState::Reconnecting { attempt } => ConnectionState::Reconnecting(attempt),
+                                    };
+                                    *connection_task.lock().await =
+                                        Some(ConnectionTask::InProgress {
+                                            state,
+                                            cancellation_token:
+                                                cancellation_token.clone(),
+                                        });
+                                    egui_ctx.request_repaint();
+                                }
+                            }
+                        }
+                    };
+                    let is_offerer = peer_conn.local_description().unwrap().sdp_type == datachannel_wrapper::SdpType::Offer;
                     let (dc_tx, dc_rx) = dc.split();
                     let mut sender = net::Sender::new(dc_tx);
                     let mut receiver = net::Receiver::new(dc_rx);
-                    net::negotiate(&mut sender, &mut receiver).await?;
+                    let capabilities = net::negotiate(&mut sender, &mut receiver).await?;
+                    let sender = std::sync::Arc::new(tokio::sync::Mutex::new(sender));
+                    let receiver = std::sync::Arc::new(tokio::sync::Mutex::new(receiver));
+                    let remote_returned_to_lobby = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
 
-                    let default_match_type = {
+                    let (default_match_type, default_best_of) = {
                         let config = config.read();
-                        config.default_match_type
+                        (config.default_match_type, config.default_best_of)
                     };
 
                     let lobby = std::sync::Arc::new(tokio::sync::Mutex::new(Lobby{
                         attention_requested: false,
-                        sender: Some(sender),
+                        sender: Some(sender.clone()),
                         local_selection: None,
                         remote_selection: None,
                         nickname,
                         link_code,
                         match_type: (default_match_type, 0),
+                        best_of: if capabilities.contains(net::protocol::Capabilities::SET_SCORING) {
+                            default_best_of
+                        } else {
+                            0
+                        },
+                        lockstep: false,
+                        capabilities,
+                        chat: std::sync::Arc::new(parking_lot::Mutex::new(chat::Chat::new())),
+                        chat_input: String::new(),
+                        show_chat: false,
                         reveal_setup: false,
                         remote_settings: net::protocol::Settings::default(),
+                        remote_best_of: 0,
+                        remote_lockstep: false,
                         remote_commitment: None,
                         latencies: crate::stats::LatencyCounter::new(5),
                         local_negotiated_state: None,
@@ -673,6 +862,10 @@ async fn run_connection_task(
                         let mut lobby = lobby.lock().await;
                         let settings = lobby.make_local_settings();
                         lobby.send_settings(settings).await?;
+                        let best_of = lobby.best_of;
+                        lobby.send_set_scoring(best_of).await?;
+                        let lockstep = lobby.lockstep;
+                        lobby.send_lockstep(lockstep).await?;
                     }
 
                     *connection_task.lock().await =
@@ -682,148 +875,198 @@ async fn run_connection_task(
                                 cancellation_token.clone(),
                         });
 
-                    let mut remote_chunks = vec![];
                     let mut ping_timer = tokio::time::interval(net::PING_INTERVAL);
-                    'l: loop {
-                        tokio::select! {
-                            _ = ping_timer.tick() => {
-                                lobby.lock().await.send_ping().await?;
-                            }
-                            p = receiver.receive() => {
-                                match p? {
-                                    net::protocol::Packet::Ping(ping) => {
-                                        lobby.lock().await.send_pong(ping.ts).await?;
-                                    },
-                                    net::protocol::Packet::Pong(pong) => {
-                                        let mut lobby = lobby.lock().await;
-                                        if let Ok(d) = std::time::SystemTime::now().duration_since(pong.ts) {
-                                            lobby.latencies.mark(d);
+                    loop {
+                        let mut locked_receiver = receiver.lock().await;
+                        let mut remote_chunks = vec![];
+                        'l: loop {
+                            tokio::select! {
+                                _ = ping_timer.tick() => {
+                                    lobby.lock().await.send_ping().await?;
+                                }
+                                p = locked_receiver.receive() => {
+                                    match p? {
+                                        net::protocol::Packet::Ping(ping) => {
+                                            lobby.lock().await.send_pong(ping.ts).await?;
+                                        },
+                                        net::protocol::Packet::Pong(pong) => {
+                                            let mut lobby = lobby.lock().await;
+                                            if let Ok(d) = std::time::SystemTime::now().duration_since(pong.ts) {
+                                                lobby.latencies.mark(d);
+                                                egui_ctx.request_repaint();
+                                            }
+                                        },
+                                        net::protocol::Packet::Settings(settings) => {
+                                            let mut lobby = lobby.lock().await;
+                                            lobby.set_remote_settings(settings, &patches_path);
+                                            egui_ctx.request_repaint();
+                                        },
+                                        net::protocol::Packet::Commit(commit) => {
+                                            let mut lobby = lobby.lock().await;
+                                            lobby.remote_commitment = Some(commit.commitment);
                                             egui_ctx.request_repaint();
-                                        }
-                                    },
-                                    net::protocol::Packet::Settings(settings) => {
-                                        let mut lobby = lobby.lock().await;
-                                        lobby.set_remote_settings(settings, &patches_path);
-                                        egui_ctx.request_repaint();
-                                    },
-                                    net::protocol::Packet::Commit(commit) => {
-                                        let mut lobby = lobby.lock().await;
-                                        lobby.remote_commitment = Some(commit.commitment);
-                                        egui_ctx.request_repaint();
 
-                                        if lobby.local_negotiated_state.is_some() {
+                                            if lobby.local_negotiated_state.is_some() {
+                                                break 'l;
+                                            }
+                                        },
+                                        net::protocol::Packet::Uncommit(_) => {
+                                            lobby.lock().await.remote_commitment = None;
+                                            egui_ctx.request_repaint();
+                                        },
+                                        net::protocol::Packet::Chunk(chunk) => {
+                                            remote_chunks.push(chunk.chunk);
                                             break 'l;
+                                        },
+                                        net::protocol::Packet::Chat(chat) => {
+                                            let lobby = lobby.lock().await;
+                                            if lobby.capabilities.contains(net::protocol::Capabilities::CHAT) {
+                                                lobby.chat.lock().add_remote(&chat.text);
+                                                egui_ctx.request_repaint();
+                                            }
+                                        },
+                                        net::protocol::Packet::SetScoring(set_scoring) => {
+                                            let mut lobby = lobby.lock().await;
+                                            if lobby.capabilities.contains(net::protocol::Capabilities::SET_SCORING) {
+                                                lobby.remote_best_of = set_scoring.best_of;
+                                                if !lobby.can_ready() {
+                                                    lobby.remote_commitment = None;
+                                                }
+                                                egui_ctx.request_repaint();
+                                            }
+                                        },
+                                        net::protocol::Packet::Lockstep(lockstep) => {
+                                            let mut lobby = lobby.lock().await;
+                                            if lobby.capabilities.contains(net::protocol::Capabilities::LOCKSTEP) {
+                                                lobby.remote_lockstep = lockstep.enabled;
+                                                if !lobby.can_ready() {
+                                                    lobby.remote_commitment = None;
+                                                }
+                                                egui_ctx.request_repaint();
+                                            }
+                                        },
+                                        p => {
+                                            return Err(ConnectionError::Other(anyhow::anyhow!("unexpected packet: {:?}", p)));
                                         }
-                                    },
-                                    net::protocol::Packet::Uncommit(_) => {
-                                        lobby.lock().await.remote_commitment = None;
-                                        egui_ctx.request_repaint();
-                                    },
-                                    net::protocol::Packet::Chunk(chunk) => {
-                                        remote_chunks.push(chunk.chunk);
-                                        break 'l;
-                                    },
-                                    p => {
-                                        return Err(ConnectionError::Other(anyhow::anyhow!("unexpected packet: {:?}", p)));
                                     }
                                 }
                             }
                         }
-                    }
 
-                    log::info!("ending lobby");
+                        log::info!("ending lobby");
 
-                    let (mut sender, match_type, local_settings, remote_selection, remote_settings, remote_commitment, local_negotiated_state, local_selection, link_code) = {
-                        let mut lobby = lobby.lock().await;
-                        let local_settings = lobby.make_local_settings();
-                        let sender = if let Some(sender) = lobby.sender.take() {
-                            sender
-                        } else {
-                            return Err(ConnectionError::Other(anyhow::anyhow!("no sender?")));
+                        let (match_type, best_of, lockstep, local_settings, remote_selection, remote_settings, remote_commitment, local_negotiated_state, local_selection, link_code, chat) = {
+                            let mut lobby = lobby.lock().await;
+                            let local_settings = lobby.make_local_settings();
+                            if lobby.sender.take().is_none() {
+                                return Err(ConnectionError::Other(anyhow::anyhow!("no sender?")));
+                            }
+                            let chat = if lobby.capabilities.contains(net::protocol::Capabilities::CHAT) {
+                                Some(lobby.chat.clone())
+                            } else {
+                                None
+                            };
+                            (lobby.match_type, lobby.best_of, lobby.lockstep, local_settings, lobby.remote_selection.clone(), lobby.remote_settings.clone(), lobby.remote_commitment, lobby.local_negotiated_state.clone(), lobby.local_selection.clone(), lobby.link_code.clone(), chat)
                         };
-                        (sender, lobby.match_type, local_settings, lobby.remote_selection.clone(), lobby.remote_settings.clone(), lobby.remote_commitment, lobby.local_negotiated_state.clone(), lobby.local_selection.clone(), lobby.link_code.clone())
-                    };
 
-                    let remote_selection = if let Some(remote_selection) = remote_selection {
-                        remote_selection
-                    } else {
-                        return Err(ConnectionError::Other(anyhow::anyhow!("missing remote selection?")));
-                    };
+                        let remote_selection = if let Some(remote_selection) = remote_selection {
+                            remote_selection
+                        } else {
+                            return Err(ConnectionError::Other(anyhow::anyhow!("missing remote selection?")));
+                        };
 
-                    let remote_patch_overrides = remote_selection.patch.as_ref().map(|(_, _, version_meta)| version_meta.rom_overrides.clone()).unwrap_or_default();
+                        let remote_patch_overrides = remote_selection.patch.as_ref().map(|(_, _, version_meta)| version_meta.rom_overrides.clone()).unwrap_or_default();
 
-                    let (local_negotiated_state, raw_local_state) = if let Some((negotiated_state, raw_local_state)) = local_negotiated_state {
-                        (negotiated_state, raw_local_state)
-                    } else {
-                        return Err(ConnectionError::Other(anyhow::anyhow!("missing local state?")));
-                    };
-
-                    const CHUNK_SIZE: usize = 32 * 1024;
-                    const CHUNKS_REQUIRED: usize = 5;
-                    for (_, chunk) in std::iter::zip(
-                        0..CHUNKS_REQUIRED,
-                        raw_local_state.chunks(CHUNK_SIZE).chain(std::iter::repeat(&[][..]))
-                     ) {
-                        sender.send_chunk(chunk.to_vec()).await?;
+                        let (local_negotiated_state, raw_local_state) = if let Some((negotiated_state, raw_local_state)) = local_negotiated_state {
+                            (negotiated_state, raw_local_state)
+                        } else {
+                            return Err(ConnectionError::Other(anyhow::anyhow!("missing local state?")));
+                        };
 
-                        if remote_chunks.len() < CHUNKS_REQUIRED {
-                            loop {
-                                match receiver.receive().await? {
-                                    net::protocol::Packet::Ping(ping) => {
-                                        sender.send_pong(ping.ts).await?;
-                                    },
-                                    net::protocol::Packet::Pong(_) => { },
-                                    net::protocol::Packet::Chunk(chunk) => {
-                                        remote_chunks.push(chunk.chunk);
-                                        break;
-                                    },
-                                    p => {
-                                        return Err(ConnectionError::Other(anyhow::format_err!("unexpected packet: {:?}", p)));
+                        let mut locked_sender = sender.lock().await;
+
+                        const CHUNK_SIZE: usize = 32 * 1024;
+                        const CHUNKS_REQUIRED: usize = 5;
+                        for (_, chunk) in std::iter::zip(
+                            0..CHUNKS_REQUIRED,
+                            raw_local_state.chunks(CHUNK_SIZE).chain(std::iter::repeat(&[][..]))
+                         ) {
+                            locked_sender.send_chunk(chunk.to_vec()).await?;
+
+                            if remote_chunks.len() < CHUNKS_REQUIRED {
+                                loop {
+                                    match locked_receiver.receive().await? {
+                                        net::protocol::Packet::Ping(ping) => {
+                                            locked_sender.send_pong(ping.ts).await?;
+                                        },
+                                        net::protocol::Packet::Pong(_) => { },
+                                        net::protocol::Packet::Chat(c) => {
+                                            if let Some(chat) = chat.as_ref() {
+                                                chat.lock().add_remote(&c.text);
+                                            }
+                                        },
+                                        net::protocol::Packet::Chunk(chunk) => {
+                                            remote_chunks.push(chunk.chunk);
+                                            break;
+                                        },
+                                        p => {
+                                            return Err(ConnectionError::Other(anyhow::format_err!("unexpected packet: {:?}", p)));
+                                        }
                                     }
                                 }
                             }
                         }
-                    }
 
-                    let raw_remote_negotiated_state = remote_chunks.into_iter().flatten().collect::<Vec<_>>();
+                        let raw_remote_negotiated_state = remote_chunks.into_iter().flatten().collect::<Vec<_>>();
 
-                    let received_remote_commitment = if let Some(commitment) = remote_commitment {
-                        commitment
-                    } else {
-                        return Err(ConnectionError::Other(anyhow::anyhow!("no remote commitment?")));
-                    };
+                        let received_remote_commitment = if let Some(commitment) = remote_commitment {
+                            commitment
+                        } else {
+                            return Err(ConnectionError::Other(anyhow::anyhow!("no remote commitment?")));
+                        };
 
-                    log::info!("remote commitment = {:02x?}", received_remote_commitment);
+                        log::info!("remote commitment = {:02x?}", received_remote_commitment);
 
-                    if !bool::from(make_commitment(&raw_remote_negotiated_state).ct_eq(&received_remote_commitment)) {
-                        return Err(ConnectionError::Other(anyhow::anyhow!("commitment mismatch?")));
-                    }
+                        if !bool::from(make_commitment(&raw_remote_negotiated_state).ct_eq(&received_remote_commitment)) {
+                            return Err(ConnectionError::Other(anyhow::anyhow!("commitment mismatch?")));
+                        }
 
-                    let raw_remote_negotiated_state = zstd::stream::decode_all(&raw_remote_negotiated_state[..])?;
-                    let remote_negotiated_state = net::protocol::NegotiatedState::deserialize(&raw_remote_negotiated_state)
-                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+                        let raw_remote_negotiated_state = zstd::stream::decode_all(&raw_remote_negotiated_state[..])?;
+                        let remote_negotiated_state = net::protocol::NegotiatedState::deserialize(&raw_remote_negotiated_state)
+                            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
 
-                    let rng_seed = std::iter::zip(local_negotiated_state.nonce, remote_negotiated_state.nonce).map(|(x, y)| x ^ y).collect::<Vec<_>>().try_into().unwrap();
-                    log::info!("session verified! rng seed = {:02x?}", rng_seed);
+                        let rng_seed = std::iter::zip(local_negotiated_state.nonce, remote_negotiated_state.nonce).map(|(x, y)| x ^ y).collect::<Vec<_>>().try_into().unwrap();
+                        log::info!("session verified! rng seed = {:02x?}", rng_seed);
 
-                    let local_selection = if let Some(local_selection) = local_selection {
-                        local_selection
-                    } else {
-                        return Err(ConnectionError::Other(anyhow::anyhow!("attempted to start match in invalid state")));
-                    };
+                        let local_selection = if let Some(local_selection) = local_selection {
+                            local_selection
+                        } else {
+                            return Err(ConnectionError::Other(anyhow::anyhow!("attempted to start match in invalid state")));
+                        };
 
-                    sender.send_start_match().await?;
-                    match receiver.receive().await? {
-                        net::protocol::Packet::StartMatch(_) => {},
-                        p => return Err(ConnectionError::Other(anyhow::anyhow!("unexpected packet when expecting start match: {:?}", p))),
-                    }
+                        locked_sender.send_start_match().await?;
+                        loop {
+                            match locked_receiver.receive().await? {
+                                net::protocol::Packet::StartMatch(_) => {
+                                    break;
+                                },
+                                net::protocol::Packet::Chat(c) => {
+                                    if let Some(chat) = chat.as_ref() {
+                                        chat.lock().add_remote(&c.text);
+                                    }
+                                },
+                                p => return Err(ConnectionError::Other(anyhow::anyhow!("unexpected packet when expecting start match: {:?}", p))),
+                            }
+                        }
 
-                    log::info!("starting session");
-                    let is_offerer = peer_conn.local_description().unwrap().sdp_type == datachannel_wrapper::SdpType::Offer;
-                    {
-                        *session.lock() = Some(session::Session::new_pvp(
+                        // The match takes over the connection from here.
+                        drop(locked_sender);
+                        drop(locked_receiver);
+
+                        log::info!("starting session");
+                        let s = session::Session::new_pvp(
                             config.clone(),
-                            audio_binder,
+                            audio_binder.clone(),
                             link_code,
                             local_selection.patch.as_ref()
                                 .map(|(_, _, metadata)| metadata.netplay_compatibility.clone())
@@ -844,19 +1087,80 @@ async fn run_connection_task(
                             &remote_selection.rom,
                             remote_selection.game.save_from_wram(&remote_negotiated_state.save_data)?,
                             emu_tps_counter.clone(),
-                            sender,
-                            receiver,
-                            peer_conn,
+                            session::Opponent::Peer {
+                                sender: sender.clone(),
+                                receiver: receiver.clone(),
+                                chat: chat.clone(),
+                                remote_returned_to_lobby: remote_returned_to_lobby.clone(),
+                            },
                             is_offerer,
-                            replays_path,
+                            replays_path.clone(),
                             match_type,
+                            best_of,
+                            lockstep,
                             rng_seed,
-                        )?);
-                    }
-                    egui_ctx.request_repaint();
-                    *connection_task.lock().await = None;
+                        )?;
+                        let session_cancellation_token = match s.mode() {
+                            session::Mode::PvP(pvp) => pvp.cancellation_token().clone(),
+                            _ => unreachable!(),
+                        };
+                        let completion_token = s.completion_token().clone();
+                        *session.lock() = Some(s);
+                        egui_ctx.request_repaint();
+
+                        if !capabilities.contains(net::protocol::Capabilities::REMATCH) {
+                            *connection_task.lock().await = None;
+
+                            // Keep the connection open until the session is done with it.
+                            session_cancellation_token.cancelled().await;
+                            return Ok(());
+                        }
+
+                        session_cancellation_token.cancelled().await;
+
+                        // Only offer the same saves again if the match actually finished: if we quit out of it, the other side should not be pulled into another one.
+                        let rematch = completion_token.is_complete();
+                        log::info!("session ended, returning to lobby (rematch = {})", rematch);
+
+                        let previous_save_data = {
+                            let mut lobby = lobby.lock().await;
+                            lobby.remote_commitment = None;
+                            lobby.local_negotiated_state.take().map(|(negotiated_state, _)| negotiated_state.save_data)
+                        };
+                        egui_ctx.request_repaint();
 
-                    Ok(())
+                        sender.lock().await.send_return_to_lobby().await?;
+                        if !remote_returned_to_lobby.swap(false, std::sync::atomic::Ordering::SeqCst) {
+                            let mut locked_receiver = receiver.lock().await;
+                            loop {
+                                match locked_receiver.receive().await? {
+                                    net::protocol::Packet::ReturnToLobby(_) => {
+                                        break;
+                                    },
+                                    net::protocol::Packet::Ping(ping) => {
+                                        sender.lock().await.send_pong(ping.ts).await?;
+                                    },
+                                    net::protocol::Packet::Pong(_) | net::protocol::Packet::Input(_) => { },
+                                    net::protocol::Packet::Chat(c) => {
+                                        if let Some(chat) = chat.as_ref() {
+                                            chat.lock().add_remote(&c.text);
+                                            egui_ctx.request_repaint();
+                                        }
+                                    },
+                                    p => return Err(ConnectionError::Other(anyhow::anyhow!("unexpected packet when expecting return to lobby: {:?}", p))),
+                                }
+                            }
+                        }
+
+                        {
+                            let mut lobby = lobby.lock().await;
+                            lobby.sender = Some(sender.clone());
+                            if let (true, Some(save_data)) = (rematch, previous_save_data) {
+                                lobby.commit(&save_data).await?;
+                            }
+                        }
+                        egui_ctx.request_repaint();
+                    }
                 }
             }
             => {
@@ -899,8 +1203,10 @@ enum ConnectionTask {
 
 enum ConnectionState {
     Starting,
+    Queueing,
     Signaling,
     Waiting,
+    Reconnecting(u32),
     InLobby(std::sync::Arc<tokio::sync::Mutex<Lobby>>),
 }
 
@@ -909,6 +1215,7 @@ pub struct State {
     show_link_code: bool,
     connection_task: std::sync::Arc<tokio::sync::Mutex<Option<ConnectionTask>>>,
     save_select_state: gui::save_select_view::State,
+    rooms_window: Option<gui::rooms_window::State>,
 }
 
 impl State {
@@ -918,6 +1225,7 @@ impl State {
             show_link_code: false,
             connection_task: std::sync::Arc::new(tokio::sync::Mutex::new(None)),
             save_select_state: gui::save_select_view::State::new(selection),
+            rooms_window: None,
         }
     }
 }
@@ -957,6 +1265,8 @@ fn show_lobby_table(
         .size(egui_extras::Size::exact(row_height + spacing_y))
         .size(egui_extras::Size::exact(row_height + spacing_y))
         .size(egui_extras::Size::exact(row_height + spacing_y))
+        .size(egui_extras::Size::exact(row_height + spacing_y))
+        .size(egui_extras::Size::exact(row_height + spacing_y))
         .vertical(|mut outer_strip| {
             const CELL_WIDTH: f32 = 200.0;
             outer_strip.strip(|sb| {
@@ -1031,7 +1341,7 @@ fn show_lobby_table(
                                             .unwrap()
                                     } else {
                                         i18n::LOCALES
-                                            .lookup(&config.language, "play-details-game.unknown")
+                                            .lookup(&config.language, "play-details-game.unrecognized")
                                             .unwrap()
                                     });
                                     if let Some((patch_name, version, _)) = local_selection.patch.as_ref() {
@@ -1165,6 +1475,115 @@ fn show_lobby_table(
                     });
             });
 
+            outer_strip.strip(|sb| {
+                sb.size(egui_extras::Size::remainder())
+                    .size(egui_extras::Size::exact(CELL_WIDTH))
+                    .size(egui_extras::Size::exact(CELL_WIDTH))
+                    .horizontal(|mut strip| {
+                        let language = config.language.clone();
+                        let best_of_label = |best_of: u8| {
+                            if best_of == 0 {
+                                i18n::LOCALES
+                                    .lookup(&language, "play-details-best-of.unlimited")
+                                    .unwrap()
+                            } else {
+                                i18n::LOCALES
+                                    .lookup_with_args(
+                                        &language,
+                                        "play-details-best-of.format",
+                                        &std::collections::HashMap::from([("n", best_of.into())]),
+                                    )
+                                    .unwrap()
+                            }
+                        };
+                        strip.cell(|ui| {
+                            ui.horizontal(|ui| {
+                                ui.strong(i18n::LOCALES.lookup(&config.language, "play-details-best-of").unwrap());
+                                if lobby.remote_settings.game_info.is_some() && lobby.best_of != lobby.remote_best_of {
+                                    gui::warning::show(
+                                        ui,
+                                        i18n::LOCALES
+                                            .lookup(&config.language, "lobby-issue-best-of-mismatch")
+                                            .unwrap(),
+                                    );
+                                }
+                            });
+                        });
+                        strip.cell(|ui| {
+                            ui.add_enabled_ui(
+                                lobby.capabilities.contains(net::protocol::Capabilities::SET_SCORING),
+                                |ui| {
+                                    egui::ComboBox::new("start-best-of-combobox", "")
+                                        .width(150.0)
+                                        .selected_text(best_of_label(lobby.best_of))
+                                        .show_ui(ui, |ui| {
+                                            let mut best_of = lobby.best_of;
+                                            for n in [0, 1, 3, 5, 7] {
+                                                ui.selectable_value(&mut best_of, n, best_of_label(n));
+                                            }
+                                            if best_of != lobby.best_of {
+                                                config.default_best_of = best_of;
+                                                let _ = sync::block_on(lobby.set_best_of(best_of));
+                                            }
+                                        });
+                                },
+                            );
+                        });
+                        strip.cell(|ui| {
+                            ui.label(best_of_label(lobby.remote_best_of));
+                        });
+                    });
+            });
+
+            outer_strip.strip(|sb| {
+                sb.size(egui_extras::Size::remainder())
+                    .size(egui_extras::Size::exact(CELL_WIDTH))
+                    .size(egui_extras::Size::exact(CELL_WIDTH))
+                    .horizontal(|mut strip| {
+                        strip.cell(|ui| {
+                            ui.horizontal(|ui| {
+                                ui.strong(i18n::LOCALES.lookup(&config.language, "play-details-lockstep").unwrap())
+                                    .on_hover_text(
+                                        i18n::LOCALES
+                                            .lookup(&config.language, "play-details-lockstep.description")
+                                            .unwrap(),
+                                    );
+                                if lobby.remote_settings.game_info.is_some() && lobby.lockstep != lobby.remote_lockstep
+                                {
+                                    gui::warning::show(
+                                        ui,
+                                        i18n::LOCALES
+                                            .lookup(&config.language, "lobby-issue-lockstep-mismatch")
+                                            .unwrap(),
+                                    );
+                                }
+                                if lobby.lockstep && !lobby.lockstep_supported() {
+                                    gui::warning::show(
+                                        ui,
+                                        i18n::LOCALES
+                                            .lookup(&config.language, "lobby-issue-lockstep-unsupported")
+                                            .unwrap(),
+                                    );
+                                }
+                            });
+                        });
+                        strip.cell(|ui| {
+                            ui.add_enabled_ui(
+                                lobby.capabilities.contains(net::protocol::Capabilities::LOCKSTEP)
+                                    && (lobby.lockstep || lobby.lockstep_supported()),
+                                |ui| {
+                                    let mut checked = lobby.lockstep;
+                                    ui.checkbox(&mut checked, "");
+                                    let _ = sync::block_on(lobby.set_lockstep(checked));
+                                },
+                            );
+                        });
+                        strip.cell(|ui| {
+                            ui.checkbox(&mut lobby.remote_lockstep.clone(), "");
+                        });
+                    });
+            });
+
             outer_strip.strip(|sb| {
                 sb.size(egui_extras::Size::remainder())
                     .size(egui_extras::Size::exact(CELL_WIDTH))
@@ -1253,6 +1672,7 @@ fn show_bottom_pane(
     link_code: &mut String,
     show_link_code: &mut bool,
     init_link_code: &mut Option<String>,
+    rooms_window: &mut Option<gui::rooms_window::State>,
 ) {
     let selection = &mut shared_root_state.selection;
 
@@ -1277,6 +1697,38 @@ fn show_bottom_pane(
                     )) => i18n::LOCALES
                         .lookup(&config.language, "connection-error-protocol-version-too-old")
                         .unwrap(),
+                    ConnectionError::Signaling(tango_signaling::Error::ServerAbort(
+                        tango_signaling::AbortReason::RateLimited,
+                    )) => i18n::LOCALES
+                        .lookup(&config.language, "connection-error-rate-limited")
+                        .unwrap(),
+                    ConnectionError::Signaling(tango_signaling::Error::ServerAbort(
+                        tango_signaling::AbortReason::ServerFull,
+                    )) => i18n::LOCALES
+                        .lookup(&config.language, "connection-error-server-full")
+                        .unwrap(),
+                    ConnectionError::Signaling(tango_signaling::Error::ServerAbort(
+                        tango_signaling::AbortReason::IdleTimeout,
+                    )) => i18n::LOCALES
+                        .lookup(&config.language, "connection-error-idle-timeout")
+                        .unwrap(),
+                    ConnectionError::Signaling(tango_signaling::Error::ServerAbort(
+                        tango_signaling::AbortReason::Banned,
+                    )) => i18n::LOCALES
+                        .lookup(&config.language, "connection-error-banned")
+                        .unwrap(),
+                    ConnectionError::Signaling(tango_signaling::Error::ServerAbort(
+                        tango_signaling::AbortReason::ClosedByAdmin,
+                    )) => i18n::LOCALES
+                        .lookup(&config.language, "connection-error-closed-by-admin")
+                        .unwrap(),
+                    ConnectionError::Signaling(tango_signaling::Error::ServerMaintenance(message)) => i18n::LOCALES
+                        .lookup_with_args(
+                            &config.language,
+                            "connection-error-maintenance",
+                            &std::collections::HashMap::from([("message", message.clone().into())]),
+                        )
+                        .unwrap(),
                     ConnectionError::Negotiation(net::NegotiationError::RemoteProtocolVersionTooNew) => i18n::LOCALES
                         .lookup(&config.language, "connection-error-remote-protocol-version-too-new")
                         .unwrap(),
@@ -1329,7 +1781,11 @@ fn show_bottom_pane(
                 }) = connection_task.as_ref()
                 {
                     match connection_state {
-                        ConnectionState::Starting | ConnectionState::Signaling | ConnectionState::Waiting => {
+                        ConnectionState::Starting
+                        | ConnectionState::Queueing
+                        | ConnectionState::Signaling
+                        | ConnectionState::Waiting
+                        | ConnectionState::Reconnecting(_) => {
                             ui.horizontal(|ui| {
                                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
                                     if ui
@@ -1349,12 +1805,25 @@ fn show_bottom_pane(
                                                 ConnectionState::Starting => i18n::LOCALES
                                                     .lookup(&config.language, "play-connection-task-starting")
                                                     .unwrap(),
+                                                ConnectionState::Queueing => i18n::LOCALES
+                                                    .lookup(&config.language, "play-connection-task-queueing")
+                                                    .unwrap(),
                                                 ConnectionState::Signaling => i18n::LOCALES
                                                     .lookup(&config.language, "play-connection-task-signaling")
                                                     .unwrap(),
                                                 ConnectionState::Waiting => i18n::LOCALES
                                                     .lookup(&config.language, "play-connection-task-waiting")
                                                     .unwrap(),
+                                                ConnectionState::Reconnecting(attempt) => i18n::LOCALES
+                                                    .lookup_with_args(
+                                                        &config.language,
+                                                        "play-connection-task-reconnecting",
+                                                        &std::collections::HashMap::from([(
+                                                            "attempt",
+                                                            (*attempt).into(),
+                                                        )]),
+                                                    )
+                                                    .unwrap(),
                                                 _ => unreachable!(),
                                             });
                                         });
@@ -1401,6 +1870,13 @@ fn show_bottom_pane(
                             ui.add_enabled_ui(lobby.local_negotiated_state.is_none() && lobby.sender.is_some(), |ui| {
                                 show_lobby_table(ui, cancellation_token, config, &mut lobby, &roms, &patches);
                             });
+
+                            if lobby.capabilities.contains(net::protocol::Capabilities::CHAT) {
+                                ui.separator();
+                                ui.add_enabled_ui(lobby.sender.is_some(), |ui| {
+                                    show_lobby_chat(ui, config, &mut lobby);
+                                });
+                            }
                         }
                     }
                 } else {
@@ -1430,6 +1906,8 @@ fn show_bottom_pane(
                     };
 
                     let mut submitted = false;
+                    let mut queue_request = None;
+                    let mut room = None;
                     if cancellation_token.is_none() {
                         if ui
                             .add_enabled(
@@ -1454,6 +1932,68 @@ fn show_bottom_pane(
                             let _ = shared_root_state.clipboard.set_text(link_code.clone());
                         }
 
+                        if ui
+                            .add_enabled(
+                                !error_window_open && selection.is_some(),
+                                egui::Button::new(egui::RichText::new("🌐")),
+                            )
+                            .on_hover_text(i18n::LOCALES.lookup(&config.language, "play-queue").unwrap())
+                            .clicked()
+                        {
+                            if let Some(selection) = selection.as_ref() {
+                                queue_request = Some(make_queue_request(config, selection));
+                                link_code.clear();
+                                submitted = true;
+                            }
+                        }
+
+                        if ui
+                            .add_enabled(
+                                !error_window_open && selection.is_some(),
+                                egui::Button::new(egui::RichText::new("📢")),
+                            )
+                            .on_hover_text(i18n::LOCALES.lookup(&config.language, "play-host-room").unwrap())
+                            .clicked()
+                        {
+                            if let Some(selection) = selection.as_ref() {
+                                room = Some(make_room(config, selection));
+                                *link_code = randomcode::generate(&config.language);
+                                submitted = true;
+                            }
+                        }
+
+                        if ui
+                            .add_enabled(!error_window_open, egui::Button::new(egui::RichText::new("🏠")))
+                            .on_hover_text(i18n::LOCALES.lookup(&config.language, "play-rooms").unwrap())
+                            .clicked()
+                        {
+                            gui::rooms_window::open(ui.ctx(), config, rooms_window);
+                        }
+
+                        if ui
+                            .add_enabled(
+                                !error_window_open && selection.is_some(),
+                                egui::Button::new(egui::RichText::new("🤖")),
+                            )
+                            .on_hover_text(i18n::LOCALES.lookup(&config.language, "play-bot").unwrap())
+                            .clicked()
+                        {
+                            if let Some(selection) = selection.as_ref() {
+                                if let Err(e) = start_bot_session(
+                                    ui.ctx().clone(),
+                                    config,
+                                    shared_root_state.config.clone(),
+                                    shared_root_state.audio_binder.clone(),
+                                    shared_root_state.emu_tps_counter.clone(),
+                                    shared_root_state.session.clone(),
+                                    connection_task_arc.clone(),
+                                    selection,
+                                ) {
+                                    *connection_task = Some(ConnectionTask::Failed(ConnectionError::Other(e)));
+                                }
+                            }
+                        }
+
                         if config.streamer_mode
                             && ui
                                 .selectable_label(*show_link_code, "👁️")
@@ -1470,6 +2010,9 @@ fn show_bottom_pane(
                         let was_ready = ready;
                         ui.add_enabled(
                             selection.is_some()
+                                && lobby.best_of == lobby.remote_best_of
+                                && lobby.lockstep == lobby.remote_lockstep
+                                && (!lobby.lockstep || lobby.lockstep_supported())
                                 && are_settings_compatible(
                                     &lobby.make_local_settings(),
                                     &lobby.remote_settings,
@@ -1543,13 +2086,20 @@ fn show_bottom_pane(
                         submitted = true;
                     }
 
+                    if let Some(session_id) = gui::rooms_window::show(ui.ctx(), config, rooms_window) {
+                        if cancellation_token.is_none() && !error_window_open {
+                            *link_code = session_id;
+                            submitted = true;
+                        }
+                    }
+
                     if submitted {
                         let audio_binder = shared_root_state.audio_binder.clone();
                         let egui_ctx = ui.ctx().clone();
                         let session = shared_root_state.session.clone();
                         let emu_tps_counter = shared_root_state.emu_tps_counter.clone();
 
-                        if !link_code.is_empty() {
+                        if !link_code.is_empty() || queue_request.is_some() {
                             let cancellation_token = tokio_util::sync::CancellationToken::new();
                             *connection_task = Some(ConnectionTask::InProgress {
                                 state: ConnectionState::Starting,
@@ -1563,6 +2113,8 @@ fn show_bottom_pane(
                                     config::DEFAULT_MATCHMAKING_ENDPOINT.to_string()
                                 };
                                 let link_code = link_code.to_owned();
+                                let queue_request = queue_request.take();
+                                let room = room.take();
                                 let nickname = config.nickname.clone().unwrap_or_default();
                                 let patches_path = config.patches_path();
                                 let replays_path = config.replays_path();
@@ -1581,6 +2133,8 @@ fn show_bottom_pane(
                                         patches_scanner,
                                         matchmaking_endpoint,
                                         link_code,
+                                        queue_request,
+                                        room,
                                         nickname,
                                         patches_path,
                                         replays_path,
@@ -1599,6 +2153,14 @@ fn show_bottom_pane(
                                 .patch
                                 .as_ref()
                                 .map(|(name, version, _)| (name.clone(), version.clone()));
+                            let rewind_buffer_secs = config.rewind_buffer_secs;
+                            let cheats = cheats::load(&cheats::path(config, game))
+                                .map(|cheats| cheats::compile(&cheats))
+                                .unwrap_or_else(|e| {
+                                    log::error!("failed to load cheats: {:?}", e);
+                                    vec![]
+                                });
+                            let script = scripting::load_from_config(config, false);
                             let save_file = std::fs::OpenOptions::new()
                                 .create(true)
                                 .write(true)
@@ -1616,6 +2178,9 @@ fn show_bottom_pane(
                                         &rom,
                                         save_file,
                                         emu_tps_counter,
+                                        rewind_buffer_secs,
+                                        cheats,
+                                        script,
                                     )
                                     .unwrap(),
                                 ); // TODO: Don't unwrap maybe
@@ -1629,6 +2194,159 @@ fn show_bottom_pane(
     });
 }
 
+fn make_queue_request(config: &config::Config, selection: &gui::Selection) -> tango_signaling::QueueRequest {
+    let (family, _) = selection.game.gamedb_entry().family_and_variant;
+    tango_signaling::QueueRequest {
+        protocol_version: net::protocol::VERSION as u32,
+        game_family: family.to_string(),
+        patch_name: selection.patch.as_ref().map(|(name, _, _)| name.clone()),
+        patch_version: selection.patch.as_ref().map(|(_, version, _)| version.to_string()),
+        netplay_compatibility: selection
+            .patch
+            .as_ref()
+            .map(|(_, _, metadata)| metadata.netplay_compatibility.clone())
+            .unwrap_or(family.to_owned()),
+        match_type: config.default_match_type as u32,
+        match_subtype: 0,
+    }
+}
+
+fn make_room(config: &config::Config, selection: &gui::Selection) -> tango_signaling::Room {
+    let (family, variant) = selection.game.gamedb_entry().family_and_variant;
+    tango_signaling::Room {
+        nickname: config.nickname.clone().unwrap_or_default(),
+        game_family: family.to_string(),
+        game_variant: variant as u32,
+        patch_name: selection.patch.as_ref().map(|(name, _, _)| name.clone()),
+        patch_version: selection.patch.as_ref().map(|(_, version, _)| version.to_string()),
+        netplay_compatibility: selection
+            .patch
+            .as_ref()
+            .map(|(_, _, metadata)| metadata.netplay_compatibility.clone())
+            .unwrap_or(family.to_owned()),
+        match_type: config.default_match_type as u32,
+        match_subtype: 0,
+    }
+}
+
+fn make_bot_policy(config: &config::Config) -> Result<Box<dyn tango_pvp::bot::Policy + Send + Sync>, anyhow::Error> {
+    Ok(match config.bot_policy {
+        config::BotPolicy::Idle => Box::new(tango_pvp::bot::IdlePolicy),
+        config::BotPolicy::Random => Box::new(tango_pvp::bot::RandomPolicy::new(rand_pcg::Mcg128Xsl64::new(
+            rand::random(),
+        ))),
+        config::BotPolicy::Replay => {
+            let f = std::fs::File::open(&config.bot_replay_path)?;
+            let replay = tango_pvp::replay::Replay::decode(f)?;
+            Box::new(tango_pvp::bot::ReplayPolicy::new(&replay))
+        }
+        config::BotPolicy::Script => Box::new(tango_pvp::bot::ScriptPolicy::parse(&config.bot_script)?),
+    })
+}
+
+fn start_bot_session(
+    egui_ctx: egui::Context,
+    config: &config::Config,
+    config_arc: std::sync::Arc<parking_lot::RwLock<config::Config>>,
+    audio_binder: audio::LateBinder,
+    emu_tps_counter: std::sync::Arc<parking_lot::Mutex<stats::Counter>>,
+    session: std::sync::Arc<parking_lot::Mutex<Option<session::Session>>>,
+    connection_task: std::sync::Arc<tokio::sync::Mutex<Option<ConnectionTask>>>,
+    selection: &gui::Selection,
+) -> Result<(), anyhow::Error> {
+    let policy = make_bot_policy(config)?;
+
+    let game = selection.game;
+    let rom = selection.rom.clone();
+    let save_data = selection.save.save.as_raw_wram().to_vec();
+    let patch = selection.patch.clone();
+    let replays_path = config.replays_path();
+
+    let match_type = (
+        if (config.default_match_type as usize) < game.match_types().len() {
+            config.default_match_type
+        } else {
+            0
+        },
+        0,
+    );
+    let best_of = config.default_best_of;
+
+    let (family, variant) = game.gamedb_entry().family_and_variant;
+    let game_info = net::protocol::GameInfo {
+        family_and_variant: (family.to_string(), variant),
+        patch: patch.as_ref().map(|(name, version, _)| net::protocol::PatchInfo {
+            name: name.clone(),
+            version: version.clone(),
+        }),
+    };
+    let local_settings = net::protocol::Settings {
+        nickname: config.nickname.clone().unwrap_or_default(),
+        match_type,
+        game_info: Some(game_info.clone()),
+        available_games: vec![],
+        available_patches: vec![],
+        reveal_setup: true,
+    };
+    let remote_settings = net::protocol::Settings {
+        nickname: i18n::LOCALES.lookup(&config.language, "play-bot-nickname").unwrap(),
+        ..local_settings.clone()
+    };
+
+    let mut rng_seed = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut rng_seed);
+
+    // We have to run this in a thread in order to lock main_view safely. Furthermore, we have to use a real thread because of parking_lot::Mutex.
+    tokio::task::spawn_blocking(move || {
+        let patch_overrides = patch
+            .as_ref()
+            .map(|(_, _, meta)| meta.rom_overrides.clone())
+            .unwrap_or_default();
+        let s = (|| {
+            session::Session::new_pvp(
+                config_arc,
+                audio_binder,
+                "bot".to_string(),
+                patch
+                    .as_ref()
+                    .map(|(_, _, metadata)| metadata.netplay_compatibility.clone())
+                    .unwrap_or(game.gamedb_entry().family_and_variant.0.to_owned()),
+                local_settings,
+                game,
+                patch.as_ref().map(|(name, version, _)| (name.clone(), version.clone())),
+                &patch_overrides,
+                &rom,
+                game.save_from_wram(&save_data)?,
+                remote_settings,
+                game,
+                &patch_overrides,
+                &rom,
+                game.save_from_wram(&save_data)?,
+                emu_tps_counter,
+                session::Opponent::Bot(policy),
+                true,
+                replays_path,
+                match_type,
+                best_of,
+                false,
+                rng_seed,
+            )
+        })();
+        match s {
+            Ok(s) => {
+                *session.lock() = Some(s);
+            }
+            Err(e) => {
+                log::error!("failed to start bot session: {:?}", e);
+                *connection_task.blocking_lock() = Some(ConnectionTask::Failed(ConnectionError::Other(e)));
+            }
+        }
+        egui_ctx.request_repaint();
+    });
+
+    Ok(())
+}
+
 pub fn show(
     ui: &mut egui::Ui,
     config: &mut config::Config,
@@ -1652,6 +2370,7 @@ pub fn show(
         &mut state.link_code,
         &mut state.show_link_code,
         init_link_code,
+        &mut state.rooms_window,
     );
 
     egui::CentralPanel::default()
@@ -1,5 +1,5 @@
 use super::{memoize::ResultCacheSingle, replay_dump_window::ReplayDumpWindow};
-use crate::{config, game, gui, i18n, patch, scanner, session};
+use crate::{config, game, gui, i18n, patch, scanner, scripting, session};
 use fluent_templates::Loader;
 use std::{rc::Rc, sync::Arc};
 use tango_dataview::save::Save;
@@ -446,6 +446,7 @@ pub fn show(
                                 let emu_tps_counter = shared_root_state.emu_tps_counter.clone();
                                 let replay = replay.clone();
                                 let session = shared_root_state.session.clone();
+                                let script = scripting::load_from_config(config, true);
 
                                 move || {
                                     *session.lock() = Some(
@@ -456,6 +457,7 @@ pub fn show(
                                             &rom,
                                             emu_tps_counter,
                                             &replay,
+                                            script,
                                         )
                                         .unwrap(),
                                     ); // TODO: Don't unwrap maybe
@@ -0,0 +1,225 @@
+use fluent_templates::Loader;
+
+use crate::{config, game, i18n};
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct Room {
+    pub session_id: String,
+    pub nickname: String,
+    pub game_family: String,
+    pub game_variant: u32,
+    pub patch_name: Option<String>,
+    pub patch_version: Option<String>,
+    pub netplay_compatibility: String,
+    pub match_type: (u32, u32),
+    pub open_for_secs: u64,
+}
+
+enum Listing {
+    Loading,
+    Loaded(Vec<Room>),
+    Failed(String),
+}
+
+pub struct State {
+    listing: std::sync::Arc<parking_lot::Mutex<Listing>>,
+}
+
+impl State {
+    pub fn new() -> Self {
+        Self {
+            listing: std::sync::Arc::new(parking_lot::Mutex::new(Listing::Loading)),
+        }
+    }
+
+    fn refresh(&self, egui_ctx: egui::Context, matchmaking_endpoint: String) {
+        *self.listing.lock() = Listing::Loading;
+        let listing = self.listing.clone();
+        tokio::task::spawn(async move {
+            *listing.lock() = match fetch(&matchmaking_endpoint).await {
+                Ok(rooms) => Listing::Loaded(rooms),
+                Err(e) => {
+                    log::error!("failed to fetch rooms: {:?}", e);
+                    Listing::Failed(format!("{}", e))
+                }
+            };
+            egui_ctx.request_repaint();
+        });
+    }
+}
+
+fn matchmaking_endpoint(config: &config::Config) -> String {
+    if !config.matchmaking_endpoint.is_empty() {
+        config.matchmaking_endpoint.clone()
+    } else {
+        config::DEFAULT_MATCHMAKING_ENDPOINT.to_string()
+    }
+}
+
+/// The rooms list is served over plain HTTP from the same host as the signaling websocket.
+fn rooms_url(matchmaking_endpoint: &str) -> Result<reqwest::Url, anyhow::Error> {
+    let mut url = reqwest::Url::parse(matchmaking_endpoint)?;
+    let scheme = match url.scheme() {
+        "wss" => "https",
+        "ws" => "http",
+        scheme => anyhow::bail!("unsupported matchmaking endpoint scheme: {}", scheme),
+    };
+    url.set_scheme(scheme)
+        .map_err(|_| anyhow::anyhow!("could not set scheme to {}", scheme))?;
+    url.set_path("/rooms");
+    Ok(url)
+}
+
+pub async fn fetch(matchmaking_endpoint: &str) -> Result<Vec<Room>, anyhow::Error> {
+    let url = rooms_url(matchmaking_endpoint)?;
+    Ok(tokio::time::timeout(std::time::Duration::from_secs(30), async {
+        reqwest::Client::new()
+            .get(url)
+            .header("User-Agent", "tango")
+            .send()
+            .await?
+            .json::<Vec<Room>>()
+            .await
+    })
+    .await??)
+}
+
+/// Returns the session ID of the room to join, if one was picked.
+pub fn show(ctx: &egui::Context, config: &config::Config, state: &mut Option<State>) -> Option<String> {
+    let matchmaking_endpoint = matchmaking_endpoint(config);
+
+    let mut open = state.is_some();
+    let mut selected = None;
+    egui::Window::new(format!(
+        "🏠 {}",
+        i18n::LOCALES.lookup(&config.language, "rooms").unwrap()
+    ))
+    .id(egui::Id::new("rooms-window"))
+    .open(&mut open)
+    .default_width(480.0)
+    .show(ctx, |ui| {
+        let Some(state) = state.as_ref() else {
+            return;
+        };
+
+        if ui
+            .button(format!(
+                "🔄 {}",
+                i18n::LOCALES.lookup(&config.language, "rooms-refresh").unwrap()
+            ))
+            .clicked()
+        {
+            state.refresh(ui.ctx().clone(), matchmaking_endpoint.clone());
+        }
+        ui.separator();
+
+        let listing = state.listing.lock();
+        let rooms = match &*listing {
+            Listing::Loading => {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label(i18n::LOCALES.lookup(&config.language, "rooms-loading").unwrap());
+                });
+                return;
+            }
+            Listing::Failed(e) => {
+                ui.label(
+                    i18n::LOCALES
+                        .lookup_with_args(
+                            &config.language,
+                            "rooms-error",
+                            &std::collections::HashMap::from([("error", e.clone().into())]),
+                        )
+                        .unwrap(),
+                );
+                return;
+            }
+            Listing::Loaded(rooms) => rooms,
+        };
+
+        if rooms.is_empty() {
+            ui.label(i18n::LOCALES.lookup(&config.language, "rooms-empty").unwrap());
+            return;
+        }
+
+        egui::ScrollArea::vertical().auto_shrink([false, true]).show(ui, |ui| {
+            egui::Grid::new("rooms-grid")
+                .num_columns(5)
+                .striped(true)
+                .show(ui, |ui| {
+                    for room in rooms.iter() {
+                        ui.strong(&room.nickname);
+
+                        let game = u8::try_from(room.game_variant)
+                            .ok()
+                            .and_then(|variant| game::find_by_family_and_variant(&room.game_family, variant));
+                        ui.vertical(|ui| {
+                            ui.label(if game.is_some() {
+                                i18n::LOCALES
+                                    .lookup(&config.language, &format!("game-{}", room.game_family))
+                                    .unwrap()
+                            } else {
+                                i18n::LOCALES
+                                    .lookup(&config.language, "play-details-game.unrecognized")
+                                    .unwrap()
+                            });
+                            if let (Some(patch_name), Some(patch_version)) =
+                                (room.patch_name.as_ref(), room.patch_version.as_ref())
+                            {
+                                ui.small(format!("{} v{}", patch_name, patch_version));
+                            }
+                        });
+
+                        ui.label(
+                            game.and_then(|_| {
+                                i18n::LOCALES.lookup(
+                                    &config.language,
+                                    &format!(
+                                        "game-{}.match-type-{}-{}",
+                                        room.game_family, room.match_type.0, room.match_type.1
+                                    ),
+                                )
+                            })
+                            .unwrap_or_default(),
+                        );
+
+                        ui.label(
+                            i18n::LOCALES
+                                .lookup_with_args(
+                                    &config.language,
+                                    "rooms-open-for",
+                                    &std::collections::HashMap::from([("minutes", (room.open_for_secs / 60).into())]),
+                                )
+                                .unwrap(),
+                        );
+
+                        if ui
+                            .button(i18n::LOCALES.lookup(&config.language, "rooms-join").unwrap())
+                            .clicked()
+                        {
+                            selected = Some(room.session_id.clone());
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+    });
+
+    if selected.is_some() {
+        open = false;
+    }
+
+    if !open {
+        *state = None;
+    }
+
+    selected
+}
+
+/// Opens the window and starts fetching the listing straight away.
+pub fn open(ctx: &egui::Context, config: &config::Config, state: &mut Option<State>) {
+    let matchmaking_endpoint = matchmaking_endpoint(config);
+    let new_state = State::new();
+    new_state.refresh(ctx.clone(), matchmaking_endpoint);
+    *state = Some(new_state);
+}
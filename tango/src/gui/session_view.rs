@@ -1,4 +1,4 @@
-use crate::{config, discord, gui, i18n, input, session, sync, video};
+use crate::{config, discord, gui, i18n, input, savestate, scripting, session, sync, video};
 use fluent_templates::Loader;
 mod replay_controls_window;
 
@@ -7,6 +7,10 @@ pub struct State {
     opponent_save_view: gui::save_view::State,
     own_save_view: gui::save_view::State,
     debug_window: Option<gui::debug_window::State>,
+    chat_input: String,
+    show_chat: bool,
+    state_slot: usize,
+    state_slot_message: Option<(String, std::time::Instant)>,
 }
 
 impl State {
@@ -16,6 +20,10 @@ impl State {
             opponent_save_view: gui::save_view::State::new(),
             own_save_view: gui::save_view::State::new(),
             debug_window: None,
+            chat_input: String::new(),
+            show_chat: false,
+            state_slot: 0,
+            state_slot_message: None,
         }
     }
 }
@@ -45,7 +53,7 @@ fn show_emulator(
     max_scale: u32,
     integer_scaling: bool,
     vbuf: &mut Option<VBuf>,
-) {
+) -> egui::Rect {
     let video_filter = video::filter_by_name(video_filter).unwrap_or(Box::new(video::NullFilter));
 
     // Apply stupid video scaling filter that only mint wants 🥴
@@ -92,6 +100,43 @@ fn show_emulator(
 
     ui.put(rect, egui::Image::new((vbuf.texture.id(), scaled_size)));
     ui.ctx().request_repaint();
+    rect
+}
+
+/// Draws what the script asked for on top of the game, scaled along with it.
+fn show_script_overlay(ui: &mut egui::Ui, rect: egui::Rect, script: &scripting::Script) {
+    let scale = rect.width() / mgba::gba::SCREEN_WIDTH as f32;
+    let painter = ui.painter_at(rect);
+    let font_id = egui::FontId::monospace(8.0 * scale);
+
+    for text in script.overlay() {
+        let pos = rect.min + egui::vec2(text.x, text.y) * scale;
+        let [r, g, b] = text.color;
+        painter.text(
+            pos + egui::vec2(1.0, 1.0) * scale,
+            egui::Align2::LEFT_TOP,
+            &text.text,
+            font_id.clone(),
+            egui::Color32::BLACK,
+        );
+        painter.text(
+            pos,
+            egui::Align2::LEFT_TOP,
+            &text.text,
+            font_id.clone(),
+            egui::Color32::from_rgb(r, g, b),
+        );
+    }
+
+    if let Some(error) = script.error() {
+        painter.text(
+            rect.left_bottom(),
+            egui::Align2::LEFT_BOTTOM,
+            error,
+            egui::FontId::monospace(12.0),
+            egui::Color32::RED,
+        );
+    }
 }
 
 pub fn show(
@@ -147,21 +192,31 @@ pub fn show(
                 )),
             )));
         }
-        session::Mode::Replayer => {
+        session::Mode::Replayer(_) => {
             discord_client.set_current_activity(Some(discord::make_base_activity(None)));
         }
     }
 
     match session.mode() {
-        session::Mode::SinglePlayer(_) => {
+        session::Mode::SinglePlayer(single_player) => {
             session.set_fps_target(if input_mapping.speed_change.iter().any(|c| c.is_active(input_state)) {
                 session::EXPECTED_FPS * speed_change_factor
             } else {
                 session::EXPECTED_FPS
             });
+            single_player.set_rewinding(input_mapping.rewind.iter().any(|c| c.is_active(input_state)));
+            if let Some(selection) = shared_root_state.selection.as_ref() {
+                handle_save_state_hotkeys(
+                    config,
+                    &savestate::slots_path(config, selection),
+                    input_state,
+                    session,
+                    state,
+                );
+            }
         }
-        session::Mode::Replayer => {
-            replay_controls_window::show(ctx, session, language, last_mouse_motion_time);
+        session::Mode::Replayer(replayer) => {
+            replay_controls_window::show(ctx, session, replayer, language, last_mouse_motion_time);
         }
         _ => {}
     }
@@ -273,7 +328,10 @@ cpsr = {:08x}"#,
             ui.with_layout(
                 egui::Layout::centered_and_justified(egui::Direction::LeftToRight),
                 |ui| {
-                    show_emulator(ui, session, video_filter, max_scale, integer_scaling, &mut state.vbuf);
+                    let rect = show_emulator(ui, session, video_filter, max_scale, integer_scaling, &mut state.vbuf);
+                    if let Some(script) = session.script() {
+                        show_script_overlay(ui, rect, script);
+                    }
                 },
             );
         });
@@ -294,7 +352,164 @@ cpsr = {:08x}"#,
             &mut state.debug_window,
         );
     }
-    gui::debug_window::show(ctx, language, session, &mut state.debug_window);
+    gui::debug_window::show(
+        ctx,
+        language,
+        &config.telemetry_path(),
+        session,
+        &mut state.debug_window,
+    );
+
+    show_state_slot_message(ctx, &mut state.state_slot_message);
+
+    if let session::Mode::PvP(pvp) = session.mode() {
+        let between_rounds = pvp
+            .match_
+            .blocking_lock()
+            .as_ref()
+            .map(|match_| match_.lock_round_state().round.is_none())
+            .unwrap_or(false);
+        if between_rounds {
+            show_chat_overlay(ctx, config, pvp, &mut state.chat_input, &mut state.show_chat);
+        }
+    }
+}
+
+fn handle_save_state_hotkeys(
+    config: &config::Config,
+    slots_path: &std::path::Path,
+    input_state: &input::State,
+    session: &session::Session,
+    state: &mut State,
+) {
+    let input_mapping = &config.input_mapping;
+    let slot_arg = |slot: usize| std::collections::HashMap::from([("slot", (slot + 1).into())]);
+
+    let message = if input_mapping.prev_state_slot.iter().any(|c| c.is_pressed(input_state)) {
+        state.state_slot = (state.state_slot + savestate::NUM_SLOTS - 1) % savestate::NUM_SLOTS;
+        i18n::LOCALES.lookup_with_args(&config.language, "save-state-slot", &slot_arg(state.state_slot))
+    } else if input_mapping.next_state_slot.iter().any(|c| c.is_pressed(input_state)) {
+        state.state_slot = (state.state_slot + 1) % savestate::NUM_SLOTS;
+        i18n::LOCALES.lookup_with_args(&config.language, "save-state-slot", &slot_arg(state.state_slot))
+    } else if input_mapping.save_state.iter().any(|c| c.is_pressed(input_state)) {
+        match savestate::save_from_session(session, slots_path, state.state_slot) {
+            Ok(()) => i18n::LOCALES.lookup_with_args(&config.language, "save-state-saved", &slot_arg(state.state_slot)),
+            Err(e) => {
+                log::error!("failed to save state: {:?}", e);
+                i18n::LOCALES.lookup_with_args(&config.language, "save-state-save-failed", &slot_arg(state.state_slot))
+            }
+        }
+    } else if input_mapping.load_state.iter().any(|c| c.is_pressed(input_state)) {
+        match savestate::load_into_session(session, slots_path, state.state_slot) {
+            Ok(()) => {
+                i18n::LOCALES.lookup_with_args(&config.language, "save-state-loaded", &slot_arg(state.state_slot))
+            }
+            Err(e) => {
+                log::error!("failed to load state: {:?}", e);
+                i18n::LOCALES.lookup_with_args(&config.language, "save-state-load-failed", &slot_arg(state.state_slot))
+            }
+        }
+    } else {
+        None
+    };
+
+    if let Some(message) = message {
+        state.state_slot_message = Some((message, std::time::Instant::now()));
+    }
+}
+
+fn show_state_slot_message(ctx: &egui::Context, state_slot_message: &mut Option<(String, std::time::Instant)>) {
+    const SHOW_FOR: std::time::Duration = std::time::Duration::from_secs(2);
+
+    if state_slot_message
+        .as_ref()
+        .map(|(_, shown_at)| shown_at.elapsed() > SHOW_FOR)
+        .unwrap_or(false)
+    {
+        *state_slot_message = None;
+    }
+    let message = if let Some((message, _)) = state_slot_message.as_ref() {
+        message
+    } else {
+        return;
+    };
+
+    egui::Window::new("")
+        .id(egui::Id::new("state-slot-message"))
+        .title_bar(false)
+        .resizable(false)
+        .interactable(false)
+        .anchor(egui::Align2::LEFT_TOP, egui::vec2(8.0, 8.0))
+        .frame(egui::Frame::popup(&ctx.style()).fill(egui::Color32::from_black_alpha(0xc0)))
+        .show(ctx, |ui| {
+            ui.label(message);
+        });
+}
+
+fn show_chat_overlay(
+    ctx: &egui::Context,
+    config: &config::Config,
+    pvp: &session::PvP,
+    chat_input: &mut String,
+    show_chat: &mut bool,
+) {
+    let chat = if let Some(chat) = pvp.chat() {
+        chat
+    } else {
+        return;
+    };
+
+    const MAX_MESSAGES_SHOWN: usize = 8;
+
+    egui::Window::new("")
+        .id(egui::Id::new("chat-overlay"))
+        .title_bar(false)
+        .resizable(false)
+        .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(8.0, -8.0))
+        .frame(egui::Frame::popup(&ctx.style()).fill(egui::Color32::from_black_alpha(0xc0)))
+        .show(ctx, |ui| {
+            ui.set_width(300.0);
+            if config.streamer_mode && !*show_chat {
+                ui.weak(i18n::LOCALES.lookup(&config.language, "chat-hidden").unwrap());
+            } else {
+                let chat = chat.lock();
+                let messages = chat.messages().collect::<Vec<_>>();
+                for message in &messages[messages.len().saturating_sub(MAX_MESSAGES_SHOWN)..] {
+                    ui.horizontal_wrapped(|ui| {
+                        ui.strong(if message.is_local {
+                            pvp.local_nickname()
+                        } else {
+                            pvp.remote_nickname()
+                        });
+                        ui.label(&message.text);
+                    });
+                }
+            }
+
+            ui.horizontal(|ui| {
+                if config.streamer_mode
+                    && ui
+                        .selectable_label(*show_chat, "👁️")
+                        .on_hover_text(i18n::LOCALES.lookup(&config.language, "chat-show").unwrap())
+                        .clicked()
+                {
+                    *show_chat = !*show_chat;
+                }
+
+                let input_resp = ui.add(
+                    egui::TextEdit::singleline(chat_input)
+                        .char_limit(crate::chat::MAX_MESSAGE_LENGTH)
+                        .hint_text(i18n::LOCALES.lookup(&config.language, "chat-input").unwrap())
+                        .desired_width(f32::INFINITY),
+                );
+                if input_resp.lost_focus() && ui.ctx().input(|i| i.key_pressed(egui::Key::Enter)) {
+                    let text = std::mem::take(chat_input);
+                    if let Err(e) = pvp.send_chat(&text) {
+                        log::error!("failed to send chat message: {:?}", e);
+                    }
+                }
+            });
+        });
 }
 
 fn show_status_bar(
@@ -312,32 +527,38 @@ fn show_status_bar(
     egui::TopBottomPanel::bottom("session-status-bar").show(ctx, |ui| {
         ui.horizontal(|ui| {
             ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                let (tps_adjustment, latency, round_info) = (|| {
+                let (tps_adjustment, latency, set_score, round_info) = (|| {
                     let pvp = if let session::Mode::PvP(pvp) = session.mode() {
                         pvp
                     } else {
-                        return (0.0, None, None);
+                        return (0.0, None, None, None);
                     };
 
                     let match_ = pvp.match_.blocking_lock();
                     let match_ = if let Some(match_) = &*match_ {
                         match_
                     } else {
-                        return (0.0, None, None);
+                        return (0.0, None, None, None);
                     };
 
                     let latency = sync::block_on(pvp.latency());
 
                     let round_state = match_.lock_round_state();
+                    let set_score = if round_state.score.best_of > 0 {
+                        Some(round_state.score)
+                    } else {
+                        None
+                    };
                     let round = if let Some(round) = round_state.round.as_ref() {
                         round
                     } else {
-                        return (0.0, Some(latency), None);
+                        return (0.0, Some(latency), set_score, None);
                     };
 
                     (
                         round.tps_adjustment(),
                         Some(latency),
+                        set_score,
                         Some((
                             round.local_queue_length(),
                             round.remote_queue_length(),
@@ -404,6 +625,14 @@ fn show_status_bar(
                     ui.monospace(format!("P{}", local_player_index + 1));
                 }
 
+                if let Some(set_score) = set_score {
+                    ui.add(egui::Separator::default().vertical());
+                    ui.monospace(format!(
+                        "bo{} {}-{}",
+                        set_score.best_of, set_score.wins, set_score.losses
+                    ));
+                }
+
                 ui.add(egui::Separator::default().vertical());
             });
         });
@@ -7,22 +7,47 @@ const HIDE_AFTER: std::time::Duration = std::time::Duration::from_secs(5);
 pub fn show(
     ctx: &egui::Context,
     session: &session::Session,
+    replayer: &session::Replayer,
     language: &unic_langid::LanguageIdentifier,
     last_mouse_motion_time: &Option<std::time::Instant>,
 ) {
+    session.finish_replay_seek();
+
     let paused = session.is_paused();
+    let seeking = replayer.is_seeking();
     egui::Window::new("")
         .id(egui::Id::new("replay-controls-window"))
         .resizable(false)
         .title_bar(false)
         .open(&mut {
             paused
+                || seeking
                 || last_mouse_motion_time
                     .map(|t| std::time::Instant::now() - t < HIDE_AFTER)
                     .unwrap_or(false)
         })
         .anchor(egui::Align2::CENTER_BOTTOM, egui::Vec2::new(0.0, -50.0))
         .show(ctx, |ui| {
+            // While the scrub bar is being dragged, it shows where it's being dragged to rather than where the replay is.
+            let scrub_id = egui::Id::new("replay-controls-scrub");
+            let last_tick = replayer.last_tick();
+            let mut tick = ui
+                .data(|d| d.get_temp::<u32>(scrub_id))
+                .unwrap_or_else(|| replayer.current_tick());
+            ui.horizontal(|ui| {
+                ui.spacing_mut().slider_width = 400.0;
+                let resp = ui
+                    .add(egui::Slider::new(&mut tick, 0..=last_tick).show_value(false))
+                    .on_hover_text(i18n::LOCALES.lookup(language, "replay-viewer-seek").unwrap());
+                if resp.dragged() {
+                    ui.data_mut(|d| d.insert_temp(scrub_id, tick));
+                }
+                if resp.drag_stopped() || (resp.changed() && !resp.dragged()) {
+                    ui.data_mut(|d| d.remove::<u32>(scrub_id));
+                    session.seek_replay(tick);
+                }
+                ui.monospace(format!("{}/{}", tick, last_tick));
+            });
             ui.horizontal(|ui| {
                 if ui
                     .selectable_label(paused, "⏸️")
@@ -31,6 +56,13 @@ pub fn show(
                 {
                     session.set_paused(!paused);
                 }
+                if ui
+                    .button("⏪")
+                    .on_hover_text(i18n::LOCALES.lookup(language, "replay-viewer-step-back").unwrap())
+                    .clicked()
+                {
+                    session.step_back_replay();
+                }
                 if ui
                     .button("⏯️")
                     .on_hover_text(i18n::LOCALES.lookup(language, "replay-viewer-step").unwrap())
@@ -38,6 +70,10 @@ pub fn show(
                 {
                     session.frame_step();
                 }
+                if seeking {
+                    // The replay is running flat out to get to the seeked tick, so leave its speed alone until it's there.
+                    return;
+                }
                 let mut speed = session.fps_target() / session::EXPECTED_FPS;
                 ui.add(egui::Separator::default().vertical());
                 if ui
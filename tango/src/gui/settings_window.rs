@@ -1,4 +1,4 @@
-use crate::{config, game, gui, i18n, input, patch, save, version};
+use crate::{config, game, gui, i18n, input, patch, save, scripting, version};
 use fluent_templates::Loader;
 
 #[derive(PartialEq, Eq)]
@@ -15,11 +15,16 @@ enum Tab {
 
 pub struct State {
     tab: Tab,
+    /// Listed the first time they're needed, instead of reading the scripts folder on every frame.
+    scripts: Option<Vec<String>>,
 }
 
 impl State {
     pub fn new() -> Self {
-        Self { tab: Tab::General }
+        Self {
+            tab: Tab::General,
+            scripts: None,
+        }
     }
 }
 
@@ -89,7 +94,9 @@ pub fn show(
             egui::ScrollArea::vertical().auto_shrink([false; 2]).show(ui, |ui| {
                 ui.with_layout(egui::Layout::top_down_justified(egui::Align::LEFT), |ui| {
                     match state.tab {
-                        Tab::General => show_general_tab(ui, config, &shared_root_state.font_families),
+                        Tab::General => {
+                            show_general_tab(ui, config, &shared_root_state.font_families, &mut state.scripts)
+                        }
                         Tab::Input => show_input_tab(ui, &config.language, &mut config.input_mapping, steal_input),
                         Tab::Graphics => show_graphics_tab(ui, config, window),
                         Tab::Audio => show_audio_tab(ui, config),
@@ -107,7 +114,12 @@ pub fn show(
     }
 }
 
-fn show_general_tab(ui: &mut egui::Ui, config: &mut config::Config, font_families: &gui::FontFamilies) {
+fn show_general_tab(
+    ui: &mut egui::Ui,
+    config: &mut config::Config,
+    font_families: &gui::FontFamilies,
+    scripts: &mut Option<Vec<String>>,
+) {
     egui::Grid::new("settings-window-general-grid")
         .num_columns(2)
         .show(ui, |ui| {
@@ -213,6 +225,45 @@ fn show_general_tab(ui: &mut egui::Ui, config: &mut config::Config, font_familie
                 );
                 ui.end_row();
             }
+
+            {
+                ui.strong(
+                    i18n::LOCALES
+                        .lookup(&config.language, "settings-rewind-buffer")
+                        .unwrap(),
+                );
+                ui.add(
+                    egui::DragValue::new(&mut config.rewind_buffer_secs)
+                        .range(0..=300)
+                        .suffix("s")
+                        .speed(1),
+                );
+                ui.end_row();
+            }
+
+            {
+                ui.strong(i18n::LOCALES.lookup(&config.language, "settings-script").unwrap());
+
+                let none_label = i18n::LOCALES.lookup(&config.language, "settings-script.none").unwrap();
+                let scripts = scripts.get_or_insert_with(|| scripting::list(config));
+
+                egui::ComboBox::from_id_source("settings-window-script")
+                    .selected_text(config.script.as_deref().unwrap_or(&none_label))
+                    .width(200.0)
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut config.script, None, &none_label);
+                        for name in scripts.iter() {
+                            ui.selectable_value(&mut config.script, Some(name.clone()), name);
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        i18n::LOCALES
+                            .lookup(&config.language, "settings-script.tooltip")
+                            .unwrap(),
+                    );
+                ui.end_row();
+            }
         });
 }
 
@@ -306,6 +357,15 @@ fn show_input_tab(
             add_row("input-button-speed-change", |input_mapping| {
                 &mut input_mapping.speed_change
             });
+            add_row("input-button-rewind", |input_mapping| &mut input_mapping.rewind);
+            add_row("input-button-save-state", |input_mapping| &mut input_mapping.save_state);
+            add_row("input-button-load-state", |input_mapping| &mut input_mapping.load_state);
+            add_row("input-button-prev-state-slot", |input_mapping| {
+                &mut input_mapping.prev_state_slot
+            });
+            add_row("input-button-next-state-slot", |input_mapping| {
+                &mut input_mapping.next_state_slot
+            });
             add_row("input-button-menu", |input_mapping| &mut input_mapping.menu);
         });
 }
@@ -571,6 +631,70 @@ fn show_netplay_tab(ui: &mut egui::Ui, config: &mut config::Config) {
             );
             ui.add(egui::TextEdit::singleline(&mut config.replaycollector_endpoint).desired_width(200.0));
             ui.end_row();
+
+            ui.strong(
+                i18n::LOCALES
+                    .lookup(&config.language, "settings-record-telemetry")
+                    .unwrap(),
+            );
+            ui.checkbox(&mut config.record_telemetry, "");
+            ui.end_row();
+
+            {
+                ui.strong(i18n::LOCALES.lookup(&config.language, "settings-bot-policy").unwrap());
+
+                let idle_label = i18n::LOCALES
+                    .lookup(&config.language, "settings-bot-policy.idle")
+                    .unwrap();
+                let random_label = i18n::LOCALES
+                    .lookup(&config.language, "settings-bot-policy.random")
+                    .unwrap();
+                let replay_label = i18n::LOCALES
+                    .lookup(&config.language, "settings-bot-policy.replay")
+                    .unwrap();
+                let script_label = i18n::LOCALES
+                    .lookup(&config.language, "settings-bot-policy.script")
+                    .unwrap();
+
+                egui::ComboBox::from_id_source("settings-window-netplay-bot-policy")
+                    .width(200.0)
+                    .selected_text(match config.bot_policy {
+                        config::BotPolicy::Idle => idle_label.clone(),
+                        config::BotPolicy::Random => random_label.clone(),
+                        config::BotPolicy::Replay => replay_label.clone(),
+                        config::BotPolicy::Script => script_label.clone(),
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut config.bot_policy, config::BotPolicy::Idle, &idle_label);
+                        ui.selectable_value(&mut config.bot_policy, config::BotPolicy::Random, &random_label);
+                        ui.selectable_value(&mut config.bot_policy, config::BotPolicy::Replay, &replay_label);
+                        ui.selectable_value(&mut config.bot_policy, config::BotPolicy::Script, &script_label);
+                    });
+                ui.end_row();
+            }
+
+            match config.bot_policy {
+                config::BotPolicy::Replay => {
+                    ui.strong(
+                        i18n::LOCALES
+                            .lookup(&config.language, "settings-bot-replay-path")
+                            .unwrap(),
+                    );
+                    ui.add(egui::TextEdit::singleline(&mut config.bot_replay_path).desired_width(200.0));
+                    ui.end_row();
+                }
+                config::BotPolicy::Script => {
+                    ui.strong(i18n::LOCALES.lookup(&config.language, "settings-bot-script").unwrap());
+                    ui.add(
+                        egui::TextEdit::multiline(&mut config.bot_script)
+                            .code_editor()
+                            .desired_width(200.0)
+                            .hint_text("A+RIGHT 10\n- 20"),
+                    );
+                    ui.end_row();
+                }
+                _ => {}
+            }
         });
 }
 
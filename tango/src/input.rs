@@ -66,6 +66,11 @@ pub struct Mapping {
     pub select: Vec<PhysicalInput>,
     pub start: Vec<PhysicalInput>,
     pub speed_change: Vec<PhysicalInput>,
+    pub rewind: Vec<PhysicalInput>,
+    pub save_state: Vec<PhysicalInput>,
+    pub load_state: Vec<PhysicalInput>,
+    pub prev_state_slot: Vec<PhysicalInput>,
+    pub next_state_slot: Vec<PhysicalInput>,
     pub menu: Vec<PhysicalInput>,
 }
 
@@ -131,6 +136,11 @@ impl Default for Mapping {
                 PhysicalInput::Button(ControllerButton::Start),
             ],
             speed_change: vec![PhysicalInput::Key(Key::LShift)],
+            rewind: vec![PhysicalInput::Key(Key::Grave)],
+            save_state: vec![PhysicalInput::Key(Key::F5)],
+            load_state: vec![PhysicalInput::Key(Key::F8)],
+            prev_state_slot: vec![PhysicalInput::Key(Key::F6)],
+            next_state_slot: vec![PhysicalInput::Key(Key::F7)],
             menu: vec![PhysicalInput::Key(Key::Escape)],
         }
     }
@@ -8,6 +8,8 @@ use clap::Parser;
 extern crate lazy_static;
 
 mod audio;
+mod chat;
+mod cheats;
 mod config;
 mod controller;
 mod discord;
@@ -20,9 +22,13 @@ mod keyboard;
 mod net;
 mod patch;
 mod randomcode;
+mod replay_timeline;
+mod rewind;
 mod rom;
 mod save;
+mod savestate;
 mod scanner;
+mod scripting;
 mod session;
 mod stats;
 mod sync;
@@ -460,7 +466,12 @@ fn child_main(mut config: config::Config) -> Result<(), anyhow::Error> {
         }
 
         if let Some(session) = state.shared.session.lock().as_mut() {
-            session.set_joyflags(next_config.input_mapping.to_mgba_keys(&input_state));
+            session.set_joyflags(if gfx_backend.egui_ctx().wants_keyboard_input() {
+                // Don't send keys to the game while typing into a text field (e.g. chat).
+                0
+            } else {
+                next_config.input_mapping.to_mgba_keys(&input_state)
+            });
             session.set_master_volume(next_config.volume);
         }
 
@@ -7,6 +7,9 @@ pub enum NegotiationError {
     #[error("expected hello")]
     ExpectedHello,
 
+    #[error("expected capabilities")]
+    ExpectedCapabilities,
+
     #[error("remote protocol version too old")]
     RemoteProtocolVersionTooOld,
 
@@ -17,7 +20,10 @@ pub enum NegotiationError {
     Other(#[from] anyhow::Error),
 }
 
-pub async fn negotiate(sender: &mut Sender, receiver: &mut Receiver) -> Result<(), NegotiationError> {
+pub async fn negotiate(
+    sender: &mut Sender,
+    receiver: &mut Receiver,
+) -> Result<protocol::Capabilities, NegotiationError> {
     sender
         .send_hello()
         .await
@@ -38,7 +44,31 @@ pub async fn negotiate(sender: &mut Sender, receiver: &mut Receiver) -> Result<(
         return Err(NegotiationError::RemoteProtocolVersionTooNew);
     }
 
-    Ok(())
+    // Capabilities are only exchanged after the version check, so that the hello itself never has to change.
+    sender
+        .send_capabilities()
+        .await
+        .map_err(|e| NegotiationError::Other(e.into()))?;
+
+    let remote_capabilities = match receiver
+        .receive()
+        .await
+        .map_err(|_| NegotiationError::ExpectedCapabilities)?
+    {
+        protocol::Packet::Capabilities(capabilities) => capabilities,
+        _ => {
+            return Err(NegotiationError::ExpectedCapabilities);
+        }
+    };
+
+    let capabilities = protocol::Capabilities::SUPPORTED.intersection(remote_capabilities);
+    log::info!(
+        "negotiated capabilities: {:?} (remote advertised {:?})",
+        capabilities,
+        remote_capabilities
+    );
+
+    Ok(capabilities)
 }
 
 pub struct Sender {
@@ -62,6 +92,11 @@ impl Sender {
         .await
     }
 
+    pub async fn send_capabilities(&mut self) -> std::io::Result<()> {
+        self.send_packet(&protocol::Packet::Capabilities(protocol::Capabilities::SUPPORTED))
+            .await
+    }
+
     pub async fn send_ping(&mut self, ts: std::time::SystemTime) -> std::io::Result<()> {
         self.send_packet(&protocol::Packet::Ping(protocol::Ping { ts })).await
     }
@@ -89,10 +124,29 @@ impl Sender {
             .await
     }
 
+    pub async fn send_chat(&mut self, text: String) -> std::io::Result<()> {
+        self.send_packet(&protocol::Packet::Chat(protocol::Chat { text })).await
+    }
+
     pub async fn send_start_match(&mut self) -> std::io::Result<()> {
         self.send_packet(&protocol::Packet::StartMatch(protocol::StartMatch {}))
             .await
     }
+
+    pub async fn send_return_to_lobby(&mut self) -> std::io::Result<()> {
+        self.send_packet(&protocol::Packet::ReturnToLobby(protocol::ReturnToLobby {}))
+            .await
+    }
+
+    pub async fn send_set_scoring(&mut self, best_of: u8) -> std::io::Result<()> {
+        self.send_packet(&protocol::Packet::SetScoring(protocol::SetScoring { best_of }))
+            .await
+    }
+
+    pub async fn send_lockstep(&mut self, enabled: bool) -> std::io::Result<()> {
+        self.send_packet(&protocol::Packet::Lockstep(protocol::Lockstep { enabled }))
+            .await
+    }
 }
 
 pub struct Receiver {
@@ -145,22 +199,31 @@ impl tango_pvp::net::Sender for PvpSender {
 }
 
 pub struct PvpReceiver {
-    receiver: Receiver,
+    receiver: std::sync::Arc<tokio::sync::Mutex<Receiver>>,
     sender: std::sync::Arc<tokio::sync::Mutex<Sender>>,
     latency_counter: std::sync::Arc<tokio::sync::Mutex<crate::stats::LatencyCounter>>,
+    telemetry: tango_pvp::telemetry::Recorder,
+    chat: Option<std::sync::Arc<parking_lot::Mutex<crate::chat::Chat>>>,
+    remote_returned_to_lobby: std::sync::Arc<std::sync::atomic::AtomicBool>,
     ping_timer: tokio::time::Interval,
 }
 
 impl PvpReceiver {
     pub fn new(
-        receiver: Receiver,
+        receiver: std::sync::Arc<tokio::sync::Mutex<Receiver>>,
         sender: std::sync::Arc<tokio::sync::Mutex<Sender>>,
         latency_counter: std::sync::Arc<tokio::sync::Mutex<crate::stats::LatencyCounter>>,
+        telemetry: tango_pvp::telemetry::Recorder,
+        chat: Option<std::sync::Arc<parking_lot::Mutex<crate::chat::Chat>>>,
+        remote_returned_to_lobby: std::sync::Arc<std::sync::atomic::AtomicBool>,
     ) -> Self {
         Self {
             receiver,
             sender,
             latency_counter,
+            telemetry,
+            chat,
+            remote_returned_to_lobby,
             ping_timer: tokio::time::interval(PING_INTERVAL),
         }
     }
@@ -169,12 +232,13 @@ impl PvpReceiver {
 #[async_trait::async_trait]
 impl tango_pvp::net::Receiver for PvpReceiver {
     async fn receive(&mut self) -> std::io::Result<tango_pvp::net::Input> {
+        let mut receiver = self.receiver.lock().await;
         loop {
             tokio::select! {
                 _ = self.ping_timer.tick() => {
                     self.sender.lock().await.send_ping(std::time::SystemTime::now()).await?;
                 }
-                p = self.receiver.receive() => {
+                p = receiver.receive() => {
                     match p? {
                         protocol::Packet::Ping(ping) => {
                             self.sender.lock().await.send_pong(ping.ts).await?;
@@ -182,11 +246,25 @@ impl tango_pvp::net::Receiver for PvpReceiver {
                         protocol::Packet::Pong(pong) => {
                             if let Ok(dt) = std::time::SystemTime::now().duration_since(pong.ts) {
                                 self.latency_counter.lock().await.mark(dt);
+                                self.telemetry.record(tango_pvp::telemetry::Event::Rtt {
+                                    rtt_us: dt.as_micros() as u64,
+                                });
                             }
                         }
                         protocol::Packet::Input(input) => {
                             return Ok(input);
                         }
+                        protocol::Packet::Chat(chat) => {
+                            if let Some(c) = self.chat.as_ref() {
+                                c.lock().add_remote(&chat.text);
+                            } else {
+                                log::info!("ignoring chat message as chat was not negotiated");
+                            }
+                        }
+                        protocol::Packet::ReturnToLobby(_) => {
+                            self.remote_returned_to_lobby.store(true, std::sync::atomic::Ordering::SeqCst);
+                            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "remote returned to lobby"));
+                        }
                         p => {
                             return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("invalid packet: {:?}", p)))
                         },
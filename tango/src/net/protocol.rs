@@ -1,6 +1,7 @@
 use bincode::Options;
 
-pub const VERSION: u8 = 0x3a;
+/// Only bumped for changes that can't be gated on a [`Capabilities`] bit.
+pub const VERSION: u8 = 0x3b;
 
 lazy_static! {
     static ref BINCODE_OPTIONS: bincode::config::WithOtherLimit<
@@ -31,6 +32,21 @@ pub enum Packet {
 
     // In match.
     Input(tango_pvp::net::Input),
+
+    // Chat, only if negotiated.
+    Chat(Chat),
+
+    // Rematch, only if negotiated.
+    ReturnToLobby(ReturnToLobby),
+
+    // Set scoring, only if negotiated.
+    SetScoring(SetScoring),
+
+    // Lockstep, only if negotiated.
+    Lockstep(Lockstep),
+
+    // Handshake, once both sides know they speak the same protocol version.
+    Capabilities(Capabilities),
 }
 
 impl Packet {
@@ -48,6 +64,31 @@ pub struct Hello {
     pub protocol_version: u8,
 }
 
+/// Optional features that can be enabled without bumping the protocol version.
+///
+/// Unknown bits from newer clients are ignored, so only features that both sides advertise may be used.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities(u64);
+
+impl Capabilities {
+    pub const NONE: Self = Self(0);
+    pub const CHAT: Self = Self(1 << 0);
+    pub const SET_SCORING: Self = Self(1 << 1);
+    pub const REMATCH: Self = Self(1 << 2);
+    pub const LOCKSTEP: Self = Self(1 << 3);
+
+    /// Capabilities implemented by this client.
+    pub const SUPPORTED: Self = Self(Self::CHAT.0 | Self::SET_SCORING.0 | Self::REMATCH.0 | Self::LOCKSTEP.0);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct Commit {
     pub commitment: [u8; 16],
@@ -104,6 +145,14 @@ pub struct Input {
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct StartMatch {}
 
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Chat {
+    pub text: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct ReturnToLobby {}
+
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct NegotiatedState {
     pub nonce: [u8; 16],
@@ -119,3 +168,13 @@ impl NegotiatedState {
         STATE_BINCODE_OPTIONS.deserialize(d)
     }
 }
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct SetScoring {
+    pub best_of: u8,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Lockstep {
+    pub enabled: bool,
+}
@@ -0,0 +1,162 @@
+/// How many ticks apart keyframes are. Seeking fast-forwards at most this many ticks past the nearest one.
+const KEYFRAME_INTERVAL: u32 = 60;
+
+const COMPRESSION_LEVEL: i32 = 1;
+
+struct Keyframe {
+    state: Vec<u8>,
+    checkpoint: tango_pvp::stepper::Checkpoint,
+}
+
+struct Seek {
+    target: u32,
+    started: bool,
+    resume_paused: bool,
+    resume_fps_target: f32,
+}
+
+/// Keyframes of a replay, captured the first time each part of it is played.
+pub struct Timeline {
+    input_pairs: Vec<tango_pvp::input::Pair<tango_pvp::input::Input, tango_pvp::input::Input>>,
+    keyframes: std::collections::BTreeMap<u32, Keyframe>,
+    current_tick: u32,
+    seek: Option<Seek>,
+    finished_seek: Option<f32>,
+}
+
+impl Timeline {
+    pub fn new(input_pairs: Vec<tango_pvp::input::Pair<tango_pvp::input::Input, tango_pvp::input::Input>>) -> Self {
+        Self {
+            input_pairs,
+            keyframes: std::collections::BTreeMap::new(),
+            current_tick: 0,
+            seek: None,
+            finished_seek: None,
+        }
+    }
+
+    pub fn current_tick(&self) -> u32 {
+        self.current_tick
+    }
+
+    /// The last tick that can be seeked to: the replay pauses once it runs out of input.
+    pub fn last_tick(&self) -> u32 {
+        self.input_pairs
+            .last()
+            .map(|ip| ip.local.local_tick.saturating_sub(1))
+            .unwrap_or(0)
+    }
+
+    pub fn is_seeking(&self) -> bool {
+        self.seek.is_some()
+    }
+
+    pub fn request_seek(&mut self, target: u32, resume_paused: bool, resume_fps_target: f32) {
+        let (resume_paused, resume_fps_target) = if let Some(seek) = self.seek.as_ref() {
+            // Don't pick up the fast-forward speed from the seek already in progress.
+            (seek.resume_paused, seek.resume_fps_target)
+        } else {
+            (resume_paused, resume_fps_target)
+        };
+        self.seek = Some(Seek {
+            target: std::cmp::min(target, self.last_tick()),
+            started: false,
+            resume_paused,
+            resume_fps_target,
+        });
+    }
+
+    /// Returns the FPS target to go back to once a seek has reached its tick.
+    pub fn take_finished_seek(&mut self) -> Option<f32> {
+        self.finished_seek.take()
+    }
+
+    fn insert_keyframe(
+        &mut self,
+        core: &mgba::core::CoreMutRef,
+        checkpoint: tango_pvp::stepper::Checkpoint,
+    ) -> anyhow::Result<()> {
+        let state = zstd::bulk::compress(core.save_state()?.as_slice(), COMPRESSION_LEVEL)?;
+        self.keyframes
+            .insert(checkpoint.current_tick(), Keyframe { state, checkpoint });
+        Ok(())
+    }
+
+    fn capture(
+        &mut self,
+        core: &mgba::core::CoreMutRef,
+        checkpoint: tango_pvp::stepper::Checkpoint,
+    ) -> anyhow::Result<()> {
+        let tick = checkpoint.current_tick();
+        if tick % KEYFRAME_INTERVAL != 0 || self.keyframes.contains_key(&tick) {
+            return Ok(());
+        }
+        self.insert_keyframe(core, checkpoint)
+    }
+
+    /// Captures the state the replay starts from, so that there's always a keyframe to seek back to.
+    pub fn capture_start(
+        &mut self,
+        core: &mgba::core::CoreMutRef,
+        checkpoint: tango_pvp::stepper::Checkpoint,
+    ) -> anyhow::Result<()> {
+        self.insert_keyframe(core, checkpoint)
+    }
+
+    /// Loads the nearest keyframe at or before the target, unless just running forward from here gets there sooner.
+    fn start_seek(
+        &mut self,
+        core: &mut mgba::core::CoreMutRef,
+        stepper_state: &tango_pvp::stepper::State,
+        target: u32,
+    ) -> anyhow::Result<()> {
+        let (tick, keyframe) = if let Some(keyframe) = self.keyframes.range(..=target).next_back() {
+            keyframe
+        } else {
+            anyhow::bail!("no keyframe at or before tick {}", target);
+        };
+        if self.current_tick <= target && self.current_tick >= *tick {
+            return Ok(());
+        }
+        let raw = zstd::bulk::decompress(&keyframe.state, std::mem::size_of::<mgba::state::State>())?;
+        core.load_state(&mgba::state::State::from_slice(&raw))?;
+        stepper_state.restore(&keyframe.checkpoint, &self.input_pairs);
+        self.current_tick = *tick;
+        Ok(())
+    }
+
+    /// Called at the end of every frame from the emulator thread. Returns whether a seek is still fast-forwarding.
+    pub fn on_frame(
+        &mut self,
+        core: &mut mgba::core::CoreMutRef,
+        stepper_state: &tango_pvp::stepper::State,
+        thread_handle: &mut mgba::thread::InThreadHandle,
+    ) -> bool {
+        let checkpoint = stepper_state.lock_inner().checkpoint();
+        self.current_tick = checkpoint.current_tick();
+        if let Err(e) = self.capture(core, checkpoint) {
+            log::error!("replay timeline: failed to capture keyframe: {:?}", e);
+        }
+
+        let (target, started) = if let Some(seek) = self.seek.as_mut() {
+            (seek.target, std::mem::replace(&mut seek.started, true))
+        } else {
+            return false;
+        };
+        if !started {
+            if let Err(e) = self.start_seek(core, stepper_state, target) {
+                log::error!("replay timeline: failed to load keyframe: {:?}", e);
+            }
+        }
+        if self.current_tick < target {
+            return true;
+        }
+
+        let seek = self.seek.take().unwrap();
+        if seek.resume_paused {
+            thread_handle.pause();
+        }
+        self.finished_seek = Some(seek.resume_fps_target);
+        false
+    }
+}
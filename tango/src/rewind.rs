@@ -0,0 +1,72 @@
+/// How many frames to run between snapshots. Rewinding steps back one snapshot per frame, so this is also how many times faster than real time rewinding goes.
+const SNAPSHOT_INTERVAL: u32 = 3;
+
+const COMPRESSION_LEVEL: i32 = 1;
+
+/// A bounded ring of compressed save states, oldest first.
+pub struct Buffer {
+    snapshots: std::collections::VecDeque<Vec<u8>>,
+    capacity: usize,
+    frames_until_snapshot: u32,
+}
+
+impl Buffer {
+    pub fn new(secs: u32) -> Self {
+        let capacity = (secs as f32 * crate::session::EXPECTED_FPS / SNAPSHOT_INTERVAL as f32).ceil() as usize;
+        Self {
+            snapshots: std::collections::VecDeque::with_capacity(capacity),
+            capacity,
+            frames_until_snapshot: 0,
+        }
+    }
+
+    fn push(&mut self, core: &mgba::core::CoreMutRef) -> anyhow::Result<()> {
+        let state = core.save_state()?;
+        let compressed = zstd::bulk::compress(state.as_slice(), COMPRESSION_LEVEL)?;
+        if self.snapshots.len() >= self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(compressed);
+        Ok(())
+    }
+
+    /// Loads the previous snapshot. Once only the oldest is left, it keeps getting loaded so the game stays put.
+    fn pop(&mut self, core: &mut mgba::core::CoreMutRef) -> anyhow::Result<()> {
+        let compressed = if self.snapshots.len() > 1 {
+            self.snapshots.pop_back()
+        } else {
+            self.snapshots.back().cloned()
+        };
+        let compressed = if let Some(compressed) = compressed {
+            compressed
+        } else {
+            return Ok(());
+        };
+        let raw = zstd::bulk::decompress(&compressed, std::mem::size_of::<mgba::state::State>())?;
+        core.load_state(&mgba::state::State::from_slice(&raw))?;
+        // Start recording again from where we rewound to.
+        self.frames_until_snapshot = SNAPSHOT_INTERVAL - 1;
+        Ok(())
+    }
+
+    /// Called at the end of every frame from the emulator thread.
+    pub fn on_frame(&mut self, core: &mut mgba::core::CoreMutRef, rewinding: bool) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let r = if rewinding {
+            self.pop(core)
+        } else if self.frames_until_snapshot == 0 {
+            self.frames_until_snapshot = SNAPSHOT_INTERVAL - 1;
+            self.push(core)
+        } else {
+            self.frames_until_snapshot -= 1;
+            Ok(())
+        };
+
+        if let Err(e) = r {
+            log::error!("rewind buffer: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use crate::{config, gui, session};
+
+pub const NUM_SLOTS: usize = 10;
+
+pub struct Slot {
+    pub saved_at: std::time::SystemTime,
+    pub thumbnail: Option<image::RgbaImage>,
+}
+
+/// Slots are kept per game, patch and save file, so loading one never swaps out the game from under the save.
+pub fn slots_path(config: &config::Config, selection: &gui::Selection) -> std::path::PathBuf {
+    let (family, variant) = selection.game.gamedb_entry().family_and_variant;
+    let mut path = config.savestates_path().join(format!("{}_{}", family, variant));
+    if let Some((name, version, _)) = selection.patch.as_ref() {
+        path = path.join(format!("{}-{}", name, version));
+    }
+    let saves_path = config.saves_path();
+    path.join(
+        selection
+            .save
+            .path
+            .strip_prefix(&saves_path)
+            .unwrap_or(&selection.save.path),
+    )
+}
+
+fn state_path(path: &std::path::Path, slot: usize) -> std::path::PathBuf {
+    path.join(format!("slot{}.state", slot + 1))
+}
+
+fn thumbnail_path(path: &std::path::Path, slot: usize) -> std::path::PathBuf {
+    path.join(format!("slot{}.png", slot + 1))
+}
+
+pub fn save(path: &std::path::Path, slot: usize, state: &mgba::state::State, vbuf: &[u8]) -> anyhow::Result<()> {
+    std::fs::create_dir_all(path)?;
+    std::fs::write(state_path(path, slot), state.as_slice())?;
+    if let Some(thumbnail) =
+        image::RgbaImage::from_raw(mgba::gba::SCREEN_WIDTH, mgba::gba::SCREEN_HEIGHT, vbuf.to_vec())
+    {
+        thumbnail.save_with_format(thumbnail_path(path, slot), image::ImageFormat::Png)?;
+    }
+    Ok(())
+}
+
+pub fn load(path: &std::path::Path, slot: usize) -> anyhow::Result<Box<mgba::state::State>> {
+    let raw = std::fs::read(state_path(path, slot))?;
+    if raw.len() != std::mem::size_of::<mgba::state::State>() {
+        anyhow::bail!("save state has the wrong size: {} bytes", raw.len());
+    }
+    Ok(mgba::state::State::from_slice(&raw))
+}
+
+pub fn save_from_session(session: &session::Session, path: &std::path::Path, slot: usize) -> anyhow::Result<()> {
+    let state = session.save_state()?;
+    let vbuf = session.lock_vbuf().clone();
+    save(path, slot, &state, &vbuf)
+}
+
+pub fn load_into_session(session: &session::Session, path: &std::path::Path, slot: usize) -> anyhow::Result<()> {
+    session.load_state(&load(path, slot)?)
+}
+
+/// Returns every slot, with None for the empty ones.
+pub fn list(path: &std::path::Path) -> Vec<Option<Slot>> {
+    (0..NUM_SLOTS)
+        .map(|slot| {
+            let saved_at = std::fs::metadata(state_path(path, slot)).ok()?.modified().ok()?;
+            let thumbnail = image::open(thumbnail_path(path, slot))
+                .map(|image| image.to_rgba8())
+                .ok();
+            Some(Slot { saved_at, thumbnail })
+        })
+        .collect()
+}
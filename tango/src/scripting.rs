@@ -0,0 +1,293 @@
+use crate::config;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// How many operations a single call into a script may take, so that a runaway loop errors out instead of hanging the emulator.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Text a script asked to be drawn over the game, in GBA screen pixels.
+#[derive(Clone, Debug)]
+pub struct Text {
+    pub x: f32,
+    pub y: f32,
+    pub text: String,
+    pub color: [u8; 3],
+}
+
+thread_local! {
+    /// The core the script is currently being run against, only set while a script function is being called.
+    static CORE: std::cell::Cell<*mut mgba::core::CoreMutRef<'static>> = std::cell::Cell::new(std::ptr::null_mut());
+}
+
+struct CoreGuard;
+
+impl Drop for CoreGuard {
+    fn drop(&mut self) {
+        CORE.with(|c| c.set(std::ptr::null_mut()));
+    }
+}
+
+fn with_core<R>(core: &mut mgba::core::CoreMutRef, f: impl FnOnce() -> R) -> R {
+    CORE.with(|c| c.set((core as *mut mgba::core::CoreMutRef).cast()));
+    let _guard = CoreGuard;
+    f()
+}
+
+fn core_op<R>(f: impl FnOnce(&mut mgba::core::CoreMutRef) -> R) -> Result<R, Box<rhai::EvalAltResult>> {
+    let core = CORE.with(|c| c.get());
+    if core.is_null() {
+        return Err("the core can only be used from on_frame or a trap handler".into());
+    }
+    // SAFETY: CORE is only set for as long as with_core holds the reference.
+    Ok(f(unsafe { &mut *core }))
+}
+
+struct Inner {
+    scope: rhai::Scope<'static>,
+    /// Bound as `this` in every call: script functions can't see top-level variables, so state that needs to stick around goes here.
+    this: rhai::Dynamic,
+}
+
+/// A Rhai script that can trap on addresses, read and write memory and draw text over the game.
+///
+/// Scripts register traps with `trap(address, "function_name")` while they're being loaded, and may define `on_frame()`, which is called at the end of every frame.
+pub struct Script {
+    engine: rhai::Engine,
+    ast: rhai::AST,
+    inner: Mutex<Inner>,
+    traps: Vec<(u32, String)>,
+    has_on_frame: bool,
+    pending_overlay: Arc<Mutex<Vec<Text>>>,
+    overlay: Mutex<Vec<Text>>,
+    error: Mutex<Option<String>>,
+}
+
+fn read_only_error<T>() -> Result<T, Box<rhai::EvalAltResult>> {
+    Err("the game can't be changed from here: replays have to play back exactly as they were recorded".into())
+}
+
+impl Script {
+    /// If `read_only` is set, the script can look at the game but not change it.
+    pub fn load(path: &std::path::Path, read_only: bool) -> anyhow::Result<Self> {
+        let source = std::fs::read_to_string(path)?;
+
+        let traps = Arc::new(Mutex::new(Some(vec![])));
+        let pending_overlay = Arc::new(Mutex::new(vec![]));
+
+        let mut engine = rhai::Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.on_print(|s| log::info!("script: {}", s));
+        engine.on_debug(|s, _, pos| log::debug!("script @ {}: {}", pos, s));
+
+        engine.register_fn("read_u8", |addr: i64| {
+            core_op(|core| core.raw_read_8(addr as u32, -1) as i64)
+        });
+        engine.register_fn("read_u16", |addr: i64| {
+            core_op(|core| core.raw_read_16(addr as u32, -1) as i64)
+        });
+        engine.register_fn("read_u32", |addr: i64| {
+            core_op(|core| core.raw_read_32(addr as u32, -1) as i64)
+        });
+        engine.register_fn("write_u8", move |addr: i64, v: i64| {
+            if read_only {
+                return read_only_error();
+            }
+            core_op(|core| core.raw_write_8(addr as u32, -1, v as u8))
+        });
+        engine.register_fn("write_u16", move |addr: i64, v: i64| {
+            if read_only {
+                return read_only_error();
+            }
+            core_op(|core| core.raw_write_16(addr as u32, -1, v as u16))
+        });
+        engine.register_fn("write_u32", move |addr: i64, v: i64| {
+            if read_only {
+                return read_only_error();
+            }
+            core_op(|core| core.raw_write_32(addr as u32, -1, v as u32))
+        });
+        engine.register_fn("reg", |r: i64| {
+            if !(0..16).contains(&r) {
+                return Err(format!("no such register: r{}", r).into());
+            }
+            core_op(|core| core.as_ref().gba().cpu().gpr(r as usize) as u32 as i64)
+        });
+        engine.register_fn("set_reg", move |r: i64, v: i64| {
+            if read_only {
+                return read_only_error();
+            }
+            if !(0..15).contains(&r) {
+                return Err(format!("can't set register: r{}", r).into());
+            }
+            core_op(|core| core.gba_mut().cpu_mut().set_gpr(r as usize, v as u32 as i32))
+        });
+
+        engine.register_fn("trap", {
+            let traps = traps.clone();
+            move |addr: i64, name: &str| -> Result<(), Box<rhai::EvalAltResult>> {
+                let mut traps = traps.lock();
+                let traps = if let Some(traps) = traps.as_mut() {
+                    traps
+                } else {
+                    return Err("traps can only be registered while the script is loading".into());
+                };
+                traps.push((addr as u32, name.to_string()));
+                Ok(())
+            }
+        });
+
+        engine.register_fn("draw_text", {
+            let pending_overlay = pending_overlay.clone();
+            move |x: i64, y: i64, text: &str| {
+                pending_overlay.lock().push(Text {
+                    x: x as f32,
+                    y: y as f32,
+                    text: text.to_string(),
+                    color: [0xff, 0xff, 0xff],
+                });
+            }
+        });
+        engine.register_fn("draw_text", {
+            let pending_overlay = pending_overlay.clone();
+            move |x: i64, y: i64, text: &str, color: i64| {
+                pending_overlay.lock().push(Text {
+                    x: x as f32,
+                    y: y as f32,
+                    text: text.to_string(),
+                    color: [(color >> 16) as u8, (color >> 8) as u8, color as u8],
+                });
+            }
+        });
+
+        let ast = engine
+            .compile(&source)
+            .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+
+        let mut scope = rhai::Scope::new();
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+
+        let has_fn = |name: &str| ast.iter_functions().any(|f| f.name == name && f.params.is_empty());
+
+        let traps = traps.lock().take().unwrap();
+        let mut seen = std::collections::HashSet::new();
+        for (addr, name) in traps.iter() {
+            if !seen.insert(*addr) {
+                anyhow::bail!("{}: more than one trap at 0x{:08x}", path.display(), addr);
+            }
+            if !has_fn(name) {
+                anyhow::bail!("{}: no function {}() for trap at 0x{:08x}", path.display(), name, addr);
+            }
+        }
+
+        let has_on_frame = has_fn("on_frame");
+
+        Ok(Self {
+            engine,
+            ast,
+            inner: Mutex::new(Inner {
+                scope,
+                this: rhai::Map::new().into(),
+            }),
+            traps,
+            has_on_frame,
+            pending_overlay,
+            overlay: Mutex::new(vec![]),
+            error: Mutex::new(None),
+        })
+    }
+
+    pub fn trap_addresses(&self) -> impl Iterator<Item = u32> + '_ {
+        self.traps.iter().map(|(addr, _)| *addr)
+    }
+
+    /// Traps to install on the core. Must be set before the core starts running.
+    pub fn traps(self: &Arc<Self>) -> Vec<(u32, Box<dyn Fn(mgba::core::CoreMutRef)>)> {
+        self.traps
+            .iter()
+            .map(|(addr, name)| {
+                let script = self.clone();
+                let name = name.clone();
+                (
+                    *addr,
+                    Box::new(move |mut core: mgba::core::CoreMutRef| {
+                        script.call(&mut core, &name);
+                    }) as Box<dyn Fn(mgba::core::CoreMutRef)>,
+                )
+            })
+            .collect()
+    }
+
+    /// Once a script errors, it stops running for the rest of the session.
+    fn call(&self, core: &mut mgba::core::CoreMutRef, name: &str) {
+        if self.error.lock().is_some() {
+            return;
+        }
+
+        let mut inner = self.inner.lock();
+        let Inner { scope, this } = &mut *inner;
+        let options = rhai::CallFnOptions::new()
+            .eval_ast(false)
+            .rewind_scope(false)
+            .bind_this_ptr(this);
+        let result = with_core(core, || {
+            self.engine
+                .call_fn_with_options::<rhai::Dynamic>(options, scope, &self.ast, name, ())
+        });
+
+        if let Err(e) = result {
+            log::error!("script error in {}(): {}", name, e);
+            *self.error.lock() = Some(format!("{}(): {}", name, e));
+        }
+    }
+
+    /// Called at the end of every frame from the emulator thread.
+    pub fn on_frame(&self, core: &mut mgba::core::CoreMutRef) {
+        if self.has_on_frame {
+            self.call(core, "on_frame");
+        }
+        *self.overlay.lock() = std::mem::take(&mut *self.pending_overlay.lock());
+    }
+
+    /// What was drawn during the last frame.
+    pub fn overlay(&self) -> Vec<Text> {
+        self.overlay.lock().clone()
+    }
+
+    pub fn error(&self) -> Option<String> {
+        self.error.lock().clone()
+    }
+}
+
+/// Script file names in the scripts folder, sorted.
+pub fn list(config: &config::Config) -> Vec<String> {
+    let mut names = std::fs::read_dir(config.scripts_path())
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension() == Some(std::ffi::OsStr::new("rhai")))
+                .filter_map(|path| {
+                    path.file_name()
+                        .and_then(|name| name.to_str())
+                        .map(|name| name.to_string())
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+/// Loads the script picked in settings, if any. A script that fails to load is logged and left out.
+pub fn load_from_config(config: &config::Config, read_only: bool) -> Option<Script> {
+    let name = config.script.as_ref()?;
+    match Script::load(&config.scripts_path().join(name), read_only) {
+        Ok(script) => Some(script),
+        Err(e) => {
+            log::error!("failed to load script: {:?}", e);
+            None
+        }
+    }
+}
@@ -1,10 +1,20 @@
-use crate::{audio, config, game, net, rom, stats, video};
+use crate::{audio, chat, cheats, config, game, net, replay_timeline, rewind, rom, scripting, stats, video};
 use parking_lot::Mutex;
 use rand::SeedableRng;
 use std::sync::Arc;
 
 pub const EXPECTED_FPS: f32 = 16777216.0 / 280896.0;
 
+/// How fast replays run while fast-forwarding to a seeked tick.
+const SEEK_FPS_TARGET: f32 = EXPECTED_FPS * 32.0;
+
+/// Telemetry is written every frame, so old files are deleted once they add up to more than this.
+const MAX_TELEMETRY_BYTES: u64 = 256 * 1024 * 1024;
+
+const TIME_DESCRIPTION: &[time::format_description::FormatItem<'_>] = time::macros::format_description!(
+    "[year padding:zero][month padding:zero repr:numerical][day padding:zero][hour padding:zero][minute padding:zero][second padding:zero]"
+);
+
 pub struct GameInfo {
     pub game: &'static (dyn game::Game + Send + Sync),
     pub patch: Option<(String, semver::Version)>,
@@ -28,27 +38,114 @@ pub struct Session {
     pause_on_next_frame: std::sync::Arc<std::sync::atomic::AtomicBool>,
     opponent_setup: Option<Setup>,
     own_setup: Option<Setup>,
+    script: Option<Arc<scripting::Script>>,
 }
 
 pub struct PvP {
     pub match_: std::sync::Arc<tokio::sync::Mutex<Option<std::sync::Arc<tango_pvp::battle::Match>>>>,
     cancellation_token: tokio_util::sync::CancellationToken,
     latency_counter: std::sync::Arc<tokio::sync::Mutex<crate::stats::LatencyCounter>>,
-    _peer_conn: datachannel_wrapper::PeerConnection,
+    telemetry: tango_pvp::telemetry::Recorder,
+    chat: Option<std::sync::Arc<Mutex<chat::Chat>>>,
+    sender: Option<std::sync::Arc<tokio::sync::Mutex<net::Sender>>>,
+    local_nickname: String,
+    remote_nickname: String,
+}
+
+/// For a peer, the connection itself is kept alive by the caller so it can outlive the session for a rematch.
+pub enum Opponent {
+    Peer {
+        sender: std::sync::Arc<tokio::sync::Mutex<net::Sender>>,
+        receiver: std::sync::Arc<tokio::sync::Mutex<net::Receiver>>,
+        chat: Option<std::sync::Arc<Mutex<chat::Chat>>>,
+        remote_returned_to_lobby: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    },
+    Bot(Box<dyn tango_pvp::bot::Policy + Send + Sync>),
 }
 
 impl PvP {
     pub async fn latency(&self) -> std::time::Duration {
         self.latency_counter.lock().await.median()
     }
+
+    pub fn telemetry(&self) -> &tango_pvp::telemetry::Recorder {
+        &self.telemetry
+    }
+
+    pub fn chat(&self) -> Option<&std::sync::Arc<Mutex<chat::Chat>>> {
+        self.chat.as_ref()
+    }
+
+    pub fn local_nickname(&self) -> &str {
+        &self.local_nickname
+    }
+
+    pub fn remote_nickname(&self) -> &str {
+        &self.remote_nickname
+    }
+
+    /// Cancelled when the session is dropped.
+    pub fn cancellation_token(&self) -> &tokio_util::sync::CancellationToken {
+        &self.cancellation_token
+    }
+
+    /// Adds the message to the chat right away and sends it in the background, as the sender may be busy with inputs.
+    pub fn send_chat(&self, text: &str) -> anyhow::Result<()> {
+        let (chat, sender) = if let (Some(chat), Some(sender)) = (self.chat.as_ref(), self.sender.as_ref()) {
+            (chat, sender)
+        } else {
+            anyhow::bail!("chat is not available");
+        };
+
+        let text = if let Some(text) = chat.lock().add_local(text) {
+            text
+        } else {
+            return Ok(());
+        };
+        let sender = sender.clone();
+        tokio::task::spawn(async move {
+            if let Err(e) = sender.lock().await.send_chat(text).await {
+                log::error!("failed to send chat message: {:?}", e);
+            }
+        });
+        Ok(())
+    }
+}
+
+pub struct SinglePlayer {
+    rewinding: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    cheats: Arc<Mutex<Vec<cheats::Op>>>,
+}
+
+impl SinglePlayer {
+    /// While set, the game steps backwards through the rewind buffer instead of running forwards.
+    pub fn set_rewinding(&self, rewinding: bool) {
+        self.rewinding.store(rewinding, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+pub struct Replayer {
+    timeline: Arc<Mutex<replay_timeline::Timeline>>,
 }
 
-pub struct SinglePlayer {}
+impl Replayer {
+    pub fn current_tick(&self) -> u32 {
+        self.timeline.lock().current_tick()
+    }
+
+    pub fn last_tick(&self) -> u32 {
+        self.timeline.lock().last_tick()
+    }
+
+    pub fn is_seeking(&self) -> bool {
+        self.timeline.lock().is_seeking()
+    }
+}
 
 pub enum Mode {
     SinglePlayer(SinglePlayer),
     PvP(PvP),
-    Replayer,
+    Replayer(Replayer),
 }
 
 impl Session {
@@ -69,12 +166,12 @@ impl Session {
         remote_rom: &[u8],
         remote_save: Box<dyn tango_dataview::save::Save + Send + Sync + 'static>,
         emu_tps_counter: Arc<Mutex<stats::Counter>>,
-        sender: net::Sender,
-        receiver: net::Receiver,
-        peer_conn: datachannel_wrapper::PeerConnection,
+        opponent: Opponent,
         is_offerer: bool,
         replays_path: std::path::PathBuf,
         match_type: (u8, u8),
+        best_of: u8,
+        lockstep: bool,
         rng_seed: [u8; 16],
     ) -> Result<Self, anyhow::Error> {
         let mut core = mgba::core::Core::new_gba("tango")?;
@@ -117,9 +214,73 @@ impl Session {
 
         let thread = mgba::thread::Thread::new(core);
 
-        let sender = std::sync::Arc::new(tokio::sync::Mutex::new(sender));
         let latency_counter = std::sync::Arc::new(tokio::sync::Mutex::new(crate::stats::LatencyCounter::new(5)));
 
+        let telemetry = tango_pvp::telemetry::Recorder::new({
+            let config = config.read();
+            if config.record_telemetry {
+                let telemetry_filename = config.telemetry_path().join(format!(
+                    "{}.jsonl",
+                    format!(
+                        "{}-{}-{}-vs-{}",
+                        time::OffsetDateTime::from(std::time::SystemTime::now())
+                            .format(TIME_DESCRIPTION)
+                            .expect("format time"),
+                        link_code,
+                        netplay_compatibility,
+                        remote_settings.nickname,
+                    )
+                    .chars()
+                    .filter(|c| "/\\?%*:|\"<>. ".chars().all(|c2| c2 != *c))
+                    .collect::<String>()
+                ));
+                if let Err(e) = tango_pvp::telemetry::prune(&config.telemetry_path(), MAX_TELEMETRY_BYTES) {
+                    log::error!("failed to prune telemetry: {:?}", e);
+                }
+                log::info!("open telemetry: {}", telemetry_filename.display());
+                match std::fs::File::create(&telemetry_filename) {
+                    Ok(f) => Some(Box::new(std::io::BufWriter::new(f)) as Box<dyn std::io::Write + Send>),
+                    Err(e) => {
+                        log::error!("failed to open telemetry file: {:?}", e);
+                        None
+                    }
+                }
+            } else {
+                None
+            }
+        });
+
+        let is_bot = matches!(opponent, Opponent::Bot(_));
+        let (sender, receiver, chat, chat_sender): (
+            Box<dyn tango_pvp::net::Sender + Send + Sync>,
+            Box<dyn tango_pvp::net::Receiver + Send + Sync>,
+            _,
+            _,
+        ) = match opponent {
+            Opponent::Peer {
+                sender,
+                receiver,
+                chat,
+                remote_returned_to_lobby,
+            } => (
+                Box::new(crate::net::PvpSender::new(sender.clone())),
+                Box::new(crate::net::PvpReceiver::new(
+                    receiver,
+                    sender.clone(),
+                    latency_counter.clone(),
+                    telemetry.clone(),
+                    chat.clone(),
+                    remote_returned_to_lobby,
+                )),
+                chat,
+                Some(sender),
+            ),
+            Opponent::Bot(policy) => {
+                let (sender, receiver) = tango_pvp::bot::new(policy);
+                (Box::new(sender), Box::new(receiver), None, None)
+            }
+        };
+
         let cancellation_token = tokio_util::sync::CancellationToken::new();
         let match_ = match_.clone();
         *match_.try_lock().unwrap() = Some({
@@ -135,18 +296,18 @@ impl Session {
                 local_hooks,
                 tango_pvp::hooks::hooks_for_gamedb_entry(remote_game.gamedb_entry()).unwrap(),
                 cancellation_token.clone(),
-                Box::new(crate::net::PvpSender::new(sender.clone())),
+                sender,
                 rand_pcg::Mcg128Xsl64::from_seed(rng_seed),
                 is_offerer,
                 thread.handle(),
                 remote_rom,
                 remote_save.as_ref(),
                 match_type,
+                best_of,
+                lockstep,
                 config.input_delay,
-                move |round_number, local_player_index| {
-                    const TIME_DESCRIPTION: &[time::format_description::FormatItem<'_>] = time::macros::format_description!(
-                        "[year padding:zero][month padding:zero repr:numerical][day padding:zero][hour padding:zero][minute padding:zero][second padding:zero]"
-                    );
+                completion_token.clone(),
+                move |round_number, local_player_index, set_score| {
                     let replay_filename = replays_path.join(format!(
                         "{}.tangoreplay",
                         format!(
@@ -209,13 +370,22 @@ impl Session {
                             round: round_number as u32,
                             match_type: match_type.0 as u32,
                             match_subtype: match_type.1 as u32,
+                            set_score: if set_score.best_of > 0 {
+                                Some(tango_pvp::replay::metadata::SetScore {
+                                    best_of: set_score.best_of as u32,
+                                    local_wins: set_score.wins as u32,
+                                    remote_wins: set_score.losses as u32,
+                                })
+                            } else {
+                                None
+                            },
                         },
                         local_player_index,
                         local_hooks.packet_size() as u8,
                     )?))
                 },
                 move |r| {
-                    if replaycollector_endpoint.is_empty() {
+                    if is_bot || replaycollector_endpoint.is_empty() {
                         return Ok(());
                     }
 
@@ -244,17 +414,13 @@ impl Session {
 
                     Ok(())
                 },
+                telemetry.clone(),
             )
             .expect("new match");
 
             {
                 let match_ = match_.clone();
                 let inner_match = inner_match.clone();
-                let receiver = Box::new(crate::net::PvpReceiver::new(
-                    receiver,
-                    sender.clone(),
-                    latency_counter.clone(),
-                ));
                 tokio::task::spawn(async move {
                     tokio::select! {
                         r = inner_match.run(receiver) => {
@@ -315,8 +481,12 @@ impl Session {
             mode: Mode::PvP(PvP {
                 match_,
                 cancellation_token,
-                _peer_conn: peer_conn,
                 latency_counter,
+                telemetry,
+                chat,
+                sender: chat_sender,
+                local_nickname: local_settings.nickname.clone(),
+                remote_nickname: remote_settings.nickname.clone(),
             }),
             completion_token,
             pause_on_next_frame: std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false)),
@@ -345,6 +515,7 @@ impl Session {
             } else {
                 None
             },
+            script: None,
         })
     }
 
@@ -355,6 +526,9 @@ impl Session {
         rom: &[u8],
         save_file: std::fs::File,
         emu_tps_counter: Arc<Mutex<stats::Counter>>,
+        rewind_buffer_secs: u32,
+        cheats: Vec<cheats::Op>,
+        script: Option<scripting::Script>,
     ) -> Result<Self, anyhow::Error> {
         let mut core = mgba::core::Core::new_gba("tango")?;
         core.enable_video_buffer();
@@ -370,6 +544,11 @@ impl Session {
         let hooks = tango_pvp::hooks::hooks_for_gamedb_entry(game.gamedb_entry()).unwrap();
         hooks.patch(core.as_mut());
 
+        let script = script.map(Arc::new);
+        if let Some(script) = script.as_ref() {
+            core.set_traps(script.traps());
+        }
+
         let thread = mgba::thread::Thread::new(core);
 
         thread.start()?;
@@ -381,6 +560,9 @@ impl Session {
         ))))?;
 
         let pause_on_next_frame = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let rewinding = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let rewind_buffer = Mutex::new(rewind::Buffer::new(rewind_buffer_secs));
+        let cheats = Arc::new(Mutex::new(cheats));
         let vbuf = Arc::new(Mutex::new(vec![
             0u8;
             (mgba::gba::SCREEN_WIDTH * mgba::gba::SCREEN_HEIGHT * 4)
@@ -391,6 +573,9 @@ impl Session {
             let vbuf = vbuf.clone();
             let emu_tps_counter = emu_tps_counter.clone();
             let pause_on_next_frame = pause_on_next_frame.clone();
+            let rewinding = rewinding.clone();
+            let cheats = cheats.clone();
+            let script = script.clone();
             move |mut core, video_buffer, mut thread_handle| {
                 let mut vbuf = vbuf.lock();
                 vbuf.copy_from_slice(video_buffer);
@@ -398,6 +583,16 @@ impl Session {
                 core.set_keys(joyflags.load(std::sync::atomic::Ordering::Relaxed));
                 emu_tps_counter.lock().mark();
 
+                rewind_buffer
+                    .lock()
+                    .on_frame(&mut core, rewinding.load(std::sync::atomic::Ordering::Relaxed));
+
+                crate::cheats::apply(&mut core, &cheats.lock());
+
+                if let Some(script) = script.as_ref() {
+                    script.on_frame(&mut core);
+                }
+
                 if pause_on_next_frame.swap(false, std::sync::atomic::Ordering::SeqCst) {
                     thread_handle.pause();
                 }
@@ -410,11 +605,12 @@ impl Session {
             _audio_binding: audio_binding,
             thread,
             joyflags,
-            mode: Mode::SinglePlayer(SinglePlayer {}),
+            mode: Mode::SinglePlayer(SinglePlayer { rewinding, cheats }),
             pause_on_next_frame,
             completion_token: tango_pvp::hooks::CompletionToken::new(),
             own_setup: None,
             opponent_setup: None,
+            script,
         })
     }
 
@@ -425,6 +621,7 @@ impl Session {
         rom: &[u8],
         emu_tps_counter: Arc<Mutex<stats::Counter>>,
         replay: &tango_pvp::replay::Replay,
+        script: Option<scripting::Script>,
     ) -> Result<Self, anyhow::Error> {
         let mut core = mgba::core::Core::new_gba("tango")?;
         core.enable_video_buffer();
@@ -436,23 +633,28 @@ impl Session {
 
         let completion_token = tango_pvp::hooks::CompletionToken::new();
 
-        let replay_is_complete = replay.is_complete;
         let input_pairs = replay.input_pairs.clone();
         let stepper_state = tango_pvp::stepper::State::new(
             (replay.metadata.match_type as u8, replay.metadata.match_subtype as u8),
             replay.local_player_index,
             input_pairs.clone(),
             0,
-            Box::new({
-                let completion_token = completion_token.clone();
-                move || {
-                    completion_token.complete();
-                }
-            }),
+            // Replays pause at the end of the round instead of completing, so that they can still be seeked back into.
+            Box::new(|| {}),
         );
         let mut traps = hooks.common_traps();
         traps.extend(hooks.stepper_traps(stepper_state.clone()));
         traps.extend(hooks.stepper_replay_traps());
+        let script = script.map(Arc::new);
+        if let Some(script) = script.as_ref() {
+            if let Some(addr) = script
+                .trap_addresses()
+                .find(|addr| traps.iter().any(|(trap_addr, _)| trap_addr == addr))
+            {
+                anyhow::bail!("script trap at 0x{:08x} clashes with one of tango's own traps", addr);
+            }
+            traps.extend(script.traps());
+        }
         core.set_traps(traps);
 
         let thread = mgba::thread::Thread::new(core);
@@ -466,9 +668,18 @@ impl Session {
             audio_binder.sample_rate(),
         ))))?;
 
+        let timeline = Arc::new(Mutex::new(replay_timeline::Timeline::new(input_pairs)));
         let local_state = replay.local_state.clone();
-        thread.handle().run_on_core(move |mut core| {
-            core.load_state(&local_state).expect("load state");
+        thread.handle().run_on_core({
+            let timeline = timeline.clone();
+            let stepper_state = stepper_state.clone();
+            move |mut core| {
+                core.load_state(&local_state).expect("load state");
+                let checkpoint = stepper_state.lock_inner().checkpoint();
+                if let Err(e) = timeline.lock().capture_start(&core, checkpoint) {
+                    log::error!("replay timeline: failed to capture starting keyframe: {:?}", e);
+                }
+            }
         });
         thread.handle().unpause();
 
@@ -481,22 +692,30 @@ impl Session {
         thread.set_frame_callback({
             let vbuf = vbuf.clone();
             let emu_tps_counter = emu_tps_counter.clone();
-            let completion_token = completion_token.clone();
             let stepper_state = stepper_state.clone();
             let pause_on_next_frame = pause_on_next_frame.clone();
-            move |_core, video_buffer, mut thread_handle| {
+            let timeline = timeline.clone();
+            let script = script.clone();
+            move |mut core, video_buffer, mut thread_handle| {
                 let mut vbuf = vbuf.lock();
                 vbuf.copy_from_slice(video_buffer);
                 video::fix_vbuf_alpha(&mut vbuf);
                 emu_tps_counter.lock().mark();
 
-                if !replay_is_complete && stepper_state.lock_inner().input_pairs_left() == 0 {
-                    completion_token.complete();
+                if timeline.lock().on_frame(&mut core, &stepper_state, &mut thread_handle) {
+                    return;
+                }
+
+                if let Some(script) = script.as_ref() {
+                    script.on_frame(&mut core);
                 }
 
-                if pause_on_next_frame.swap(false, std::sync::atomic::Ordering::SeqCst)
-                    || completion_token.is_complete()
-                {
+                // Checked at wherever the replay is now instead of latched, so that seeking back from the end plays forward again.
+                let at_end = {
+                    let inner_state = stepper_state.lock_inner();
+                    inner_state.input_pairs_left() == 0 || inner_state.is_round_ended()
+                };
+                if pause_on_next_frame.swap(false, std::sync::atomic::Ordering::SeqCst) || at_end {
                     thread_handle.pause();
                 }
             }
@@ -509,14 +728,19 @@ impl Session {
             _audio_binding: audio_binding,
             thread,
             joyflags: Arc::new(std::sync::atomic::AtomicU32::new(0)),
-            mode: Mode::Replayer,
+            mode: Mode::Replayer(Replayer { timeline }),
             completion_token,
             pause_on_next_frame,
             own_setup: None,
             opponent_setup: None,
+            script,
         })
     }
 
+    pub fn completion_token(&self) -> &tango_pvp::hooks::CompletionToken {
+        &self.completion_token
+    }
+
     pub fn completed(&self) -> bool {
         self.completion_token.is_complete()
     }
@@ -546,6 +770,84 @@ impl Session {
         handle.unpause();
     }
 
+    /// Save states are only allowed in single player: loading one in a match would desync it.
+    pub fn save_state(&self) -> anyhow::Result<Box<mgba::state::State>> {
+        if !matches!(self.mode, Mode::SinglePlayer(_)) {
+            anyhow::bail!("save states are only available in single player");
+        }
+        let handle = self.thread.handle();
+        let mut audio_guard = handle.lock_audio();
+        Ok(audio_guard.core_mut().save_state()?)
+    }
+
+    pub fn load_state(&self, state: &mgba::state::State) -> anyhow::Result<()> {
+        if !matches!(self.mode, Mode::SinglePlayer(_)) {
+            anyhow::bail!("save states are only available in single player");
+        }
+        let handle = self.thread.handle();
+        let mut audio_guard = handle.lock_audio();
+        audio_guard.core_mut().load_state(state)?;
+        Ok(())
+    }
+
+    /// Jumps a replay to a tick, keeping it paused afterwards if it was paused before.
+    pub fn seek_replay(&self, tick: u32) {
+        let replayer = if let Mode::Replayer(replayer) = &self.mode {
+            replayer
+        } else {
+            return;
+        };
+        replayer
+            .timeline
+            .lock()
+            .request_seek(tick, self.is_paused(), self.fps_target());
+        self.set_fps_target(SEEK_FPS_TARGET);
+        self.thread.handle().unpause();
+    }
+
+    pub fn step_back_replay(&self) {
+        let replayer = if let Mode::Replayer(replayer) = &self.mode {
+            replayer
+        } else {
+            return;
+        };
+        let tick = replayer.current_tick();
+        if tick == 0 {
+            return;
+        }
+        self.set_paused(true);
+        self.seek_replay(tick - 1);
+    }
+
+    /// Puts the replay's speed back once a seek is done. Called from the UI every frame.
+    pub fn finish_replay_seek(&self) {
+        let replayer = if let Mode::Replayer(replayer) = &self.mode {
+            replayer
+        } else {
+            return;
+        };
+        let fps_target = replayer.timeline.lock().take_finished_seek();
+        if let Some(fps_target) = fps_target {
+            self.set_fps_target(fps_target);
+        }
+    }
+
+    /// Cheats are only allowed in single player, for the same reason as save states.
+    pub fn set_cheats(&self, ops: Vec<cheats::Op>) -> anyhow::Result<()> {
+        let single_player = if let Mode::SinglePlayer(single_player) = &self.mode {
+            single_player
+        } else {
+            anyhow::bail!("cheats are only available in single player");
+        };
+        *single_player.cheats.lock() = ops;
+        Ok(())
+    }
+
+    /// Only single player and replay sessions can have a script.
+    pub fn script(&self) -> Option<&scripting::Script> {
+        self.script.as_deref()
+    }
+
     pub fn set_fps_target(&self, fps: f32) {
         let handle = self.thread.handle();
         let audio_guard = handle.lock_audio();